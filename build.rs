@@ -1,6 +1,11 @@
 use std::process::Command;
 
 fn main() {
+    // The pure-rust backend does not link against libtermkey at all
+    if std::env::var_os("CARGO_FEATURE_PURE_RUST").is_some() {
+        return;
+    }
+
     let git_ref = "v0.17";
     assert!(Command::new("./build_termkey.sh")
         .arg(git_ref)
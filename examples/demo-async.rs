@@ -70,7 +70,7 @@ fn main() {
         loop {
             match tk.getkey() {
                 termkey::Result::Key(key) => {
-                    on_key(&mut tk, key);
+                    on_key(&mut tk, key.clone());
                     if let termkey::Event::Unicode {
                         mods,
                         codepoint,
@@ -0,0 +1,46 @@
+extern crate libc;
+extern crate termkey;
+
+use mio::{Events, Interest, Poll, Token};
+
+fn main() {
+    let mut tk = termkey::TermKey::new(0, termkey::c::Flag::CTRLC);
+    unsafe {
+        let flags = libc::fcntl(0, libc::F_GETFL);
+        libc::fcntl(0, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.registry()
+        .register(&mut tk, Token(0), Interest::READABLE)
+        .unwrap();
+
+    let mut drain = termkey::KeyDrain::new();
+    let mut keys = Vec::new();
+    let mut running = true;
+
+    while running {
+        poll.poll(&mut events, drain.timeout()).unwrap();
+        let mut open = drain.check_timeout(&mut tk, &mut keys).unwrap();
+        if !events.is_empty() {
+            open = open && drain.readable(&mut tk, &mut keys).unwrap();
+        }
+        for key in keys.drain(..) {
+            println!("{}", tk.strfkey(key.clone(), termkey::c::Format::VIM));
+            if let termkey::Event::Unicode {
+                mods,
+                codepoint,
+                utf8: _,
+            } = key
+            {
+                if !(mods & termkey::c::KeyMod::CTRL).is_empty()
+                    && (codepoint == 'C' || codepoint == 'c')
+                {
+                    running = false;
+                }
+            }
+        }
+        running = running && open;
+    }
+}
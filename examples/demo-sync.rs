@@ -6,6 +6,7 @@ fn main() {
     let format = termkey::c::Format::VIM;
 
     let mut tk = termkey::TermKey::new(0, termkey::c::Flag::SPACESYMBOL | termkey::c::Flag::CTRLC);
+    tk.set_output_fd(1);
     if !(tk.get_flags() & termkey::c::Flag::UTF8).is_empty() {
         println!("Termkey in UTF-8 mode")
     }
@@ -22,7 +23,7 @@ fn main() {
         match tk.waitkey() {
             termkey::Result::Eof => break,
             termkey::Result::Key(key) => {
-                let s = tk.strfkey(key, format);
+                let s = tk.strfkey(key.clone(), format);
                 println!("Key {}", s);
 
                 match key {
@@ -32,6 +33,7 @@ fn main() {
                         button: _,
                         line,
                         col,
+                        pixels: _,
                     } => {
                         println!(
                             "Mouse (printing unimplemented, sorry) at line={}, col={}\n",
@@ -49,8 +51,19 @@ fn main() {
                         let initial_str = if initial != 0 { "DEC" } else { "ANSI" };
                         println!("Mode report {} mode {} = {}\n", initial_str, mode, value)
                     }
-                    termkey::Event::UnknownCsi => {
-                        println!("Unrecognised CSI (printing unimplemented, sorry)\n")
+                    termkey::Event::UnknownCsi {
+                        args,
+                        initial,
+                        intermediate,
+                        command,
+                    } => {
+                        println!(
+                            "Unrecognised CSI initial={} intermediate={} command={} args={:?}\n",
+                            initial,
+                            intermediate,
+                            command,
+                            args.as_slice()
+                        )
                     }
                     _ => {}
                 }
@@ -66,8 +79,13 @@ fn main() {
                         break;
                     }
                     if mods.is_empty() && codepoint == '?' {
-                        // println!("\x1b[?6n"); // DECDSR 6 == request cursor position
-                        println!("\x1b[?1$p"); // DECRQM == request mode, DEC origin mode
+                        // DECRQM == request mode, DEC origin mode
+                        let query = termkey::query::Query::DecMode(1);
+                        match tk.query(query, std::time::Duration::from_millis(500)) {
+                            Ok(Some(reply)) => println!("Reply {:?}", reply),
+                            Ok(None) => println!("No reply to {:?}", query),
+                            Err(err) => println!("Query failed: {}", err),
+                        }
                     }
                 }
             }
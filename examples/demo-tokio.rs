@@ -0,0 +1,38 @@
+extern crate termkey;
+
+use std::future::poll_fn;
+use std::pin::Pin;
+
+use futures_core::Stream;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let tk = termkey::TermKey::new(0, termkey::c::Flag::CTRLC);
+    let mut stream = termkey::AsyncTermKey::new(tk).unwrap();
+
+    while let Some(res) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        let key = match res {
+            Ok(key) => key,
+            Err(err) => {
+                println!("Error: {}", err);
+                break;
+            }
+        };
+        let s = stream
+            .get_mut()
+            .strfkey(key.clone(), termkey::c::Format::VIM);
+        println!("{}", s);
+        if let termkey::Event::Unicode {
+            mods,
+            codepoint,
+            utf8: _,
+        } = key
+        {
+            if !(mods & termkey::c::KeyMod::CTRL).is_empty()
+                && (codepoint == 'C' || codepoint == 'c')
+            {
+                break;
+            }
+        }
+    }
+}
@@ -15,8 +15,10 @@ pub unsafe fn CHECK_VERSION() {
     termkey_check_version(VERSION_MAJOR, VERSION_MINOR);
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+/// A key symbol. libtermkey numbers them as a C enum, which key names
+/// registered at runtime extend past `N_SYMS`, so these are `Custom`.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug)]
 pub enum Sym {
     UNKNOWN = -1,
     NONE = 0,
@@ -92,12 +94,204 @@ pub enum Sym {
 
     /* et cetera ad nauseum */
     N_SYMS,
+
+    /// A key name registered with `TermKey::register_keyname`. One holding
+    /// a named symbol's number equals that symbol, but only matches the
+    /// named variant's pattern after a round trip through `from_c`.
+    Custom(u32),
+}
+
+/// Converts between the named symbols and libtermkey's numbers for them.
+/// `Custom` holds any other number, and one below `N_SYMS` is taken for
+/// the named symbol it numbers.
+macro_rules! sym_numbers {
+    ($($sym:ident = $num:expr,)*) => {
+        impl Sym {
+            pub fn from_c(sym: c_int) -> Sym {
+                match sym {
+                    $($num => Sym::$sym,)*
+                    sym => Sym::Custom(sym as u32),
+                }
+            }
+            pub fn to_c(self) -> c_int {
+                match self {
+                    $(Sym::$sym => $num,)*
+                    // Only a sentinel, so its number is left to `Custom`
+                    Sym::N_SYMS => 60,
+                    Sym::Custom(sym) => sym as c_int,
+                }
+            }
+        }
+    };
+}
+
+sym_numbers! {
+    UNKNOWN = -1,
+    NONE = 0,
+    BACKSPACE = 1,
+    TAB = 2,
+    ENTER = 3,
+    ESCAPE = 4,
+    SPACE = 5,
+    DEL = 6,
+    UP = 7,
+    DOWN = 8,
+    LEFT = 9,
+    RIGHT = 10,
+    BEGIN = 11,
+    FIND = 12,
+    INSERT = 13,
+    DELETE = 14,
+    SELECT = 15,
+    PAGEUP = 16,
+    PAGEDOWN = 17,
+    HOME = 18,
+    END = 19,
+    CANCEL = 20,
+    CLEAR = 21,
+    CLOSE = 22,
+    COMMAND = 23,
+    COPY = 24,
+    EXIT = 25,
+    HELP = 26,
+    MARK = 27,
+    MESSAGE = 28,
+    MOVE = 29,
+    OPEN = 30,
+    OPTIONS = 31,
+    PRINT = 32,
+    REDO = 33,
+    REFERENCE = 34,
+    REFRESH = 35,
+    REPLACE = 36,
+    RESTART = 37,
+    RESUME = 38,
+    SAVE = 39,
+    SUSPEND = 40,
+    UNDO = 41,
+    KP0 = 42,
+    KP1 = 43,
+    KP2 = 44,
+    KP3 = 45,
+    KP4 = 46,
+    KP5 = 47,
+    KP6 = 48,
+    KP7 = 49,
+    KP8 = 50,
+    KP9 = 51,
+    KPENTER = 52,
+    KPPLUS = 53,
+    KPMINUS = 54,
+    KPMULT = 55,
+    KPDIV = 56,
+    KPCOMMA = 57,
+    KPPERIOD = 58,
+    KPEQUALS = 59,
+}
+
+impl Sym {
+    /// Named symbols come before custom ones, each in numeric order, and a
+    /// `Custom` holding a named symbol's number is keyed as that symbol.
+    fn key(self) -> (bool, i64) {
+        if let Sym::N_SYMS = self {
+            return (false, i64::from(self.to_c()));
+        }
+        match Sym::from_c(self.to_c()) {
+            Sym::Custom(sym) => (true, i64::from(sym)),
+            sym => (false, i64::from(sym.to_c())),
+        }
+    }
+}
+impl PartialEq for Sym {
+    fn eq(&self, other: &Sym) -> bool {
+        self.key() == other.key()
+    }
+}
+impl Eq for Sym {}
+impl PartialOrd for Sym {
+    fn partial_cmp(&self, other: &Sym) -> Option<::std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Sym {
+    fn cmp(&self, other: &Sym) -> ::std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+impl ::std::hash::Hash for Sym {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
 }
 
+/// The names libtermkey gives the symbols.
+#[cfg(any(feature = "pure-rust", feature = "serde"))]
+pub(crate) static KEYNAMES: &[(Sym, &str)] = &[
+    (Sym::NONE, "NONE"),
+    (Sym::BACKSPACE, "Backspace"),
+    (Sym::TAB, "Tab"),
+    (Sym::ENTER, "Enter"),
+    (Sym::ESCAPE, "Escape"),
+    (Sym::SPACE, "Space"),
+    (Sym::DEL, "DEL"),
+    (Sym::UP, "Up"),
+    (Sym::DOWN, "Down"),
+    (Sym::LEFT, "Left"),
+    (Sym::RIGHT, "Right"),
+    (Sym::BEGIN, "Begin"),
+    (Sym::FIND, "Find"),
+    (Sym::INSERT, "Insert"),
+    (Sym::DELETE, "Delete"),
+    (Sym::SELECT, "Select"),
+    (Sym::PAGEUP, "PageUp"),
+    (Sym::PAGEDOWN, "PageDown"),
+    (Sym::HOME, "Home"),
+    (Sym::END, "End"),
+    (Sym::CANCEL, "Cancel"),
+    (Sym::CLEAR, "Clear"),
+    (Sym::CLOSE, "Close"),
+    (Sym::COMMAND, "Command"),
+    (Sym::COPY, "Copy"),
+    (Sym::EXIT, "Exit"),
+    (Sym::HELP, "Help"),
+    (Sym::MARK, "Mark"),
+    (Sym::MESSAGE, "Message"),
+    (Sym::MOVE, "Move"),
+    (Sym::OPEN, "Open"),
+    (Sym::OPTIONS, "Options"),
+    (Sym::PRINT, "Print"),
+    (Sym::REDO, "Redo"),
+    (Sym::REFERENCE, "Reference"),
+    (Sym::REFRESH, "Refresh"),
+    (Sym::REPLACE, "Replace"),
+    (Sym::RESTART, "Restart"),
+    (Sym::RESUME, "Resume"),
+    (Sym::SAVE, "Save"),
+    (Sym::SUSPEND, "Suspend"),
+    (Sym::UNDO, "Undo"),
+    (Sym::KP0, "KP0"),
+    (Sym::KP1, "KP1"),
+    (Sym::KP2, "KP2"),
+    (Sym::KP3, "KP3"),
+    (Sym::KP4, "KP4"),
+    (Sym::KP5, "KP5"),
+    (Sym::KP6, "KP6"),
+    (Sym::KP7, "KP7"),
+    (Sym::KP8, "KP8"),
+    (Sym::KP9, "KP9"),
+    (Sym::KPENTER, "KPEnter"),
+    (Sym::KPPLUS, "KPPlus"),
+    (Sym::KPMINUS, "KPMinus"),
+    (Sym::KPMULT, "KPMult"),
+    (Sym::KPDIV, "KPDiv"),
+    (Sym::KPCOMMA, "KPComma"),
+    (Sym::KPPERIOD, "KPPeriod"),
+    (Sym::KPEQUALS, "KPEquals"),
+];
+
 impl ::std::fmt::Display for Sym {
     fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        let symi: c_long = unsafe { ::std::mem::transmute(self) };
-        let _ = write!(fmt, "{}", symi);
+        let _ = write!(fmt, "{}", self.to_c());
         Ok(())
     }
 }
@@ -126,7 +320,7 @@ pub enum Result {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum MouseEvent {
     UNKNOWN,
     PRESS,
@@ -142,11 +336,26 @@ impl ::std::fmt::Display for MouseEvent {
     }
 }
 
+/// Whether a key was pressed, auto-repeated or released. Only the kitty
+/// keyboard protocol reports anything but `PRESS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyEvent {
+    PRESS,
+    REPEAT,
+    RELEASE,
+}
+
 bitflags! { pub struct KeyMod: ::libc::c_int
 {
-  const SHIFT = 1 << 0;
-  const ALT   = 1 << 1;
-  const CTRL  = 1 << 2;
+  const SHIFT     = 1 << 0;
+  const ALT       = 1 << 1;
+  const CTRL      = 1 << 2;
+  /* The rest are only reported by the kitty keyboard protocol */
+  const SUPER     = 1 << 3;
+  const HYPER     = 1 << 4;
+  const META      = 1 << 5;
+  const CAPS_LOCK = 1 << 6;
+  const NUM_LOCK  = 1 << 7;
 }}
 
 impl ::std::fmt::Display for KeyMod {
@@ -199,8 +408,13 @@ impl Key {
     }
     /// # Safety
     pub unsafe fn sym(&self) -> Sym {
-        let s: &Sym = &*(&self.code as *const i64 as *const Sym);
-        *s
+        let s: &c_int = &*(&self.code as *const i64 as *const c_int);
+        Sym::from_c(*s)
+    }
+    /// # Safety
+    pub unsafe fn mouse_code(&self) -> c_uchar {
+        let s: &[c_char; 4] = &*(&self.code as *const i64 as *const [c_char; 4]);
+        s[0] as c_uchar
     }
 }
 impl Key {
@@ -240,8 +454,8 @@ impl Key {
                 modifiers: mods,
                 utf8: [0; 7],
             };
-            let code: &mut Sym = &mut *(&mut key.code as *mut i64 as *mut Sym);
-            *code = sym;
+            let code: &mut c_int = &mut *(&mut key.code as *mut i64 as *mut c_int);
+            *code = sym.to_c();
             key
         }
     }
@@ -292,9 +506,10 @@ impl Key {
         self.set_linecol(line, col);
         let fields: &mut [c_char; 4] = &mut *(&mut self.code as *mut i64 as *mut [i8; 4]);
         fields[0] = match button {
-            1 | 2 | 3 => button - 1,
-            4 | 5 => button - 4 + 64,
-            _ => 66,
+            1..=3 => button - 1,
+            4..=7 => button - 4 + 64,
+            8..=11 => button - 8 + 128,
+            _ => 3,
         } as c_char;
         if ev == MouseEvent::DRAG {
             fields[0] |= 0x20;
@@ -403,15 +618,15 @@ extern "C" {
 
     pub fn termkey_push_bytes(tk: *mut TermKey, bytes: *const c_char, len: size_t) -> size_t;
 
-    pub fn termkey_register_keyname(tk: *mut TermKey, sym: Sym, name: *const c_char) -> Sym;
-    pub fn termkey_get_keyname(tk: *mut TermKey, sym: Sym) -> *const c_char;
+    pub fn termkey_register_keyname(tk: *mut TermKey, sym: c_int, name: *const c_char) -> c_int;
+    pub fn termkey_get_keyname(tk: *mut TermKey, sym: c_int) -> *const c_char;
     pub fn termkey_lookup_keyname(
         tk: *mut TermKey,
         str: *const c_char,
-        sym: *mut Sym,
+        sym: *mut c_int,
     ) -> *const c_char;
 
-    pub fn termkey_keyname2sym(tk: *mut TermKey, keyname: *const c_char) -> Sym;
+    pub fn termkey_keyname2sym(tk: *mut TermKey, keyname: *const c_char) -> c_int;
 
     pub fn termkey_interpret_mouse(
         tk: *mut TermKey,
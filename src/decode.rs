@@ -0,0 +1,492 @@
+//! Decoding shared by both backends, for the sequences libtermkey 0.17
+//! either does not know or cannot report in full.
+
+use std::convert::TryFrom;
+
+use crate::c::{KeyEvent, KeyMod, Sym};
+use crate::{Event, KeyCode};
+
+pub(crate) const NARGS: usize = 16;
+
+pub(crate) struct Csi {
+    pub(crate) args: [isize; NARGS],
+    pub(crate) nargs: usize,
+    /// The final byte, with the initial byte (if any) in bits 8-15 and the
+    /// intermediate byte (if any) in bits 16-23.
+    pub(crate) cmd: u32,
+    /// Length of the whole sequence, including the introducer.
+    pub(crate) len: usize,
+    /// The colon-separated sub-parameters following each argument, with
+    /// missing ones as -1.
+    pub(crate) sub: [Vec<isize>; NARGS],
+}
+
+impl Csi {
+    /// The modifiers encoded in the second argument, if present.
+    pub(crate) fn mods(&self) -> KeyMod {
+        if self.nargs > 1 && self.args[1] != -1 {
+            KeyMod::from_bits_truncate((self.args[1] - 1) as libc::c_int)
+        } else {
+            KeyMod::empty()
+        }
+    }
+    /// The event type the kitty keyboard protocol appends to the modifiers.
+    pub(crate) fn key_event(&self) -> KeyEvent {
+        match self.sub[1].first() {
+            Some(2) => KeyEvent::REPEAT,
+            Some(3) => KeyEvent::RELEASE,
+            _ => KeyEvent::PRESS,
+        }
+    }
+}
+
+/// Returns `None` if the final byte has not arrived yet.
+pub(crate) fn parse_csi(buf: &[u8], introlen: usize) -> Option<Csi> {
+    let csi_end = introlen
+        + buf[introlen..]
+            .iter()
+            .position(|&b| (0x40..0x80).contains(&b))?;
+
+    let mut csi = Csi {
+        args: [0; NARGS],
+        nargs: 0,
+        cmd: u32::from(buf[csi_end]),
+        len: csi_end + 1,
+        sub: Default::default(),
+    };
+
+    let mut p = introlen;
+    // See if there is an initial byte
+    if (b'<'..=b'?').contains(&buf[p]) {
+        csi.cmd |= u32::from(buf[p]) << 8;
+        p += 1;
+    }
+
+    let mut present = false;
+    let mut in_sub = false;
+    let mut argi = 0;
+    while p < csi_end {
+        let c = buf[p];
+        if c.is_ascii_digit() {
+            let digit = isize::from(c - b'0');
+            let value = match csi.sub[argi].last_mut() {
+                Some(value) if in_sub => value,
+                _ => &mut csi.args[argi],
+            };
+            *value = if present {
+                value.saturating_mul(10).saturating_add(digit)
+            } else {
+                digit
+            };
+            present = true;
+        } else if c == b':' {
+            if !in_sub && !present {
+                csi.args[argi] = -1;
+            }
+            csi.sub[argi].push(-1);
+            present = false;
+            in_sub = true;
+        } else if c == b';' {
+            if !in_sub && !present {
+                csi.args[argi] = -1;
+            }
+            present = false;
+            in_sub = false;
+            argi += 1;
+            if argi == NARGS {
+                break;
+            }
+        } else if (0x20..=0x2f).contains(&c) {
+            csi.cmd |= u32::from(c) << 16;
+            break;
+        }
+        p += 1;
+    }
+    if present || in_sub {
+        argi += 1;
+    }
+    csi.nargs = argi;
+
+    Some(csi)
+}
+
+/// What a CSI or SS3 final byte maps to, before modifiers are applied.
+#[derive(Clone, Copy)]
+pub(crate) enum KeyInfo {
+    Sym(Sym),
+    Function(isize),
+}
+
+impl KeyInfo {
+    pub(crate) fn event(self, mods: KeyMod) -> Event {
+        match self {
+            KeyInfo::Sym(sym) => Event::KeySym { sym, mods },
+            KeyInfo::Function(num) => Event::Function { num, mods },
+        }
+    }
+}
+
+pub(crate) enum Functional {
+    Key(KeyInfo),
+    /// The SS3 final byte of the same keypad key, so `CONVERTKP` applies
+    Keypad(u8),
+}
+
+/// The kitty keyboard protocol's codes for keys without a Unicode
+/// codepoint, which it puts in the Private Use Area.
+pub(crate) fn kitty_functional(code: isize) -> Option<Functional> {
+    Some(match code {
+        57376..=57398 => Functional::Key(KeyInfo::Function(code - 57376 + 13)),
+        57399..=57408 => Functional::Keypad(b'p' + (code - 57399) as u8),
+        57409 => Functional::Keypad(b'n'),
+        57410 => Functional::Keypad(b'o'),
+        57411 => Functional::Keypad(b'j'),
+        57412 => Functional::Keypad(b'm'),
+        57413 => Functional::Keypad(b'k'),
+        57414 => Functional::Keypad(b'M'),
+        57415 => Functional::Keypad(b'X'),
+        57416 => Functional::Keypad(b'l'),
+        57417 => Functional::Key(KeyInfo::Sym(Sym::LEFT)),
+        57418 => Functional::Key(KeyInfo::Sym(Sym::RIGHT)),
+        57419 => Functional::Key(KeyInfo::Sym(Sym::UP)),
+        57420 => Functional::Key(KeyInfo::Sym(Sym::DOWN)),
+        57421 => Functional::Key(KeyInfo::Sym(Sym::PAGEUP)),
+        57422 => Functional::Key(KeyInfo::Sym(Sym::PAGEDOWN)),
+        57423 => Functional::Key(KeyInfo::Sym(Sym::HOME)),
+        57424 => Functional::Key(KeyInfo::Sym(Sym::END)),
+        57425 => Functional::Key(KeyInfo::Sym(Sym::INSERT)),
+        57426 => Functional::Key(KeyInfo::Sym(Sym::DELETE)),
+        57427 => Functional::Key(KeyInfo::Sym(Sym::BEGIN)),
+        _ => return None,
+    })
+}
+
+/// Adds `extra` to the modifiers of any event that has them.
+pub(crate) fn add_mods(key: Event, extra: KeyMod) -> Event {
+    match key {
+        Event::Unicode {
+            codepoint,
+            mods,
+            utf8,
+        } => Event::Unicode {
+            codepoint,
+            mods: mods | extra,
+            utf8,
+        },
+        Event::Function { num, mods } => Event::Function {
+            num,
+            mods: mods | extra,
+        },
+        Event::KeySym { sym, mods } => Event::KeySym {
+            sym,
+            mods: mods | extra,
+        },
+        Event::Mouse {
+            ev,
+            mods,
+            button,
+            line,
+            col,
+            pixels,
+        } => Event::Mouse {
+            ev,
+            mods: mods | extra,
+            button,
+            line,
+            col,
+            pixels,
+        },
+        Event::KittyKey {
+            code,
+            mods,
+            event,
+            shifted,
+            base,
+            text,
+        } => Event::KittyKey {
+            code,
+            mods: mods | extra,
+            event,
+            shifted,
+            base,
+            text,
+        },
+        key => key,
+    }
+}
+
+/// Upgrades a plain key to `Event::KittyKey` if the report carried anything
+/// the plain variants cannot hold.
+pub(crate) fn kitty_key(
+    key: Event,
+    event: KeyEvent,
+    shifted: Option<char>,
+    base: Option<char>,
+    text: String,
+) -> Event {
+    if event == KeyEvent::PRESS && shifted.is_none() && base.is_none() && text.is_empty() {
+        return key;
+    }
+    let (code, mods) = match key {
+        Event::Unicode {
+            codepoint, mods, ..
+        } => (KeyCode::Unicode(codepoint), mods),
+        Event::Function { num, mods } => (KeyCode::Function(num), mods),
+        Event::KeySym { sym, mods } => (KeyCode::KeySym(sym), mods),
+        key => return key,
+    };
+    Event::KittyKey {
+        code,
+        mods,
+        event,
+        shifted,
+        base,
+        text,
+    }
+}
+
+fn to_char(codepoint: isize) -> Option<char> {
+    u32::try_from(codepoint).ok().and_then(std::char::from_u32)
+}
+
+/// Adds the rest of a `CSI u` report to the key its code stands for: the
+/// modifiers, and the kitty keyboard protocol's event type, alternate keys
+/// and associated text.
+pub(crate) fn csi_u_event(csi: &Csi, key: Event) -> Event {
+    let key = add_mods(key, csi.mods());
+
+    let shifted = csi.sub[0].first().and_then(|&cp| to_char(cp));
+    let base = csi.sub[0].get(1).and_then(|&cp| to_char(cp));
+    let text = if csi.nargs > 2 {
+        std::iter::once(&csi.args[2])
+            .chain(&csi.sub[2])
+            .filter_map(|&cp| to_char(cp))
+            .collect()
+    } else {
+        String::new()
+    };
+    kitty_key(key, csi.key_event(), shifted, base, text)
+}
+
+/// Handler for CSI M / CSI m mouse events in SGR and rxvt encodings.
+/// SGR-Pixels (DECSET 1016) reuses the SGR encoding, so `mouse_pixels`
+/// applies to that alone.
+pub(crate) fn csi_mouse(csi: &Csi, mouse_pixels: bool) -> Option<Event> {
+    if csi.nargs < 3 {
+        return None;
+    }
+    let release = csi.cmd == 0x3c6d;
+    let pixels = csi.cmd >> 8 == 0x3c && mouse_pixels;
+    Some(mouse_event(
+        csi.args[0],
+        release,
+        csi.args[2],
+        csi.args[1],
+        pixels,
+    ))
+}
+
+pub(crate) fn mouse_event(
+    code: isize,
+    release: bool,
+    line: isize,
+    col: isize,
+    pixels: bool,
+) -> Event {
+    let mods = KeyMod::from_bits_truncate(((code & 0x1c) >> 2) as libc::c_int);
+    let (ev, button) = crate::decode_mouse_button(code, release);
+
+    Event::Mouse {
+        ev,
+        mods,
+        button,
+        line,
+        col,
+        pixels,
+    }
+}
+
+pub(crate) const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Moves bytes off the front of `buf` into `paste` until the end marker
+/// turns up, and returns the next piece of the paste to report, with
+/// whether more of it follows: all of it once it has ended, or the first
+/// `max` bytes of one longer than that. `None` until either happens.
+pub(crate) fn fill_paste(
+    paste: &mut Vec<u8>,
+    buf: &mut Vec<u8>,
+    max: usize,
+    utf8: bool,
+    is_closed: bool,
+) -> Option<(String, bool)> {
+    let end = buf
+        .windows(PASTE_END.len())
+        .position(|window| window == PASTE_END);
+    let take = match end {
+        Some(end) => end,
+        // Hold back anything that could be the start of the end marker
+        None if !is_closed => {
+            let partial = (1..PASTE_END.len())
+                .rev()
+                .find(|&len| buf.ends_with(&PASTE_END[..len]))
+                .unwrap_or(0);
+            buf.len() - partial
+        }
+        None => buf.len(),
+    };
+    paste.extend(buf.drain(..take));
+
+    if paste.len() > max {
+        // Split at a character boundary, so each piece decodes cleanly
+        let mut split = max;
+        if utf8 {
+            while split > 0 && paste[split] & 0xc0 == 0x80 {
+                split -= 1;
+            }
+            if split == 0 {
+                split = max;
+            }
+        }
+        let rest = paste.split_off(split);
+        let piece = std::mem::replace(paste, rest);
+        return Some((text(piece, utf8), true));
+    }
+
+    if end.is_some() {
+        buf.drain(..PASTE_END.len());
+    } else if !is_closed {
+        return None;
+    }
+    Some((text(std::mem::take(paste), utf8), false))
+}
+
+/// A DCS, OSC or APC string being received.
+pub(crate) struct CtrlString {
+    /// The second byte of the 7-bit introducer: `P`, `]` or `_`
+    pub(crate) kind: u8,
+    /// Whether it was introduced by a C1 byte, and so may end with one
+    c1: bool,
+    pub(crate) bytes: Vec<u8>,
+}
+
+/// What the bytes at the front of the buffer are, as far as strings go.
+pub(crate) enum StringStart {
+    /// A string, whose introducer is this many bytes long
+    String(CtrlString, usize),
+    /// An introducer whose header has not been seen in full
+    Partial,
+    /// Keys, perhaps Alt with `P`, `]` or `_`
+    NotString,
+}
+
+/// Headers longer than this are not waited for.
+const HEADER_MAX: usize = 32;
+
+/// Recognises the introducer of a DCS, OSC or APC string, but only with
+/// the start of a header a terminal would send after it: a number for OSC,
+/// parameters and a final byte for DCS, and a `key=` for APC as kitty's
+/// graphics replies have. Anything else after it was typed.
+pub(crate) fn string_start(buf: &[u8]) -> StringStart {
+    let (kind, c1, introlen) = match *buf {
+        [0x1b, kind @ (b'P' | b']' | b'_'), ..] => (kind, false, 2),
+        [c1 @ (0x90 | 0x9d | 0x9f), ..] => (c1 - 0x40, true, 1),
+        _ => return StringStart::NotString,
+    };
+    let header = &buf[introlen..buf.len().min(introlen + HEADER_MAX)];
+    let valid = match kind {
+        b']' => osc_header(header, c1),
+        b'P' => dcs_header(header),
+        _ => apc_header(header),
+    };
+    match valid {
+        Some(true) => StringStart::String(
+            CtrlString {
+                kind,
+                c1,
+                bytes: Vec::new(),
+            },
+            introlen,
+        ),
+        None if header.len() < HEADER_MAX => StringStart::Partial,
+        _ => StringStart::NotString,
+    }
+}
+
+/// `Some(false)` if `header` cannot start a valid one, `None` if it might
+/// once more bytes arrive.
+fn osc_header(header: &[u8], c1: bool) -> Option<bool> {
+    let digits = header.iter().take_while(|b| b.is_ascii_digit()).count();
+    match header.get(digits)? {
+        b';' | 0x07 | 0x1b => Some(digits > 0),
+        0x9c if c1 => Some(digits > 0),
+        _ => Some(false),
+    }
+}
+
+fn dcs_header(header: &[u8]) -> Option<bool> {
+    let mut p = 0;
+    if let Some(b'<'..=b'?') = header.first() {
+        p += 1;
+    }
+    while let Some(b'0'..=b'9' | b';' | 0x20..=0x2f) = header.get(p) {
+        p += 1;
+    }
+    match header.get(p)? {
+        0x40..=0x7e => Some(p > 0),
+        _ => Some(false),
+    }
+}
+
+fn apc_header(header: &[u8]) -> Option<bool> {
+    if !header.first()?.is_ascii_alphabetic() {
+        return Some(false);
+    }
+    let name = header
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric())
+        .count();
+    Some(*header.get(name)? == b'=')
+}
+
+impl CtrlString {
+    /// Moves bytes off the front of `buf` into the string until its
+    /// terminator turns up, dropping any beyond `max`. ST ends any string,
+    /// and BEL an OSC as xterm allows. Returns whether the string is
+    /// complete, which it is at the end of the input too.
+    pub(crate) fn fill(&mut self, buf: &mut Vec<u8>, max: usize, is_closed: bool) -> bool {
+        let end = buf.iter().enumerate().find_map(|(i, &b)| match b {
+            0x1b if buf.get(i + 1) == Some(&b'\\') => Some((i, 2)),
+            0x07 if self.kind == b']' => Some((i, 1)),
+            0x9c if self.c1 => Some((i, 1)),
+            _ => None,
+        });
+        let take = match end {
+            Some((end, _)) => end,
+            // Hold back an Escape that could start the terminator
+            None if !is_closed && buf.last() == Some(&0x1b) => buf.len() - 1,
+            None => buf.len(),
+        };
+        let room = max.saturating_sub(self.bytes.len());
+        self.bytes.extend(buf.drain(..take).take(room));
+
+        match end {
+            Some((_, termlen)) => {
+                buf.drain(..termlen);
+                true
+            }
+            None => is_closed,
+        }
+    }
+}
+
+/// The text of a paste or string, decoded as UTF-8 if `utf8` is set and
+/// as Latin-1 otherwise.
+pub(crate) fn text(bytes: Vec<u8>, utf8: bool) -> String {
+    if utf8 {
+        String::from_utf8(bytes)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+    } else {
+        bytes.into_iter().map(char::from).collect()
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeSet;
+
+use crate::c::{Canon, Flag, KeyMod};
+use crate::{Error, Event, Result, TermKey};
+
+use super::terminfo;
+
+/// A problem `analyse` found with a list of bindings, which it refers to
+/// by their index in the list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Two bindings are for the same keys, once canonicalised.
+    Duplicate { first: usize, second: usize },
+    /// A binding is for the first keys of a longer one, so a
+    /// `KeySequenceMatcher` can only tell them apart by its timeout.
+    Shadowed { prefix: usize, longer: usize },
+    /// The keys of a binding canonicalise to other keys, as `Canon::DELBS`
+    /// turns DEL into Backspace, so keys that are read never equal them.
+    Uncanonical {
+        binding: usize,
+        canonical: Vec<Event>,
+    },
+    /// A key of a binding that the terminal cannot send.
+    Unproducible { binding: usize, key: Event },
+}
+
+/// Looks for bindings that cannot all be told apart, or reached at all, on
+/// the terminal `term` with the given flags and canonicalisation flags.
+///
+/// Which keys the terminal can send is found out from the keys its
+/// terminfo entry has strings for, and by decoding what terminals send
+/// without the kitty keyboard protocol or xterm's modifyOtherKeys: every
+/// ASCII character with and without an Escape before it, and the CSI and
+/// SS3 sequences for special keys with and without Shift, Alt and Ctrl.
+/// Only `Unicode`, `Function` and `KeySym` keys are checked.
+///
+/// The terminfo entry is read as libtermkey reads it, even with the
+/// `pure-rust` feature, whose backend decodes the generic sequences alone.
+pub fn analyse(
+    term: &str,
+    flags: Flag,
+    canonflags: Canon,
+    bindings: &[Vec<Event>],
+) -> ::std::result::Result<Vec<Conflict>, Error> {
+    let mut tk = TermKey::try_new_abstract(term, flags)?;
+    tk.set_canonflags(canonflags);
+    let mut producible = producible_keys(&mut tk);
+    producible.extend(
+        terminfo::keys(term)
+            .into_iter()
+            .map(|key| tk.canonicalise(key)),
+    );
+
+    let canonical: Vec<Vec<Event>> = bindings
+        .iter()
+        .map(|keys| {
+            keys.iter()
+                .map(|key| tk.canonicalise(key.clone()))
+                .collect()
+        })
+        .collect();
+
+    let mut conflicts = Vec::new();
+    for (binding, keys) in canonical.iter().enumerate() {
+        if *keys != bindings[binding] {
+            conflicts.push(Conflict::Uncanonical {
+                binding,
+                canonical: keys.clone(),
+            });
+        }
+        for key in keys {
+            if !can_produce(&producible, key) {
+                conflicts.push(Conflict::Unproducible {
+                    binding,
+                    key: key.clone(),
+                });
+            }
+        }
+        for (first, earlier) in canonical[..binding].iter().enumerate() {
+            if earlier == keys {
+                conflicts.push(Conflict::Duplicate {
+                    first,
+                    second: binding,
+                });
+            } else if keys.starts_with(earlier) {
+                conflicts.push(Conflict::Shadowed {
+                    prefix: first,
+                    longer: binding,
+                });
+            } else if earlier.starts_with(keys) {
+                conflicts.push(Conflict::Shadowed {
+                    prefix: binding,
+                    longer: first,
+                });
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+fn can_produce(producible: &BTreeSet<Event>, key: &Event) -> bool {
+    match *key {
+        // typed as UTF-8, which Escape can precede like any other key
+        Event::Unicode {
+            codepoint, mods, ..
+        } if !codepoint.is_ascii() => (mods - KeyMod::ALT).is_empty(),
+        Event::Unicode { .. } | Event::Function { .. } | Event::KeySym { .. } => {
+            producible.contains(key)
+        }
+        _ => true,
+    }
+}
+
+/// Everything `tk` decodes from the sequences legacy terminals send.
+fn producible_keys(tk: &mut TermKey) -> BTreeSet<Event> {
+    let mut inputs: Vec<Vec<u8>> = Vec::new();
+    for byte in 0..0x80 {
+        inputs.push(vec![byte]);
+        inputs.push(vec![0x1b, byte]);
+    }
+    // xterm's modifier parameters for Shift, Alt and Ctrl are 2 to 8
+    for num in 1..=34 {
+        inputs.push(format!("\x1b[{}~", num).into_bytes());
+        for param in 2..=8 {
+            inputs.push(format!("\x1b[{};{}~", num, param).into_bytes());
+        }
+    }
+    for letter in (b'A'..=b'Z').chain(b'a'..=b'z') {
+        let letter = letter as char;
+        inputs.push(format!("\x1b[{}", letter).into_bytes());
+        inputs.push(format!("\x1bO{}", letter).into_bytes());
+        for param in 2..=8 {
+            inputs.push(format!("\x1b[1;{}{}", param, letter).into_bytes());
+        }
+    }
+
+    let mut keys = BTreeSet::new();
+    for input in inputs {
+        tk.push_bytes(&input);
+        loop {
+            match tk.getkey() {
+                Result::Key(key) => {
+                    keys.insert(key);
+                }
+                Result::Again => match tk.getkey_force() {
+                    Result::Key(key) => {
+                        keys.insert(key);
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+    }
+    keys
+}
@@ -0,0 +1,182 @@
+//! Keymaps read from TOML, for the `toml` feature.
+
+use std::collections::BTreeMap;
+
+use toml::Spanned;
+
+use super::{parse_keys, Keymap};
+use crate::c::Format;
+use crate::TermKey;
+
+/// Why `from_toml` could not load a keymap. Lines and columns count from 1,
+/// and columns in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The text is not TOML, or an action is not a string.
+    Toml {
+        line: usize,
+        col: usize,
+        message: String,
+    },
+    /// Keys that `parse_keys` could not read; the position is that of the
+    /// first key it could not read.
+    BadKeys {
+        line: usize,
+        col: usize,
+        keys: String,
+    },
+    /// Keys bound earlier in the file, possibly written another way.
+    Duplicate {
+        line: usize,
+        col: usize,
+        keys: String,
+        first_line: usize,
+        first_col: usize,
+    },
+}
+
+impl ConfigError {
+    /// The line and column the error was found at.
+    pub fn position(&self) -> (usize, usize) {
+        match *self {
+            ConfigError::Toml { line, col, .. }
+            | ConfigError::BadKeys { line, col, .. }
+            | ConfigError::Duplicate { line, col, .. } => (line, col),
+        }
+    }
+}
+
+impl ::std::fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            ConfigError::Toml {
+                line,
+                col,
+                ref message,
+            } => write!(fmt, "{}:{}: {}", line, col, message),
+            ConfigError::BadKeys {
+                line,
+                col,
+                ref keys,
+            } => {
+                write!(fmt, "{}:{}: cannot read keys `{}`", line, col, keys)
+            }
+            ConfigError::Duplicate {
+                line,
+                col,
+                ref keys,
+                first_line,
+                first_col,
+            } => write!(
+                fmt,
+                "{}:{}: `{}` is already bound at {}:{}",
+                line, col, keys, first_line, first_col
+            ),
+        }
+    }
+}
+
+impl ::std::error::Error for ConfigError {}
+
+/// Reads a keymap from a TOML table of bindings, each written as for
+/// `parse_keys` in `format` and bound to the name of an action:
+///
+/// ```toml
+/// "<C-x><C-s>" = "save"
+/// "<C-x><C-c>" = "quit"
+/// "<PageDown>" = "scroll-down"
+/// ```
+///
+/// Binding the same keys twice is an error, even when they are written
+/// differently, like `"C-x"` and `"<C-x>"`.
+pub fn from_toml(
+    tk: &mut TermKey,
+    text: &str,
+    format: Format,
+) -> Result<Keymap<String>, ConfigError> {
+    let table: BTreeMap<Spanned<String>, Spanned<String>> =
+        toml::from_str(text).map_err(|err| {
+            let (line, col) = line_col(text, err.span().map_or(0, |span| span.start));
+            ConfigError::Toml {
+                line,
+                col,
+                message: err.message().to_string(),
+            }
+        })?;
+
+    // report problems in the order they appear in the file
+    let mut entries: Vec<_> = table.into_iter().collect();
+    entries.sort_by_key(|(keys, _)| keys.span().start);
+
+    let mut keymap = Keymap::new();
+    let mut starts = BTreeMap::new();
+    for (keys, action) in entries {
+        let start = keys.span().start;
+        let parsed = parse_keys(tk, keys.get_ref(), format).map_err(|err| {
+            let (line, col) = line_col(text, start + raw_offset(&text[start..], err.offset));
+            ConfigError::BadKeys {
+                line,
+                col,
+                keys: keys.get_ref().clone(),
+            }
+        })?;
+        if let Some(&first) = starts.get(&parsed) {
+            let (line, col) = line_col(text, start);
+            let (first_line, first_col) = line_col(text, first);
+            return Err(ConfigError::Duplicate {
+                line,
+                col,
+                keys: keys.into_inner(),
+                first_line,
+                first_col,
+            });
+        }
+        keymap.insert(&parsed, action.into_inner());
+        starts.insert(parsed, start);
+    }
+    Ok(keymap)
+}
+
+/// Maps a byte offset into the value of the key written at the start of
+/// `raw` to one into `raw`, past its opening quote and any escapes before.
+fn raw_offset(raw: &str, offset: usize) -> usize {
+    let mut chars = raw.char_indices();
+    let escapes = match chars.next() {
+        Some((_, '"')) => true,
+        Some((_, '\'')) => false,
+        _ => return offset,
+    };
+    // the length of the character an escape such as \u00e9 stands for
+    fn hex(chars: &mut std::str::CharIndices, digits: usize) -> usize {
+        let hex: String = chars.take(digits).map(|(_, ch)| ch).collect();
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .map_or(1, char::len_utf8)
+    }
+    let mut value = 0;
+    while let Some((at, ch)) = chars.next() {
+        if value >= offset {
+            return at;
+        }
+        value += match ch {
+            '\\' if escapes => match chars.next() {
+                Some((_, 'u')) => hex(&mut chars, 4),
+                Some((_, 'U')) => hex(&mut chars, 8),
+                Some((_, 'x')) => hex(&mut chars, 2),
+                _ => 1,
+            },
+            ch => ch.len_utf8(),
+        };
+    }
+    raw.len()
+}
+
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
@@ -0,0 +1,610 @@
+//! Bindings for sequences of keys, like Emacs' `C-x C-s`.
+//!
+//! A `Keymap` maps sequences of keys to actions, and a `KeySequenceMatcher`
+//! is fed keys as they are read and reports when they spell out a binding.
+//! A `ModeStack` switches between named modes with a keymap each, in the
+//! manner of vim's normal and insert modes. With the `toml` feature,
+//! `from_toml` reads a keymap from a configuration file, and `analyse`
+//! finds bindings that get in each other's way.
+
+mod analysis;
+#[cfg(feature = "toml")]
+mod config;
+mod terminfo;
+#[cfg(feature = "toml")]
+pub use self::config::{from_toml, ConfigError};
+
+pub use self::analysis::{analyse, Conflict};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::c::Format;
+use crate::{Event, TermKey};
+
+/// Where `parse_keys` found no key it could read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset into the parsed string.
+    pub offset: usize,
+}
+
+impl ::std::fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(fmt, "expected a key at byte {}", self.offset)
+    }
+}
+
+impl ::std::error::Error for ParseError {}
+
+/// Parses keys in `strpkey` notation separated by whitespace, like
+/// `"C-x C-s"`. Keys may also be wrapped in angle brackets, as in
+/// `"<C-x><C-s>"`, which need no whitespace between them; write `<` itself
+/// as `"<<>"`. The keys are canonicalised as `tk` would canonicalise keys it
+/// reads, so they compare equal to those.
+pub fn parse_keys(tk: &mut TermKey, s: &str, format: Format) -> Result<Vec<Event>, ParseError> {
+    let mut keys = Vec::new();
+    let mut rest = s.trim_start();
+    loop {
+        let err = ParseError {
+            offset: s.len() - rest.len(),
+        };
+        if rest.is_empty() {
+            return if keys.is_empty() { Err(err) } else { Ok(keys) };
+        }
+        let (key, after) = match rest.strip_prefix('<') {
+            Some(inner) => {
+                let (key, after) = tk.strpkey(inner, format).ok_or_else(|| err.clone())?;
+                (key, after.strip_prefix('>').ok_or(err)?)
+            }
+            None => {
+                let (key, after) = tk.strpkey(rest, format).ok_or_else(|| err.clone())?;
+                if !(after.is_empty() || after.starts_with(char::is_whitespace)) {
+                    return Err(err);
+                }
+                (key, after)
+            }
+        };
+        keys.push(tk.canonicalise(key));
+        rest = after.trim_start();
+    }
+}
+
+/// A trie of key sequences, each bound to an action. A sequence may be
+/// both bound itself and the beginning of longer bindings, in which case
+/// `KeySequenceMatcher` waits for its timeout to tell them apart.
+#[derive(Clone, Debug)]
+pub struct Keymap<A> {
+    root: Node<A>,
+}
+
+#[derive(Clone, Debug)]
+struct Node<A> {
+    action: Option<A>,
+    next: BTreeMap<Event, Node<A>>,
+}
+
+impl<A> Node<A> {
+    fn new() -> Node<A> {
+        Node {
+            action: None,
+            next: BTreeMap::new(),
+        }
+    }
+
+    fn remove(&mut self, keys: &[Event]) -> Option<A> {
+        let child = self.next.get_mut(&keys[0])?;
+        let action = if keys.len() == 1 {
+            child.action.take()
+        } else {
+            child.remove(&keys[1..])
+        };
+        if child.action.is_none() && child.next.is_empty() {
+            self.next.remove(&keys[0]);
+        }
+        action
+    }
+
+    fn collect<'a>(&'a self, keys: &mut Vec<Event>, out: &mut Vec<(Vec<Event>, &'a A)>) {
+        if let Some(ref action) = self.action {
+            out.push((keys.clone(), action));
+        }
+        for (key, child) in &self.next {
+            keys.push(key.clone());
+            child.collect(keys, out);
+            keys.pop();
+        }
+    }
+}
+
+impl<A> Default for Keymap<A> {
+    fn default() -> Keymap<A> {
+        Keymap::new()
+    }
+}
+
+impl<A> Keymap<A> {
+    pub fn new() -> Keymap<A> {
+        Keymap { root: Node::new() }
+    }
+
+    /// Binds `keys` to `action`, returning the action they were bound to
+    /// before. Panics if `keys` is empty.
+    pub fn insert(&mut self, keys: &[Event], action: A) -> Option<A> {
+        assert!(!keys.is_empty(), "cannot bind an empty key sequence");
+        let mut node = &mut self.root;
+        for key in keys {
+            node = node.next.entry(key.clone()).or_insert_with(Node::new);
+        }
+        node.action.replace(action)
+    }
+    /// Binds keys written as for `parse_keys`.
+    pub fn bind(
+        &mut self,
+        tk: &mut TermKey,
+        keys: &str,
+        format: Format,
+        action: A,
+    ) -> Result<Option<A>, ParseError> {
+        let keys = parse_keys(tk, keys, format)?;
+        Ok(self.insert(&keys, action))
+    }
+    /// Unbinds `keys`, returning the action they were bound to.
+    pub fn remove(&mut self, keys: &[Event]) -> Option<A> {
+        if keys.is_empty() {
+            return None;
+        }
+        self.root.remove(keys)
+    }
+
+    /// The action bound to exactly `keys`.
+    pub fn get(&self, keys: &[Event]) -> Option<&A> {
+        self.node(keys)?.action.as_ref()
+    }
+    /// Whether some binding is longer than `keys` and begins with them.
+    pub fn is_prefix(&self, keys: &[Event]) -> bool {
+        self.node(keys).is_some_and(|node| !node.next.is_empty())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.root.next.is_empty()
+    }
+    /// Every binding, ordered by its keys as `Event` orders them.
+    pub fn bindings(&self) -> Vec<(Vec<Event>, &A)> {
+        let mut out = Vec::new();
+        self.root.collect(&mut Vec::new(), &mut out);
+        out
+    }
+    /// The bindings that begin with `keys`, without the `keys` themselves.
+    pub fn bindings_after(&self, keys: &[Event]) -> Vec<(Vec<Event>, &A)> {
+        let mut out = Vec::new();
+        if let Some(node) = self.node(keys) {
+            for (key, child) in &node.next {
+                child.collect(&mut vec![key.clone()], &mut out);
+            }
+        }
+        out
+    }
+
+    fn node(&self, keys: &[Event]) -> Option<&Node<A>> {
+        let mut node = &self.root;
+        for key in keys {
+            node = node.next.get(key)?;
+        }
+        Some(node)
+    }
+}
+
+/// Where a `KeySequenceMatcher` looks up the keys it is fed.
+pub trait Lookup<A> {
+    /// The action bound to exactly `keys`.
+    fn get(&self, keys: &[Event]) -> Option<&A>;
+    /// Whether some binding is longer than `keys` and begins with them.
+    fn is_prefix(&self, keys: &[Event]) -> bool;
+}
+
+impl<A> Lookup<A> for Keymap<A> {
+    fn get(&self, keys: &[Event]) -> Option<&A> {
+        Keymap::get(self, keys)
+    }
+    fn is_prefix(&self, keys: &[Event]) -> bool {
+        Keymap::is_prefix(self, keys)
+    }
+}
+
+/// What the keys fed to a `KeySequenceMatcher` amount to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Match<A> {
+    /// The keys spell out a binding.
+    Matched(A),
+    /// The keys begin a longer binding; keep feeding keys.
+    Pending,
+    /// The keys begin no binding, and are given back to be handled as
+    /// single keys.
+    NoMatch(Vec<Event>),
+}
+
+/// Matches the keys it is fed against a `Keymap`, or any other `Lookup`.
+///
+/// A key that does not continue the pending keys ends them. The longest
+/// bound sequence among them is then `Matched`, or all of them are given
+/// back as `NoMatch`, and the keys after that are matched again. As one
+/// key can thus finish more than one match, call `resume` until it returns
+/// `None` after every `feed` and `check_timeout`.
+///
+/// Keys that begin a longer binding are `Pending` until the next key, or
+/// until the timeout set with `set_timeout` runs out. While `TermKey` waits
+/// `get_waittime` on a partial key of its own, pass that deadline, or a
+/// `KeyDrain`'s, to `timeout_with` so that a single `Poll::poll` covers
+/// both, and call `check_timeout` when it returns.
+#[derive(Debug)]
+pub struct KeySequenceMatcher {
+    pending: Vec<Event>,
+    queued: VecDeque<Event>,
+    timeout: isize,
+    deadline: Option<Instant>,
+}
+
+impl Default for KeySequenceMatcher {
+    fn default() -> KeySequenceMatcher {
+        KeySequenceMatcher::new()
+    }
+}
+
+impl KeySequenceMatcher {
+    /// A matcher without a timeout, which waits for pending keys to be
+    /// ended by another key.
+    pub fn new() -> KeySequenceMatcher {
+        KeySequenceMatcher {
+            pending: Vec::new(),
+            queued: VecDeque::new(),
+            timeout: -1,
+            deadline: None,
+        }
+    }
+
+    /// The time in milliseconds pending keys are waited on, or a negative
+    /// number to wait for the next key however long it takes.
+    pub fn get_timeout(&self) -> isize {
+        self.timeout
+    }
+    pub fn set_timeout(&mut self, msec: isize) {
+        self.timeout = msec;
+    }
+
+    /// The keys fed so far that begin a longer binding.
+    pub fn pending(&self) -> &[Event] {
+        &self.pending
+    }
+    /// The instant at which `check_timeout` will end the pending keys.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+    /// The time left until the deadline, suitable for `Poll::poll`.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_with(None)
+    }
+    /// The time left until the nearer of the deadline and `other`, such as
+    /// the instant `get_waittime` after `getkey` returned `Again`.
+    pub fn timeout_with(&self, other: Option<Instant>) -> Option<Duration> {
+        let deadline = match (self.deadline, other) {
+            (Some(deadline), Some(other)) => Some(deadline.min(other)),
+            (deadline, other) => deadline.or(other),
+        };
+        deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Matches `key` after the pending keys.
+    pub fn feed<A: Clone, K: Lookup<A> + ?Sized>(&mut self, keymap: &K, key: Event) -> Match<A> {
+        self.queued.push_back(key);
+        self.resume(keymap).unwrap()
+    }
+
+    /// Matches the next key left over after a previous match, if any.
+    pub fn resume<A: Clone, K: Lookup<A> + ?Sized>(&mut self, keymap: &K) -> Option<Match<A>> {
+        let key = self.queued.pop_front()?;
+        self.pending.push(key);
+        if keymap.is_prefix(&self.pending) {
+            self.deadline = if self.timeout >= 0 {
+                Some(Instant::now() + Duration::from_millis(self.timeout as u64))
+            } else {
+                None
+            };
+            return Some(Match::Pending);
+        }
+        if let Some(action) = keymap.get(&self.pending) {
+            self.pending.clear();
+            self.deadline = None;
+            return Some(Match::Matched(action.clone()));
+        }
+        let key = self.pending.pop().unwrap();
+        if self.pending.is_empty() {
+            return Some(Match::NoMatch(vec![key]));
+        }
+        self.queued.push_front(key);
+        self.flush(keymap)
+    }
+
+    /// Ends the pending keys if the deadline has passed.
+    pub fn check_timeout<A: Clone, K: Lookup<A> + ?Sized>(
+        &mut self,
+        keymap: &K,
+    ) -> Option<Match<A>> {
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => self.flush(keymap),
+            _ => None,
+        }
+    }
+
+    /// Ends the pending keys now, as when the input has reached its end.
+    pub fn flush<A: Clone, K: Lookup<A> + ?Sized>(&mut self, keymap: &K) -> Option<Match<A>> {
+        self.deadline = None;
+        if self.pending.is_empty() {
+            return None;
+        }
+        let mut keys = ::std::mem::take(&mut self.pending);
+        for len in (1..=keys.len()).rev() {
+            if let Some(action) = keymap.get(&keys[..len]) {
+                for key in keys.drain(len..).rev() {
+                    self.queued.push_front(key);
+                }
+                return Some(Match::Matched(action.clone()));
+            }
+        }
+        Some(Match::NoMatch(keys))
+    }
+}
+
+/// What a mode does with keys that none of its bindings match.
+#[derive(Default)]
+pub enum Unknown<A> {
+    /// Do what the parent mode does, or `Pass` without one.
+    #[default]
+    Inherit,
+    /// Give the keys back as `NoMatch`.
+    Pass,
+    /// Turn each key into an action, such as inserting the text of
+    /// `Event::Unicode` keys, or give it back as `NoMatch` for `None`.
+    Handle(fn(&Event) -> Option<A>),
+}
+
+impl<A> Clone for Unknown<A> {
+    fn clone(&self) -> Unknown<A> {
+        match *self {
+            Unknown::Inherit => Unknown::Inherit,
+            Unknown::Pass => Unknown::Pass,
+            Unknown::Handle(handler) => Unknown::Handle(handler),
+        }
+    }
+}
+
+impl<A> ::std::fmt::Debug for Unknown<A> {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Unknown::Inherit => write!(fmt, "Inherit"),
+            Unknown::Pass => write!(fmt, "Pass"),
+            Unknown::Handle(_) => write!(fmt, "Handle(..)"),
+        }
+    }
+}
+
+/// A named set of bindings for a `ModeStack`. A mode with a parent also
+/// has the bindings of the parent, and of its parent in turn, unless it
+/// binds the same keys itself.
+#[derive(Clone, Debug)]
+pub struct Mode<A> {
+    pub keymap: Keymap<A>,
+    pub parent: Option<String>,
+    pub unknown: Unknown<A>,
+}
+
+impl<A> Default for Mode<A> {
+    fn default() -> Mode<A> {
+        Mode::new()
+    }
+}
+
+impl<A> Mode<A> {
+    /// A mode without bindings or parent, which inherits `Unknown::Pass`.
+    pub fn new() -> Mode<A> {
+        Mode {
+            keymap: Keymap::new(),
+            parent: None,
+            unknown: Unknown::Inherit,
+        }
+    }
+}
+
+/// The keymaps of a mode and its ancestors, nearest first.
+struct Layers<'a, A> {
+    keymaps: Vec<&'a Keymap<A>>,
+}
+
+impl<'a, A> Lookup<A> for Layers<'a, A> {
+    fn get(&self, keys: &[Event]) -> Option<&A> {
+        self.keymaps.iter().find_map(|keymap| keymap.get(keys))
+    }
+    fn is_prefix(&self, keys: &[Event]) -> bool {
+        self.keymaps.iter().any(|keymap| keymap.is_prefix(keys))
+    }
+}
+
+/// The current mode followed by its ancestors, stopping at a missing mode
+/// or one already seen in a cycle.
+fn layers<'a, A>(modes: &'a BTreeMap<String, Mode<A>>, current: &'a str) -> Vec<&'a str> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut next = Some(current);
+    while let Some(name) = next {
+        let mode = match modes.get(name) {
+            Some(mode) if !names.contains(&name) => mode,
+            _ => break,
+        };
+        names.push(name);
+        next = mode.parent.as_deref();
+    }
+    names
+}
+
+fn lookup<'a, A>(modes: &'a BTreeMap<String, Mode<A>>, stack: &'a [String]) -> Layers<'a, A> {
+    Layers {
+        keymaps: layers(modes, stack.last().unwrap())
+            .into_iter()
+            .map(|name| &modes[name].keymap)
+            .collect(),
+    }
+}
+
+/// Named modes and a stack of the active ones, which matches keys against
+/// the mode on top.
+///
+/// Use it as a `KeySequenceMatcher`: `feed` it keys, call `resume` until
+/// it returns `None`, and mind its `timeout`. What is left unmatched goes
+/// to the mode's `Unknown` policy one key at a time. Pushing or popping a
+/// mode leaves pending keys to be matched in the new mode.
+#[derive(Debug)]
+pub struct ModeStack<A> {
+    modes: BTreeMap<String, Mode<A>>,
+    stack: Vec<String>,
+    matcher: KeySequenceMatcher,
+}
+
+impl<A: Clone> ModeStack<A> {
+    /// A stack with `base` at the bottom, which is never popped.
+    pub fn new(base: &str, mode: Mode<A>) -> ModeStack<A> {
+        let mut modes = BTreeMap::new();
+        modes.insert(base.to_string(), mode);
+        ModeStack {
+            modes,
+            stack: vec![base.to_string()],
+            matcher: KeySequenceMatcher::new(),
+        }
+    }
+
+    /// Adds or replaces the mode called `name`, returning the one it
+    /// replaced.
+    pub fn add_mode(&mut self, name: &str, mode: Mode<A>) -> Option<Mode<A>> {
+        self.modes.insert(name.to_string(), mode)
+    }
+    pub fn mode(&self, name: &str) -> Option<&Mode<A>> {
+        self.modes.get(name)
+    }
+    pub fn mode_mut(&mut self, name: &str) -> Option<&mut Mode<A>> {
+        self.modes.get_mut(name)
+    }
+    /// The names of every mode, in order.
+    pub fn mode_names(&self) -> Vec<&str> {
+        self.modes.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Makes `name` the current mode. Returns false, leaving the stack as
+    /// it was, if there is no such mode.
+    pub fn push(&mut self, name: &str) -> bool {
+        if !self.modes.contains_key(name) {
+            return false;
+        }
+        self.stack.push(name.to_string());
+        true
+    }
+    /// Returns to the mode below the current one, returning the name of
+    /// the current one, or `None` if it is the base mode.
+    pub fn pop(&mut self) -> Option<String> {
+        if self.stack.len() == 1 {
+            return None;
+        }
+        self.stack.pop()
+    }
+    pub fn current(&self) -> &str {
+        self.stack.last().unwrap()
+    }
+    /// The names of the active modes, from the base mode up.
+    pub fn stack(&self) -> &[String] {
+        &self.stack
+    }
+    /// The current mode followed by its ancestors, whose bindings are all
+    /// in effect.
+    pub fn layers(&self) -> Vec<&str> {
+        layers(&self.modes, self.current())
+    }
+
+    pub fn matcher(&self) -> &KeySequenceMatcher {
+        &self.matcher
+    }
+    /// The matcher, to set its timeout.
+    pub fn matcher_mut(&mut self) -> &mut KeySequenceMatcher {
+        &mut self.matcher
+    }
+    /// The time left until pending keys time out, suitable for
+    /// `Poll::poll`.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.matcher.timeout()
+    }
+
+    /// Every binding in effect in the current mode that begins with the
+    /// pending keys, without those keys, for showing which keys may follow.
+    /// Each comes with the name of the mode it is bound in.
+    pub fn next_keys(&self) -> Vec<(Vec<Event>, &A, &str)> {
+        let mut found: BTreeMap<Vec<Event>, (&A, &str)> = BTreeMap::new();
+        for name in self.layers() {
+            for (keys, action) in self.modes[name]
+                .keymap
+                .bindings_after(self.matcher.pending())
+            {
+                found.entry(keys).or_insert((action, name));
+            }
+        }
+        found
+            .into_iter()
+            .map(|(keys, (action, name))| (keys, action, name))
+            .collect()
+    }
+
+    /// Matches `key` after the pending keys, in the current mode.
+    pub fn feed(&mut self, key: Event) -> Match<A> {
+        self.matcher.queued.push_back(key);
+        self.resume().unwrap()
+    }
+    /// Matches the next key left over after a previous match, if any.
+    pub fn resume(&mut self) -> Option<Match<A>> {
+        let found = self.matcher.resume(&lookup(&self.modes, &self.stack))?;
+        Some(self.fall_through(found))
+    }
+    /// Ends the pending keys if the deadline has passed.
+    pub fn check_timeout(&mut self) -> Option<Match<A>> {
+        let found = self
+            .matcher
+            .check_timeout(&lookup(&self.modes, &self.stack))?;
+        Some(self.fall_through(found))
+    }
+    /// Ends the pending keys now, as when the input has reached its end.
+    pub fn flush(&mut self) -> Option<Match<A>> {
+        let found = self.matcher.flush(&lookup(&self.modes, &self.stack))?;
+        Some(self.fall_through(found))
+    }
+
+    fn unknown(&self) -> Unknown<A> {
+        for name in self.layers() {
+            match self.modes[name].unknown {
+                Unknown::Inherit => {}
+                ref unknown => return unknown.clone(),
+            }
+        }
+        Unknown::Pass
+    }
+
+    fn fall_through(&mut self, found: Match<A>) -> Match<A> {
+        let mut keys = match found {
+            Match::NoMatch(keys) => keys,
+            found => return found,
+        };
+        let handler = match self.unknown() {
+            Unknown::Handle(handler) => handler,
+            _ => return Match::NoMatch(keys),
+        };
+        for key in keys.drain(1..).rev() {
+            self.matcher.queued.push_front(key);
+        }
+        match handler(&keys[0]) {
+            Some(action) => Match::Matched(action),
+            None => Match::NoMatch(keys),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Just enough of a reader for compiled terminfo entries to find the keys
+//! a terminal has strings for.
+
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::c::{KeyMod, Sym};
+use crate::Event;
+
+/// The standard string capabilities for keys, by their index in a compiled
+/// entry, named as libtermkey names them: as in term.h, without `key_`.
+const KEY_CAPS: &[(usize, &str)] = &[
+    (55, "backspace"),
+    (59, "dc"),
+    (61, "down"),
+    (65, "f0"),
+    (66, "f1"),
+    (67, "f10"),
+    (68, "f2"),
+    (69, "f3"),
+    (70, "f4"),
+    (71, "f5"),
+    (72, "f6"),
+    (73, "f7"),
+    (74, "f8"),
+    (75, "f9"),
+    (76, "home"),
+    (77, "ic"),
+    (79, "left"),
+    (81, "npage"),
+    (82, "ppage"),
+    (83, "right"),
+    (87, "up"),
+    (148, "btab"),
+    (158, "beg"),
+    (159, "cancel"),
+    (160, "close"),
+    (161, "command"),
+    (162, "copy"),
+    (164, "end"),
+    (165, "enter"),
+    (166, "exit"),
+    (167, "find"),
+    (168, "help"),
+    (169, "mark"),
+    (170, "message"),
+    (171, "move"),
+    (172, "next"),
+    (173, "open"),
+    (174, "options"),
+    (175, "previous"),
+    (176, "print"),
+    (177, "redo"),
+    (178, "reference"),
+    (179, "refresh"),
+    (180, "replace"),
+    (181, "restart"),
+    (182, "resume"),
+    (183, "save"),
+    (184, "suspend"),
+    (185, "undo"),
+    (186, "sbeg"),
+    (187, "scancel"),
+    (188, "scommand"),
+    (189, "scopy"),
+    (191, "sdc"),
+    (193, "select"),
+    (194, "send"),
+    (196, "sexit"),
+    (197, "sfind"),
+    (198, "shelp"),
+    (199, "shome"),
+    (200, "sic"),
+    (201, "sleft"),
+    (202, "smessage"),
+    (203, "smove"),
+    (204, "snext"),
+    (205, "soptions"),
+    (206, "sprevious"),
+    (207, "sprint"),
+    (208, "sredo"),
+    (209, "sreplace"),
+    (210, "sright"),
+    (211, "srsume"),
+    (212, "ssave"),
+    (213, "ssuspend"),
+    (214, "sundo"),
+];
+
+/// The symbols libtermkey's terminfo driver gives the key capabilities.
+/// Those not listed are left out, as libtermkey has no symbol for them.
+const SYMS: &[(&str, Sym)] = &[
+    ("backspace", Sym::BACKSPACE),
+    ("beg", Sym::BEGIN),
+    ("cancel", Sym::CANCEL),
+    ("close", Sym::CLOSE),
+    ("command", Sym::COMMAND),
+    ("copy", Sym::COPY),
+    ("dc", Sym::DELETE),
+    ("down", Sym::DOWN),
+    ("end", Sym::END),
+    ("enter", Sym::ENTER),
+    ("exit", Sym::EXIT),
+    ("find", Sym::FIND),
+    ("help", Sym::HELP),
+    ("home", Sym::HOME),
+    ("ic", Sym::INSERT),
+    ("left", Sym::LEFT),
+    ("mark", Sym::MARK),
+    ("message", Sym::MESSAGE),
+    ("move", Sym::MOVE),
+    ("next", Sym::PAGEDOWN),
+    ("npage", Sym::PAGEDOWN),
+    ("open", Sym::OPEN),
+    ("options", Sym::OPTIONS),
+    ("print", Sym::PRINT),
+    ("previous", Sym::PAGEUP),
+    ("ppage", Sym::PAGEUP),
+    ("redo", Sym::REDO),
+    ("reference", Sym::REFERENCE),
+    ("refresh", Sym::REFRESH),
+    ("replace", Sym::REPLACE),
+    ("restart", Sym::RESTART),
+    ("resume", Sym::RESUME),
+    ("right", Sym::RIGHT),
+    ("save", Sym::SAVE),
+    ("select", Sym::SELECT),
+    ("suspend", Sym::SUSPEND),
+    ("undo", Sym::UNDO),
+    ("up", Sym::UP),
+];
+
+/// The keys `term`'s terminfo entry has strings for, as libtermkey's
+/// terminfo driver decodes them. Empty if the entry cannot be found or
+/// read.
+pub(crate) fn keys(term: &str) -> Vec<Event> {
+    let strings = match find(term).and_then(|data| strings(&data)) {
+        Some(strings) => strings,
+        None => return Vec::new(),
+    };
+    let function_keys = (11..=63).map(|num| (num + 205, format!("f{}", num)));
+    KEY_CAPS
+        .iter()
+        .map(|&(index, name)| (index, name.to_string()))
+        .chain(function_keys)
+        .filter(|&(index, _)| matches!(strings.get(index), Some(Some(_))))
+        .filter_map(|(_, name)| key(&name))
+        .collect()
+}
+
+fn key(name: &str) -> Option<Event> {
+    let sym = |name: &str| {
+        SYMS.iter()
+            .find(|&&(known, _)| known == name)
+            .map(|&(_, sym)| sym)
+    };
+    if let Some(num) = name.strip_prefix('f').and_then(|num| num.parse().ok()) {
+        return Some(Event::Function {
+            num,
+            mods: KeyMod::empty(),
+        });
+    }
+    if name == "btab" {
+        return Some(Event::KeySym {
+            sym: Sym::TAB,
+            mods: KeyMod::SHIFT,
+        });
+    }
+    // like libtermkey, a name that is no key but for an "s" in front is the
+    // key with Shift, as "sdc" is Shift-Delete
+    if let Some(sym) = sym(name) {
+        return Some(Event::KeySym {
+            sym,
+            mods: KeyMod::empty(),
+        });
+    }
+    Some(Event::KeySym {
+        sym: sym(name.strip_prefix('s')?)?,
+        mods: KeyMod::SHIFT,
+    })
+}
+
+/// Reads the compiled entry for `term` from wherever ncurses would look.
+fn find(term: &str) -> Option<Vec<u8>> {
+    let first = term.chars().next()?;
+    if term.contains('/') {
+        return None;
+    }
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"]
+            .iter()
+            .map(PathBuf::from),
+    );
+    dirs.iter().find_map(|dir| {
+        // macOS names the subdirectories by the first byte in hex
+        fs::read(dir.join(first.to_string()).join(term))
+            .or_else(|_| fs::read(dir.join(format!("{:x}", first as u32)).join(term)))
+            .ok()
+    })
+}
+
+/// The string capabilities of a compiled entry, in either the legacy or
+/// the 32-bit format, with `None` for those it lacks.
+fn strings(data: &[u8]) -> Option<Vec<Option<Vec<u8>>>> {
+    let short = |at: usize| -> Option<i16> {
+        let bytes = data.get(at..at + 2)?;
+        Some(i16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let count = |at: usize| short(at).and_then(|n| usize::try_from(n).ok());
+
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names, booleans, numbers, strings, table_size) =
+        (count(2)?, count(4)?, count(6)?, count(8)?, count(10)?);
+
+    let mut at = 12 + names + booleans;
+    // the numbers start on an even byte
+    at += at % 2;
+    at += numbers * number_size;
+    let table = data.get(at + strings * 2..at + strings * 2 + table_size)?;
+
+    (0..strings)
+        .map(|index| {
+            let offset = match short(at + index * 2)? {
+                offset if offset < 0 => return Some(None),
+                offset => offset as usize,
+            };
+            let string = table.get(offset..)?;
+            let len = string.iter().position(|&byte| byte == 0)?;
+            Some(Some(string[..len].to_vec()))
+        })
+        .collect()
+}
@@ -0,0 +1,34 @@
+//! Escape sequences for the kitty keyboard protocol's progressive
+//! enhancement, see <https://sw.kovidgoyal.net/kitty/keyboard-protocol/>.
+//!
+//! Write them to the terminal yourself. Either backend decodes the keys sent
+//! in the enhanced encodings, and reports the reply to `query_flags` as
+//! `Event::KittyFlags`. With libtermkey, which drops the sub-parameters the
+//! protocol relies on, those reports are decoded by the wrapper instead.
+
+bitflags! { pub struct Flags: ::libc::c_int
+{
+  const DISAMBIGUATE_ESCAPE_CODES       = 1 << 0;
+  const REPORT_EVENT_TYPES              = 1 << 1;
+  const REPORT_ALTERNATE_KEYS           = 1 << 2;
+  const REPORT_ALL_KEYS_AS_ESCAPE_CODES = 1 << 3;
+  const REPORT_ASSOCIATED_TEXT          = 1 << 4;
+}}
+
+/// Pushes `flags` onto the terminal's stack of enhancement flags.
+pub fn push_flags(flags: Flags) -> String {
+    format!("\x1b[>{}u", flags.bits())
+}
+
+/// Pops `count` entries off the terminal's stack, restoring the flags that
+/// were in effect before the matching pushes.
+pub fn pop_flags(count: usize) -> String {
+    format!("\x1b[<{}u", count)
+}
+
+/// Asks the terminal for the flags currently in effect. Terminals that do
+/// not implement the protocol do not reply, so follow this with a primary
+/// device attributes request (`\x1b[c`) and give up once that is answered.
+pub fn query_flags() -> &'static str {
+    "\x1b[?u"
+}
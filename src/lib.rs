@@ -3,120 +3,249 @@ extern crate bitflags;
 
 extern crate libc;
 pub mod c;
+mod decode;
+pub mod keymap;
+pub mod kitty;
+pub mod query;
+pub mod record;
 
+#[cfg(not(feature = "pure-rust"))]
+mod libtermkey;
+#[cfg(not(feature = "pure-rust"))]
+use crate::libtermkey as backend;
+#[cfg(feature = "pure-rust")]
+mod pure;
+#[cfg(feature = "pure-rust")]
+use crate::pure as backend;
+
+#[cfg(feature = "mio")]
+mod mio_io;
+#[cfg(feature = "mio")]
+pub use crate::mio_io::KeyDrain;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+#[cfg(feature = "tokio")]
+pub use crate::tokio_io::AsyncTermKey;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "serde")]
+pub use crate::serde_impl::compact;
+
+/// Keys are decoded by libtermkey, or with the `pure-rust` feature by a
+/// Rust port of its core and CSI driver that needs no C library at all.
 pub struct TermKey {
-    tk: *mut c::TermKey,
+    tk: backend::TermKey,
+    /// Where `query` writes its requests.
+    output_fd: c::c_int,
+    /// Keys read while `query` waited for its reply.
+    queued: ::std::collections::VecDeque<Event>,
 }
 
-impl TermKey {
-    pub fn new(fd: c::c_int, flags: c::Flag) -> TermKey {
-        unsafe {
-            c::CHECK_VERSION();
-            let tk = c::termkey_new(fd, std::mem::transmute(flags));
-            if tk as usize == 0 {
-                panic!()
-            }
-            TermKey { tk }
+/// Errors reported by the fallible `TermKey` constructors and state changes.
+#[derive(Debug)]
+pub enum Error {
+    /// The terminal name passed to `try_new_abstract` contained a NUL byte.
+    NulInTermName,
+    /// libtermkey failed to allocate or initialise the instance.
+    InitFailed(::std::io::Error),
+    /// `termkey_start` failed, usually because the termios setup failed.
+    StartFailed(::std::io::Error),
+    /// `termkey_stop` failed, usually because restoring termios failed.
+    StopFailed(::std::io::Error),
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::NulInTermName => write!(fmt, "terminal name contains a NUL byte"),
+            Error::InitFailed(ref err) => write!(fmt, "failed to create termkey instance: {}", err),
+            Error::StartFailed(ref err) => write!(fmt, "failed to start termkey: {}", err),
+            Error::StopFailed(ref err) => write!(fmt, "failed to stop termkey: {}", err),
         }
     }
-    pub fn new_abstract(term: &str, flags: c::Flag) -> TermKey {
-        unsafe {
-            c::CHECK_VERSION();
-            ::std::ffi::CString::new(term.as_bytes())
-                .map(|c_buffer| {
-                    let tk = c::termkey_new_abstract(c_buffer.as_ptr(), std::mem::transmute(flags));
-                    if tk as usize == 0 {
-                        panic!()
-                    }
-                    TermKey { tk }
-                })
-                .unwrap()
+}
+
+impl ::std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            Error::NulInTermName => None,
+            Error::InitFailed(ref err)
+            | Error::StartFailed(ref err)
+            | Error::StopFailed(ref err) => Some(err),
         }
     }
 }
 
-impl Drop for TermKey {
-    fn drop(&mut self) {
-        unsafe { c::termkey_destroy(self.tk) }
+impl TermKey {
+    pub fn try_new(fd: c::c_int, flags: c::Flag) -> ::std::result::Result<TermKey, Error> {
+        backend::TermKey::new(fd, flags).map(TermKey::wrap)
+    }
+    pub fn try_new_abstract(term: &str, flags: c::Flag) -> ::std::result::Result<TermKey, Error> {
+        backend::TermKey::new_abstract(term, flags).map(TermKey::wrap)
+    }
+    fn wrap(tk: backend::TermKey) -> TermKey {
+        TermKey {
+            tk,
+            output_fd: -1,
+            queued: ::std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Panicking variant of `try_new`.
+    pub fn new(fd: c::c_int, flags: c::Flag) -> TermKey {
+        TermKey::try_new(fd, flags).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Panicking variant of `try_new_abstract`.
+    pub fn new_abstract(term: &str, flags: c::Flag) -> TermKey {
+        TermKey::try_new_abstract(term, flags).unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 impl TermKey {
-    pub fn start(&mut self) //-> Result<(), ()>
-    {
-        unsafe {
-            if c::termkey_start(self.tk) == 0 {
-                panic!()
-            }
-        }
+    pub fn try_start(&mut self) -> ::std::result::Result<(), Error> {
+        self.tk.start()
     }
-    pub fn stop(&mut self) //-> Result<(), ()>
-    {
-        unsafe {
-            if c::termkey_stop(self.tk) == 0 {
-                panic!()
-            }
-        }
+    pub fn try_stop(&mut self) -> ::std::result::Result<(), Error> {
+        self.tk.stop()
+    }
+    /// Panicking variant of `try_start`.
+    pub fn start(&mut self) {
+        self.try_start().unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Panicking variant of `try_stop`.
+    pub fn stop(&mut self) {
+        self.try_stop().unwrap_or_else(|err| panic!("{}", err))
     }
     pub fn is_started(&mut self) -> bool {
-        unsafe { c::termkey_is_started(self.tk) != 0 }
+        self.tk.is_started()
     }
 }
 
 impl TermKey {
     pub fn get_fd(&mut self) -> isize {
-        unsafe { c::termkey_get_fd(self.tk) as isize }
+        self.tk.get_fd() as isize
     }
 
     pub fn get_flags(&mut self) -> c::Flag {
-        unsafe { std::mem::transmute(c::termkey_get_flags(self.tk)) }
+        self.tk.get_flags()
     }
     pub fn set_flags(&mut self, newflags: c::Flag) {
-        unsafe { c::termkey_set_flags(self.tk, std::mem::transmute(newflags)) }
+        self.tk.set_flags(newflags)
     }
 
     pub fn get_waittime(&mut self) -> isize {
-        unsafe { c::termkey_get_waittime(self.tk) as isize }
+        self.tk.get_waittime()
     }
     pub fn set_waittime(&mut self, msec: isize) {
-        unsafe { c::termkey_set_waittime(self.tk, msec as c::c_int) }
+        self.tk.set_waittime(msec)
     }
 
     pub fn get_canonflags(&mut self) -> c::Canon {
-        unsafe { std::mem::transmute(c::termkey_get_canonflags(self.tk)) }
+        self.tk.get_canonflags()
     }
     pub fn set_canonflags(&mut self, cflags: c::Canon) {
-        unsafe { c::termkey_set_canonflags(self.tk, std::mem::transmute(cflags)) }
+        self.tk.set_canonflags(cflags)
     }
 
     pub fn get_buffer_size(&mut self) -> usize {
-        unsafe { c::termkey_get_buffer_size(self.tk) as usize }
+        self.tk.get_buffer_size()
     }
     pub fn set_buffer_size(&mut self, size: usize) -> isize {
-        unsafe { c::termkey_set_buffer_size(self.tk, size as c::size_t) as isize }
+        self.tk.set_buffer_size(size)
     }
 
     pub fn get_buffer_remaining(&mut self) -> usize {
-        unsafe { c::termkey_get_buffer_remaining(self.tk) as usize }
+        self.tk.get_buffer_remaining()
+    }
+
+    /// The size in bytes at which a bracketed paste is reported even though
+    /// it has not ended yet, as an `Event::Paste` with `more` set. Defaults
+    /// to 1 MiB.
+    pub fn get_paste_max(&mut self) -> usize {
+        self.tk.get_paste_max()
+    }
+    pub fn set_paste_max(&mut self, size: usize) {
+        self.tk.set_paste_max(size)
+    }
+
+    /// The size in bytes beyond which the rest of a DCS, OSC or APC string
+    /// is dropped rather than kept for its event. Defaults to 1 MiB.
+    pub fn get_string_max(&mut self) -> usize {
+        self.tk.get_string_max()
+    }
+    pub fn set_string_max(&mut self, size: usize) {
+        self.tk.set_string_max(size)
+    }
+
+    /// Whether mouse reports in the SGR encoding count pixels rather than
+    /// cells. Set this when enabling SGR-Pixels mode (DECSET 1016), as the
+    /// reports themselves look the same either way.
+    pub fn get_mouse_pixels(&mut self) -> bool {
+        self.tk.get_mouse_pixels()
+    }
+    pub fn set_mouse_pixels(&mut self, pixels: bool) {
+        self.tk.set_mouse_pixels(pixels)
+    }
+}
+
+impl ::std::os::unix::io::AsRawFd for TermKey {
+    fn as_raw_fd(&self) -> ::std::os::unix::io::RawFd {
+        self.tk.get_fd()
     }
 }
 
+/// Compared and hashed by the bytes before the terminating NUL only, as
+/// libtermkey leaves whatever was there before in the rest.
 #[derive(Clone, Copy)]
 pub struct Utf8Char {
     pub bytes: [c::c_char; 7],
 }
+impl Utf8Char {
+    fn encoded(&self) -> &[c::c_char] {
+        let len = self.bytes.iter().position(|&b| b == 0).unwrap_or(7);
+        &self.bytes[..len]
+    }
+}
 impl PartialEq for Utf8Char {
     fn eq(&self, other: &Utf8Char) -> bool {
-        self.bytes == other.bytes
+        self.encoded() == other.encoded()
     }
 }
+impl Eq for Utf8Char {}
 impl PartialOrd for Utf8Char {
     fn partial_cmp(&self, other: &Utf8Char) -> Option<::std::cmp::Ordering> {
-        self.bytes.partial_cmp(&other.bytes)
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Utf8Char {
+    fn cmp(&self, other: &Utf8Char) -> ::std::cmp::Ordering {
+        self.encoded().cmp(other.encoded())
+    }
+}
+impl ::std::hash::Hash for Utf8Char {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        self.encoded().hash(state)
+    }
+}
+impl ::std::fmt::Debug for Utf8Char {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let bytes: &[u8] = unsafe { &*(self.encoded() as *const [i8] as *const [u8]) };
+        write!(fmt, "{:?}", String::from_utf8_lossy(bytes))
     }
 }
 
 impl Utf8Char {
+    pub fn new(codepoint: char) -> Utf8Char {
+        let mut buf = [0; 4];
+        let mut bytes = [0; 7];
+        for (byte, b) in bytes
+            .iter_mut()
+            .zip(codepoint.encode_utf8(&mut buf).bytes())
+        {
+            *byte = b as c::c_char;
+        }
+        Utf8Char { bytes }
+    }
     pub fn s(&self) -> &str {
         unsafe {
             let bytes: &[c::c_char] = &self.bytes;
@@ -128,10 +257,133 @@ impl Utf8Char {
     }
 }
 
+/// Arguments of a CSI sequence, as parsed by `termkey_interpret_csi`.
+/// Missing arguments are reported as -1.
+#[derive(Clone, Copy)]
+pub struct CsiArgs {
+    pub args: [isize; 16],
+    pub len: usize,
+}
+impl CsiArgs {
+    pub fn as_slice(&self) -> &[isize] {
+        &self.args[0..self.len]
+    }
+}
+impl PartialEq for CsiArgs {
+    fn eq(&self, other: &CsiArgs) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+impl Eq for CsiArgs {}
+impl PartialOrd for CsiArgs {
+    fn partial_cmp(&self, other: &CsiArgs) -> Option<::std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for CsiArgs {
+    fn cmp(&self, other: &CsiArgs) -> ::std::cmp::Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+impl ::std::hash::Hash for CsiArgs {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+impl ::std::fmt::Debug for CsiArgs {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        fmt.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+/// The key a kitty keyboard protocol report is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyCode {
+    Unicode(char),
+    Function(isize),
+    KeySym(c::Sym),
+}
+
+/// A mouse button, as numbered by xterm's mouse reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    Back,
+    Forward,
+    /// Any other button by number, or 0 for a release that does not say
+    /// which button it was.
+    Other(u8),
+}
+
+impl MouseButton {
+    pub fn new(number: u8) -> MouseButton {
+        match number {
+            1 => MouseButton::Left,
+            2 => MouseButton::Middle,
+            3 => MouseButton::Right,
+            4 => MouseButton::WheelUp,
+            5 => MouseButton::WheelDown,
+            6 => MouseButton::WheelLeft,
+            7 => MouseButton::WheelRight,
+            8 => MouseButton::Back,
+            9 => MouseButton::Forward,
+            number => MouseButton::Other(number),
+        }
+    }
+    pub fn number(self) -> u8 {
+        match self {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+            MouseButton::WheelUp => 4,
+            MouseButton::WheelDown => 5,
+            MouseButton::WheelLeft => 6,
+            MouseButton::WheelRight => 7,
+            MouseButton::Back => 8,
+            MouseButton::Forward => 9,
+            MouseButton::Other(number) => number,
+        }
+    }
+}
+
+/// Which Device Attributes request an `Event::DeviceAttributes` answers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AttributesKind {
+    /// DA1, requested by `CSI c` and answered by `CSI ? ... c`.
+    Primary,
+    /// DA2, requested by `CSI > c` and answered by `CSI > ... c`.
+    Secondary,
+    /// DA3, requested by `CSI = c` and answered by `DCS ! | ... ST`.
+    Tertiary,
+}
+
+impl ::std::fmt::Display for MouseButton {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(fmt, "{}", self.number())
+    }
+}
+
 // called Key in C
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+//
+// Ordered like libtermkey's termkey_keycmp, which ignores the UTF-8
+// encoding, and with the modifiers deciding last. Canonicalise keys before
+// comparing them, or use `TermKey::keycmp`.
+#[derive(Clone, Debug)]
 pub enum Event {
-    UnknownCsi,
+    /// A CSI sequence libtermkey does not recognise. `initial` and
+    /// `intermediate` are 0 when the sequence has no such byte.
+    UnknownCsi {
+        args: CsiArgs,
+        initial: isize,
+        intermediate: isize,
+        command: isize,
+    },
 
     Unicode {
         codepoint: char,
@@ -146,12 +398,18 @@ pub enum Event {
         sym: c::Sym,
         mods: c::KeyMod,
     },
+    /// Reports in the SGR encoding carry their coordinates in full with
+    /// either backend. libtermkey still decodes the rxvt encoding for the
+    /// default backend, and clamps its columns above 4095 and lines above
+    /// 2047.
     Mouse {
         ev: c::MouseEvent,
         mods: c::KeyMod,
-        button: isize,
+        button: MouseButton,
         line: isize,
         col: isize,
+        /// Whether `line` and `col` count pixels rather than cells.
+        pixels: bool,
     },
     Position {
         line: isize,
@@ -162,23 +420,671 @@ pub enum Event {
         mode: isize,
         value: isize,
     },
+
+    /// A key reported by the kitty keyboard protocol that is a repeat or a
+    /// release, or that comes with alternate keys or associated text. Plain
+    /// presses are reported as `Unicode`, `Function` or `KeySym` as usual.
+    KittyKey {
+        code: KeyCode,
+        mods: c::KeyMod,
+        event: c::KeyEvent,
+        /// The key with Shift applied, if it differs from `code`.
+        shifted: Option<char>,
+        /// The key in the standard PC-101 layout.
+        base: Option<char>,
+        /// The text the key would insert.
+        text: String,
+    },
+    /// The terminal's reply to `kitty::query_flags`.
+    KittyFlags {
+        flags: kitty::Flags,
+    },
+
+    /// Text pasted while bracketed paste mode (DECSET 2004) is on. A paste
+    /// longer than `get_paste_max` bytes is split over several events, all
+    /// but the last with `more` set.
+    Paste {
+        text: String,
+        more: bool,
+    },
+
+    /// The terminal gained or lost focus, reported while focus event mode
+    /// (DECSET 1004) is on.
+    FocusGained,
+    FocusLost,
+
+    /// The terminal's answer to a Device Attributes request: the numbers
+    /// of a DA1 or DA2 reply, or for DA3 the unit ID as a single number.
+    DeviceAttributes {
+        kind: AttributesKind,
+        params: Vec<isize>,
+    },
+    /// The terminal's name and version, its answer to XTVERSION
+    /// (`CSI > q`), like `"xterm(388)"`.
+    TerminalVersion(String),
+
+    /// An Operating System Command string (`ESC ]`), such as the reply to
+    /// a colour query. `number` is the number before the first `;`, or -1
+    /// if it is too large, in which case `payload` is all of the string.
+    /// Only an `ESC ]` followed by a number starts one; otherwise it is
+    /// decoded as Alt-] and the keys typed after it.
+    Osc {
+        number: isize,
+        payload: String,
+    },
+    /// A Device Control String (`ESC P`) other than the replies decoded
+    /// above, such as the reply to DECRQSS or XTGETTCAP. Its header is
+    /// split up as for `UnknownCsi`, and `payload` is what follows the
+    /// final byte.
+    Dcs {
+        args: CsiArgs,
+        initial: isize,
+        intermediate: isize,
+        command: isize,
+        payload: String,
+    },
+    /// An Application Program Command string (`ESC _`), such as a kitty
+    /// graphics protocol response.
+    Apc {
+        payload: String,
+    },
 }
 
 impl Event {
+    /// The variants are declared in the order of libtermkey's key types.
+    fn rank(&self) -> u8 {
+        match self {
+            Event::UnknownCsi { .. } => 0,
+            Event::Unicode { .. } => 1,
+            Event::Function { .. } => 2,
+            Event::KeySym { .. } => 3,
+            Event::Mouse { .. } => 4,
+            Event::Position { .. } => 5,
+            Event::ModeReport { .. } => 6,
+            Event::KittyKey { .. } => 7,
+            Event::KittyFlags { .. } => 8,
+            Event::Paste { .. } => 9,
+            Event::FocusGained => 10,
+            Event::FocusLost => 11,
+            Event::DeviceAttributes { .. } => 12,
+            Event::TerminalVersion(_) => 13,
+            Event::Osc { .. } => 14,
+            Event::Dcs { .. } => 15,
+            Event::Apc { .. } => 16,
+        }
+    }
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Event) -> bool {
+        self.cmp(other) == ::std::cmp::Ordering::Equal
+    }
+}
+impl Eq for Event {}
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Event) -> Option<::std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Event {
+    fn cmp(&self, other: &Event) -> ::std::cmp::Ordering {
+        self.rank()
+            .cmp(&other.rank())
+            .then_with(|| match (self, other) {
+                (
+                    Event::UnknownCsi {
+                        args: a1,
+                        initial: i1,
+                        intermediate: m1,
+                        command: c1,
+                    },
+                    Event::UnknownCsi {
+                        args: a2,
+                        initial: i2,
+                        intermediate: m2,
+                        command: c2,
+                    },
+                ) => (c1, i1, m1, a1).cmp(&(c2, i2, m2, a2)),
+                (
+                    Event::Unicode {
+                        codepoint: c1,
+                        mods: m1,
+                        ..
+                    },
+                    Event::Unicode {
+                        codepoint: c2,
+                        mods: m2,
+                        ..
+                    },
+                ) => (c1, m1).cmp(&(c2, m2)),
+                (Event::Function { num: n1, mods: m1 }, Event::Function { num: n2, mods: m2 }) => {
+                    (n1, m1).cmp(&(n2, m2))
+                }
+                (Event::KeySym { sym: s1, mods: m1 }, Event::KeySym { sym: s2, mods: m2 }) => {
+                    (s1, m1).cmp(&(s2, m2))
+                }
+                (
+                    Event::Mouse {
+                        ev: e1,
+                        mods: m1,
+                        button: b1,
+                        line: l1,
+                        col: c1,
+                        pixels: p1,
+                    },
+                    Event::Mouse {
+                        ev: e2,
+                        mods: m2,
+                        button: b2,
+                        line: l2,
+                        col: c2,
+                        pixels: p2,
+                    },
+                ) => (b1, e1, c1, l1, p1, m1).cmp(&(b2, e2, c2, l2, p2, m2)),
+                (Event::Position { line: l1, col: c1 }, Event::Position { line: l2, col: c2 }) => {
+                    (l1, c1).cmp(&(l2, c2))
+                }
+                (
+                    Event::ModeReport {
+                        initial: i1,
+                        mode: m1,
+                        value: v1,
+                    },
+                    Event::ModeReport {
+                        initial: i2,
+                        mode: m2,
+                        value: v2,
+                    },
+                ) => (i1, m1, v1).cmp(&(i2, m2, v2)),
+                (
+                    Event::KittyKey {
+                        code: c1,
+                        mods: m1,
+                        event: e1,
+                        shifted: s1,
+                        base: b1,
+                        text: t1,
+                    },
+                    Event::KittyKey {
+                        code: c2,
+                        mods: m2,
+                        event: e2,
+                        shifted: s2,
+                        base: b2,
+                        text: t2,
+                    },
+                ) => (c1, e1, s1, b1, t1, m1).cmp(&(c2, e2, s2, b2, t2, m2)),
+                (Event::KittyFlags { flags: f1 }, Event::KittyFlags { flags: f2 }) => f1.cmp(f2),
+                (Event::Paste { text: t1, more: m1 }, Event::Paste { text: t2, more: m2 }) => {
+                    (t1, m1).cmp(&(t2, m2))
+                }
+                (
+                    Event::DeviceAttributes {
+                        kind: k1,
+                        params: p1,
+                    },
+                    Event::DeviceAttributes {
+                        kind: k2,
+                        params: p2,
+                    },
+                ) => (k1, p1).cmp(&(k2, p2)),
+                (Event::TerminalVersion(v1), Event::TerminalVersion(v2)) => v1.cmp(v2),
+                (
+                    Event::Osc {
+                        number: n1,
+                        payload: p1,
+                    },
+                    Event::Osc {
+                        number: n2,
+                        payload: p2,
+                    },
+                ) => (n1, p1).cmp(&(n2, p2)),
+                (
+                    Event::Dcs {
+                        args: a1,
+                        initial: i1,
+                        intermediate: m1,
+                        command: c1,
+                        payload: p1,
+                    },
+                    Event::Dcs {
+                        args: a2,
+                        initial: i2,
+                        intermediate: m2,
+                        command: c2,
+                        payload: p2,
+                    },
+                ) => (c1, i1, m1, a1, p1).cmp(&(c2, i2, m2, a2, p2)),
+                (Event::Apc { payload: p1 }, Event::Apc { payload: p2 }) => p1.cmp(p2),
+                _ => ::std::cmp::Ordering::Equal,
+            })
+    }
+}
+impl ::std::hash::Hash for Event {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            Event::UnknownCsi {
+                args,
+                initial,
+                intermediate,
+                command,
+            } => (command, initial, intermediate, args).hash(state),
+            Event::Unicode {
+                codepoint, mods, ..
+            } => (codepoint, mods).hash(state),
+            Event::Function { num, mods } => (num, mods).hash(state),
+            Event::KeySym { sym, mods } => (sym, mods).hash(state),
+            Event::Mouse {
+                ev,
+                mods,
+                button,
+                line,
+                col,
+                pixels,
+            } => (button, ev, col, line, pixels, mods).hash(state),
+            Event::Position { line, col } => (line, col).hash(state),
+            Event::ModeReport {
+                initial,
+                mode,
+                value,
+            } => (initial, mode, value).hash(state),
+            Event::KittyKey {
+                code,
+                mods,
+                event,
+                shifted,
+                base,
+                text,
+            } => (code, event, shifted, base, text, mods).hash(state),
+            Event::KittyFlags { flags } => flags.hash(state),
+            Event::Paste { text, more } => (text, more).hash(state),
+            Event::FocusGained | Event::FocusLost => {}
+            Event::DeviceAttributes { kind, params } => (kind, params).hash(state),
+            Event::TerminalVersion(version) => version.hash(state),
+            Event::Osc { number, payload } => (number, payload).hash(state),
+            Event::Dcs {
+                args,
+                initial,
+                intermediate,
+                command,
+                payload,
+            } => (command, initial, intermediate, args, payload).hash(state),
+            Event::Apc { payload } => payload.hash(state),
+        }
+    }
+}
+
+/// The names of the focus events, plain and with `LOWERSPACE`.
+const FOCUS_NAMES: [(Event, &str, &str); 2] = [
+    (Event::FocusGained, "FocusGained", "focus gained"),
+    (Event::FocusLost, "FocusLost", "focus lost"),
+];
+
+/// Formats `FocusGained` and `FocusLost`; `None` for any other event.
+pub(crate) fn strf_focus(key: &Event, format: c::Format) -> Option<String> {
+    let (_, long, spaced) = FOCUS_NAMES.iter().find(|(event, _, _)| event == key)?;
+    let name = if format.contains(c::Format::LOWERSPACE) {
+        spaced
+    } else {
+        long
+    };
+    Some(if format.contains(c::Format::WRAPBRACKET) {
+        format!("<{}>", name)
+    } else {
+        name.to_string()
+    })
+}
+
+/// Parses the names `strf_focus` produces.
+pub(crate) fn strp_focus(s: &str, format: c::Format) -> Option<(Event, &str)> {
+    FOCUS_NAMES.iter().find_map(|(event, long, spaced)| {
+        let name = if format.contains(c::Format::LOWERSPACE) {
+            spaced
+        } else {
+            long
+        };
+        s.strip_prefix(name).map(|rest| (event.clone(), rest))
+    })
+}
+
+/// The suffixes marking kitty keyboard protocol repeats and releases,
+/// plain and with `LOWERSPACE`. Presses have none.
+const KEY_EVENT_NAMES: [(c::KeyEvent, &str, &str); 2] = [
+    (c::KeyEvent::REPEAT, ":Repeat", ":repeat"),
+    (c::KeyEvent::RELEASE, ":Release", ":release"),
+];
+
+/// The suffix `strfkey` puts after the name of a key to mark a repeat or a
+/// release.
+pub(crate) fn strf_key_event(event: c::KeyEvent, format: c::Format) -> &'static str {
+    KEY_EVENT_NAMES
+        .iter()
+        .find(|&&(known, _, _)| known == event)
+        .map_or("", |&(_, long, spaced)| {
+            if format.contains(c::Format::LOWERSPACE) {
+                spaced
+            } else {
+                long
+            }
+        })
+}
+
+/// Parses the suffix `strf_key_event` produces after a key `strpkey` has
+/// parsed, making it a `KittyKey` with that event.
+pub(crate) fn strp_key_event(key: Event, s: &str, format: c::Format) -> (Event, &str) {
+    if !matches!(
+        key,
+        Event::Unicode { .. } | Event::Function { .. } | Event::KeySym { .. }
+    ) {
+        return (key, s);
+    }
+    let found = KEY_EVENT_NAMES.iter().find_map(|&(event, long, spaced)| {
+        let name = if format.contains(c::Format::LOWERSPACE) {
+            spaced
+        } else {
+            long
+        };
+        s.strip_prefix(name).map(|rest| (event, rest))
+    });
+    match found {
+        Some((event, rest)) => (
+            decode::kitty_key(key, event, None, None, String::new()),
+            rest,
+        ),
+        None => (key, s),
+    }
+}
+
+/// The kitty keyboard protocol's extra modifiers, which libtermkey does not
+/// know. Their names are always long, as short ones would clash with the
+/// standard ones, and META's is not "Meta" as `ALTISMETA` names Alt that.
+/// The lock keys are state rather than modifiers and are not shown.
+static EXTRA_MODNAMES: [(c::KeyMod, &str); 3] = [
+    (c::KeyMod::SUPER, "Super"),
+    (c::KeyMod::HYPER, "Hyper"),
+    (c::KeyMod::META, "KittyMeta"),
+];
+
+fn mod_separator(format: c::Format) -> char {
+    if format.contains(c::Format::SPACEMOD) {
+        ' '
+    } else {
+        '-'
+    }
+}
+
+/// Formats the extra modifiers in `mods`, each followed by the separator,
+/// for the front of a key name.
+pub(crate) fn strf_extra_mods(mods: c::KeyMod, format: c::Format) -> String {
+    let mut buf = String::new();
+    for &(modifier, name) in EXTRA_MODNAMES.iter() {
+        if mods.contains(modifier) {
+            if format.contains(c::Format::LOWERMOD) {
+                buf.push_str(&name.to_ascii_lowercase());
+            } else {
+                buf.push_str(name);
+            }
+            buf.push(mod_separator(format));
+        }
+    }
+    buf
+}
+
+/// Parses the names `strf_extra_mods` produces off the front of `s`.
+pub(crate) fn strp_extra_mods(mut s: &str, format: c::Format) -> (c::KeyMod, &str) {
+    let mut mods = c::KeyMod::empty();
+    while let Some(n) = s.find(mod_separator(format)) {
+        let name = &s[..n];
+        let extra = EXTRA_MODNAMES.iter().find(|&&(_, extra)| {
+            name == extra
+                || format.contains(c::Format::LOWERMOD) && name == extra.to_ascii_lowercase()
+        });
+        match extra {
+            Some(&(modifier, _)) => mods |= modifier,
+            None => break,
+        }
+        s = &s[n + 1..];
+    }
+    (mods, s)
+}
+
+const MOUSE_EVENT_NAMES: [(c::MouseEvent, &str); 4] = [
+    (c::MouseEvent::UNKNOWN, "Unknown"),
+    (c::MouseEvent::PRESS, "Press"),
+    (c::MouseEvent::DRAG, "Drag"),
+    (c::MouseEvent::RELEASE, "Release"),
+];
+
+/// Decodes the button code of an xterm mouse report, without its modifier
+/// bits. `release` is set for reports that say so apart from the code.
+pub(crate) fn decode_mouse_button(code: isize, release: bool) -> (c::MouseEvent, MouseButton) {
+    let pressed = if code & 0x20 != 0 {
+        c::MouseEvent::DRAG
+    } else {
+        c::MouseEvent::PRESS
+    };
+    let (ev, number) = match code & !0x3c {
+        code @ 0..=2 => (pressed, code + 1),
+        3 => (c::MouseEvent::RELEASE, 0),
+        code @ 64..=67 => (pressed, code - 64 + 4),
+        code @ 128..=131 => (pressed, code - 128 + 8),
+        _ => (c::MouseEvent::UNKNOWN, 0),
+    };
+    let ev = if release { c::MouseEvent::RELEASE } else { ev };
+    (ev, MouseButton::new(number as u8))
+}
+
+/// Makes the event for a complete DCS, OSC or APC string, given the second
+/// byte of its 7-bit introducer and the text between that and the string
+/// terminator.
+pub(crate) fn ctrl_string(kind: u8, text: String) -> Event {
+    match kind {
+        b']' => {
+            let (number, payload) = text.split_once(';').unwrap_or((&text, ""));
+            match number.parse() {
+                Ok(value) if number.bytes().all(|b| b.is_ascii_digit()) => Event::Osc {
+                    number: value,
+                    payload: payload.to_string(),
+                },
+                _ => Event::Osc {
+                    number: -1,
+                    payload: text,
+                },
+            }
+        }
+        b'P' => dcs(text),
+        _ => Event::Apc { payload: text },
+    }
+}
+
+/// Splits a DCS into its header, which is laid out like a CSI sequence, and
+/// its payload.
+fn dcs(text: String) -> Event {
+    let mut args = CsiArgs {
+        args: [0; 16],
+        len: 0,
+    };
+    let end = match text.bytes().position(|b| (0x40..0x80).contains(&b)) {
+        Some(end) => end,
+        None => {
+            return Event::Dcs {
+                args,
+                initial: 0,
+                intermediate: 0,
+                command: 0,
+                payload: text,
+            }
+        }
+    };
+    let command = text.as_bytes()[end];
+    let mut header = &text.as_bytes()[..end];
+    let mut initial = 0;
+    if let Some((&first, rest)) = header.split_first() {
+        if (b'<'..=b'?').contains(&first) {
+            initial = first;
+            header = rest;
+        }
+    }
+    let mut intermediate = 0;
+    if let Some((&last, rest)) = header.split_last() {
+        if (0x20..=0x2f).contains(&last) {
+            intermediate = last;
+            header = rest;
+        }
+    }
+    if !header.is_empty() {
+        for (arg, param) in args.args.iter_mut().zip(header.split(|&b| b == b';')) {
+            *arg = std::str::from_utf8(param)
+                .ok()
+                .and_then(|param| param.parse().ok())
+                .unwrap_or(-1);
+            args.len += 1;
+        }
+    }
+    let payload = text[end + 1..].to_string();
+
+    match (initial, intermediate, command) {
+        (b'>', 0, b'|') => Event::TerminalVersion(payload),
+        (0, b'!', b'|') => Event::DeviceAttributes {
+            kind: AttributesKind::Tertiary,
+            params: isize::from_str_radix(&payload, 16).into_iter().collect(),
+        },
+        _ => Event::Dcs {
+            args,
+            initial: isize::from(initial),
+            intermediate: isize::from(intermediate),
+            command: isize::from(command),
+            payload,
+        },
+    }
+}
+
+/// Formats an unknown CSI sequence as its bytes after the introducer, such
+/// as `CSI >1;2q`. Missing arguments are left empty.
+pub(crate) fn strf_csi(
+    args: &CsiArgs,
+    initial: isize,
+    intermediate: isize,
+    command: isize,
+) -> String {
+    let mut buf = String::from("CSI ");
+    if initial != 0 {
+        buf.push(initial as u8 as char);
+    }
+    let args: Vec<String> = args
+        .as_slice()
+        .iter()
+        .map(|&arg| {
+            if arg < 0 {
+                String::new()
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect();
+    buf.push_str(&args.join(";"));
+    if intermediate != 0 {
+        buf.push(intermediate as u8 as char);
+    }
+    buf.push(command as u8 as char);
+    buf
+}
+
+/// Formats a mouse event without its modifiers. `MOUSE_POS` appends the
+/// position, marking pixel coordinates with a `px` suffix.
+pub(crate) fn strf_mouse(
+    ev: c::MouseEvent,
+    button: MouseButton,
+    line: isize,
+    col: isize,
+    pixels: bool,
+    format: c::Format,
+) -> String {
+    let &(_, evname) = MOUSE_EVENT_NAMES
+        .iter()
+        .find(|&&(event, _)| event == ev)
+        .unwrap();
+    let mut buf = format!("Mouse{}({})", evname, button);
+    if format.contains(c::Format::MOUSE_POS) {
+        buf.push_str(&format!(
+            " @ ({},{}){}",
+            col,
+            line,
+            if pixels { "px" } else { "" }
+        ));
+    }
+    buf
+}
+
+/// Parses a mouse event as formatted by `strfkey`, starting after its
+/// modifiers. Without a position, `line` and `col` are 0.
+pub(crate) fn strp_mouse(s: &str, mods: c::KeyMod) -> Option<(Event, &str)> {
+    fn number<T: ::std::str::FromStr>(s: &str) -> Option<(T, &str)> {
+        let len = s.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..len].parse().ok()?, &s[len..]))
+    }
+
+    let s = s.strip_prefix("Mouse")?;
+    let &(ev, name) = MOUSE_EVENT_NAMES
+        .iter()
+        .find(|(_, name)| s.starts_with(name))?;
+    let (button, s) = number(s[name.len()..].strip_prefix('(')?)?;
+    let mut s = s.strip_prefix(')')?;
+
+    let (mut line, mut col, mut pixels) = (0, 0, false);
+    if let Some(pos) = s.strip_prefix(" @ (") {
+        let (x, pos) = number(pos)?;
+        let (y, pos) = number(pos.strip_prefix(',')?)?;
+        let pos = pos.strip_prefix(')')?;
+        col = x;
+        line = y;
+        s = match pos.strip_prefix("px") {
+            Some(pos) => {
+                pixels = true;
+                pos
+            }
+            None => pos,
+        };
+    }
+    Some((
+        Event::Mouse {
+            ev,
+            mods,
+            button: MouseButton::new(button),
+            line,
+            col,
+            pixels,
+        },
+        s,
+    ))
+}
+
+#[cfg(not(feature = "pure-rust"))]
+impl Event {
+    /// Converts a key libtermkey decoded. Only available with the libtermkey
+    /// backend, as the `pure-rust` feature does not link libtermkey.
+    ///
+    /// Mouse, position, mode report and unknown CSI keys are interpreted
+    /// from libtermkey's buffer, so this gives `None` for one of those once
+    /// `tk` has moved on to the next key.
+    ///
     /// # Safety
-    pub unsafe fn from_c(tk: *mut c::TermKey, key: c::Key) -> Event {
-        match key.type_ {
+    ///
+    /// `tk` must be the libtermkey instance that decoded `key`.
+    pub unsafe fn from_c(tk: *mut c::TermKey, key: c::Key) -> Option<Event> {
+        Some(match key.type_ {
             c::Type::UNICODE => Event::Unicode {
-                mods: std::mem::transmute(key.modifiers),
+                mods: std::mem::transmute::<c::c_int, c::KeyMod>(key.modifiers),
                 codepoint: std::char::from_u32(key.codepoint() as u32).unwrap(),
                 utf8: Utf8Char { bytes: key.utf8 },
             },
             c::Type::FUNCTION => Event::Function {
-                mods: std::mem::transmute(key.modifiers),
+                mods: std::mem::transmute::<c::c_int, c::KeyMod>(key.modifiers),
                 num: key.num() as isize,
             },
             c::Type::KEYSYM => Event::KeySym {
-                mods: std::mem::transmute(key.modifiers),
+                mods: std::mem::transmute::<c::c_int, c::KeyMod>(key.modifiers),
                 sym: key.sym(),
             },
             c::Type::MOUSE => {
@@ -189,21 +1095,26 @@ impl Event {
                 if c::termkey_interpret_mouse(tk, &key, &mut ev, &mut button, &mut line, &mut col)
                     != c::Result::KEY
                 {
-                    panic!()
+                    return None;
                 }
+                // libtermkey only knows buttons 1 to 5, so decode the code
+                // it keeps for itself
+                let (ev, button) =
+                    decode_mouse_button(key.mouse_code() as isize, ev == c::MouseEvent::RELEASE);
                 Event::Mouse {
-                    mods: std::mem::transmute(key.modifiers),
+                    mods: std::mem::transmute::<c::c_int, c::KeyMod>(key.modifiers),
                     ev,
-                    button: button as isize,
+                    button,
                     line: line as isize,
                     col: col as isize,
+                    pixels: false,
                 }
             }
             c::Type::POSITION => {
                 let mut line: c::c_int = 0;
                 let mut col: c::c_int = 0;
                 if c::termkey_interpret_position(tk, &key, &mut line, &mut col) != c::Result::KEY {
-                    panic!()
+                    return None;
                 }
                 Event::Position {
                     line: line as isize,
@@ -217,7 +1128,7 @@ impl Event {
                 if c::termkey_interpret_modereport(tk, &key, &mut initial, &mut mode, &mut value)
                     != c::Result::KEY
                 {
-                    panic!()
+                    return None;
                 }
                 Event::ModeReport {
                     initial: initial as isize,
@@ -228,11 +1139,56 @@ impl Event {
             c::Type::UNKNOWN_CSI => {
                 // termkey 0.17 hard-codes size as 16. Oops!
                 // once termkey is fixed we should change this to a loop
-
-                // Removed, I have decided not to expose this API
-                Event::UnknownCsi
+                let mut cargs: [c::c_long; 16] = [0; 16];
+                let mut nargs: c::size_t = cargs.len() as c::size_t;
+                let mut cmd: c::c_ulong = 0;
+                if c::termkey_interpret_csi(tk, &key, &mut cargs[0], &mut nargs, &mut cmd)
+                    != c::Result::KEY
+                {
+                    return None;
+                }
+                if nargs == 0 && cmd == b'I' as c::c_ulong {
+                    return Some(Event::FocusGained);
+                }
+                if nargs == 0 && cmd == b'O' as c::c_ulong {
+                    return Some(Event::FocusLost);
+                }
+                // Replies to primary and secondary device attributes requests
+                let kind = match cmd {
+                    0x3f63 => Some(AttributesKind::Primary),
+                    0x3e63 => Some(AttributesKind::Secondary),
+                    _ => None,
+                };
+                if let Some(kind) = kind {
+                    return Some(Event::DeviceAttributes {
+                        kind,
+                        params: cargs[..nargs as usize]
+                            .iter()
+                            .map(|&arg| arg as isize)
+                            .collect(),
+                    });
+                }
+                // The reply to a kitty keyboard protocol flag query
+                if cmd == ((b'?' as c::c_ulong) << 8 | b'u' as c::c_ulong) && nargs > 0 {
+                    return Some(Event::KittyFlags {
+                        flags: kitty::Flags::from_bits_truncate(cargs[0] as c::c_int),
+                    });
+                }
+                let mut args = CsiArgs {
+                    args: [0; 16],
+                    len: nargs as usize,
+                };
+                for (arg, carg) in args.args.iter_mut().zip(cargs.iter()) {
+                    *arg = *carg as isize;
+                }
+                Event::UnknownCsi {
+                    args,
+                    initial: ((cmd >> 8) & 0xff) as isize,
+                    intermediate: ((cmd >> 16) & 0xff) as isize,
+                    command: (cmd & 0xff) as isize,
+                }
             }
-        }
+        })
     }
 }
 
@@ -243,12 +1199,26 @@ pub enum Result {
     Again,
     Error { err: ::std::io::Error },
 }
+#[cfg(not(feature = "pure-rust"))]
 impl Result {
+    /// Converts the result of one of libtermkey's `getkey` functions. Only
+    /// available with the libtermkey backend, like `Event::from_c`.
+    ///
     /// # Safety
+    ///
+    /// `tk` must be the libtermkey instance that returned `key` and `res`.
     pub unsafe fn from_c(tk: *mut c::TermKey, key: c::Key, res: c::Result) -> Result {
         match res {
             c::Result::NONE => Result::None_,
-            c::Result::KEY => Result::Key(Event::from_c(tk, key)),
+            c::Result::KEY => match Event::from_c(tk, key) {
+                Some(key) => Result::Key(key),
+                None => Result::Error {
+                    err: ::std::io::Error::new(
+                        ::std::io::ErrorKind::InvalidData,
+                        "libtermkey could not interpret the key it returned",
+                    ),
+                },
+            },
             c::Result::EOF => Result::Eof,
             c::Result::AGAIN => Result::Again,
             c::Result::ERROR => Result::Error {
@@ -260,139 +1230,79 @@ impl Result {
 
 impl TermKey {
     pub fn getkey(&mut self) -> Result {
-        let mut key: c::Key = std::default::Default::default();
-        let res = unsafe { c::termkey_getkey(self.tk, &mut key) };
-        unsafe { Result::from_c(self.tk, key, res) }
+        match self.queued.pop_front() {
+            Some(key) => Result::Key(key),
+            None => self.tk.getkey(),
+        }
     }
     pub fn getkey_force(&mut self) -> Result {
-        let mut key: c::Key = std::default::Default::default();
-        let res = unsafe { c::termkey_getkey_force(self.tk, &mut key) };
-        unsafe { Result::from_c(self.tk, key, res) }
+        match self.queued.pop_front() {
+            Some(key) => Result::Key(key),
+            None => self.tk.getkey_force(),
+        }
     }
     pub fn waitkey(&mut self) -> Result {
-        let mut key: c::Key = std::default::Default::default();
-        let res = unsafe { c::termkey_waitkey(self.tk, &mut key) };
-        unsafe { Result::from_c(self.tk, key, res) }
+        match self.queued.pop_front() {
+            Some(key) => Result::Key(key),
+            None => self.tk.waitkey(),
+        }
     }
     // will never return Key
     pub fn advisereadable(&mut self) -> Result {
-        let res = unsafe { c::termkey_advisereadable(self.tk) };
-        unsafe { Result::from_c(self.tk, std::default::Default::default(), res) }
+        self.tk.advisereadable()
     }
     pub fn push_bytes(&mut self, bytes: &[u8]) -> usize {
-        unsafe {
-            c::termkey_push_bytes(
-                self.tk,
-                std::mem::transmute(&bytes[0]),
-                bytes.len() as c::size_t,
-            ) as usize
-        }
+        self.tk.push_bytes(bytes)
     }
 }
 
 impl TermKey {
-    // Unsupported because it requires static strings (C literals)
-    // Also would require rethinking the enum nature.
-    // pub fn register_keyname(&mut self, sym: c::Sym, name: &str) -> c::Sym { }
-
-    //pub fn get_keyname(&mut self, sym: c::Sym) -> &'static str
-    //{
-    //unsafe
-    //{
-    //std::str::from_c_str(c::termkey_get_keyname(self.tk, sym))
-    //}
-    //}
+    /// Gives a name to a key with no symbol of its own, such as one only
+    /// terminfo knows of, returning its new `Sym::Custom` symbol. A name
+    /// that is already known returns the existing symbol, and one that
+    /// contains a NUL returns `Sym::UNKNOWN`.
+    ///
+    /// The numbers of custom symbols depend on the backend and on the order
+    /// names are registered in. The `pure-rust` backend starts them at
+    /// `Sym::N_SYMS`. libtermkey starts them after the names it registered
+    /// itself from terminfo, so they can differ between terminals.
+    pub fn register_keyname(&mut self, name: &str) -> c::Sym {
+        self.tk.register_keyname(name)
+    }
+
+    /// The name `strfkey` gives `sym`.
+    pub fn keyname(&mut self, sym: c::Sym) -> Option<&str> {
+        self.tk.keyname(sym)
+    }
 
     pub fn lookup_keyname<'a>(&mut self, s: &'a str, sym: &mut c::Sym) -> Option<&'a str> {
-        unsafe {
-            ::std::ffi::CString::new(s.as_bytes())
-                .ok()
-                .and_then(|cbuf| {
-                    let rbuf = c::termkey_lookup_keyname(self.tk, cbuf.as_ptr(), sym);
-                    let ci = cbuf.as_ptr() as usize;
-                    let ri = rbuf as usize;
-                    if ri != 0 {
-                        let off = ri - ci;
-                        let sbytelen = s.as_bytes().len();
-                        Some(s.get_unchecked(off..sbytelen))
-                    } else {
-                        None
-                    }
-                })
-        }
+        self.tk.lookup_keyname(s, sym)
     }
 
     pub fn keyname2sym(&mut self, keyname: &str) -> c::Sym {
-        unsafe {
-            ::std::ffi::CString::new(keyname.as_bytes())
-                .map(|name| c::termkey_keyname2sym(self.tk, name.as_ptr()))
-                .unwrap()
-        }
+        self.tk.keyname2sym(keyname)
     }
 }
 
 impl TermKey {
     pub fn strfkey(&mut self, key: Event, format: c::Format) -> String {
-        let mut buf: [c::c_char; 52] = [0; 52];
-        let mut key_ = match key {
-            Event::Unicode {
-                mods,
-                codepoint,
-                utf8,
-            } => c::Key::from_codepoint(mods, codepoint, utf8.bytes),
-            Event::Function { mods, num } => c::Key::from_num(mods, num),
-            Event::KeySym { mods, sym } => c::Key::from_sym(mods, sym),
-            Event::Mouse {
-                ev,
-                mods,
-                button,
-                line,
-                col,
-            } => c::Key::from_mouse(
-                mods,
-                ev,
-                button as c::c_int,
-                line as c::c_int,
-                col as c::c_int,
-            ),
-            Event::Position { line, col } => {
-                c::Key::from_position(line as c::c_int, col as c::c_int)
-            }
-            Event::ModeReport {
-                initial,
-                mode,
-                value,
-            } => c::Key::from_mode_report(initial as c::c_int, mode as c::c_int, value as c::c_int),
-            Event::UnknownCsi => {
-                // TODO implement
-                return "unknown csi (stringification not implemented)".to_string();
-            }
-        };
-        unsafe {
-            let sz = c::termkey_strfkey(self.tk, &mut buf[0], 52, &mut key_, format) as usize;
-            assert!(sz < 52, "key name should not be that long!");
-            std::str::from_utf8_unchecked(&*(&buf[0..sz] as *const [i8] as *const [u8])).to_string()
-        }
+        self.tk.strfkey(key, format)
     }
 
     pub fn strpkey<'a>(&mut self, s: &'a str, format: c::Format) -> Option<(Event, &'a str)> {
-        unsafe {
-            ::std::ffi::CString::new(s.as_bytes())
-                .ok()
-                .and_then(|cbuf| {
-                    let mut ckey: c::Key = std::default::Default::default();
-                    let rbuf = c::termkey_strpkey(self.tk, cbuf.as_ptr(), &mut ckey, format);
-                    let ci = cbuf.as_ptr() as usize;
-                    let ri = rbuf as usize;
-                    if ri != 0 {
-                        let key = Event::from_c(self.tk, ckey);
-                        let off = ri - ci;
-                        let sbytelen = s.as_bytes().len();
-                        Some((key, s.get_unchecked(off..sbytelen)))
-                    } else {
-                        None
-                    }
-                })
-        }
+        self.tk.strpkey(s, format)
+    }
+
+    /// Applies the canonicalisation flags to a key that was not read from
+    /// the terminal, such as one built by hand for a table of keybindings.
+    pub fn canonicalise(&self, key: Event) -> Event {
+        self.tk.canonicalise(key)
+    }
+
+    /// Compares two keys after canonicalising them, like libtermkey's
+    /// `termkey_keycmp`.
+    pub fn keycmp(&self, key1: &Event, key2: &Event) -> ::std::cmp::Ordering {
+        self.canonicalise(key1.clone())
+            .cmp(&self.canonicalise(key2.clone()))
     }
 }
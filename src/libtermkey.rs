@@ -0,0 +1,788 @@
+use std::io;
+
+use crate::c::Flag;
+use crate::decode::{self, CtrlString, Functional, StringStart, PASTE_START};
+use crate::{c, Error, Event, KeyCode, Result, Utf8Char};
+
+fn os_error(errno: c::c_int) -> Result {
+    Result::Error {
+        err: io::Error::from_raw_os_error(errno),
+    }
+}
+
+pub struct TermKey {
+    tk: *mut c::TermKey,
+    /// Bytes read but not yet handed to libtermkey, which only sees those
+    /// the wrapper does not decode itself
+    raw: Vec<u8>,
+    is_closed: bool,
+    /// The bytes of a bracketed paste received so far
+    paste: Option<Vec<u8>>,
+    paste_max: usize,
+    /// The DCS, OSC or APC string received so far
+    string: Option<CtrlString>,
+    string_max: usize,
+    mouse_pixels: bool,
+    /// Names given to `register_keyname`, which libtermkey only points at
+    keynames: Vec<(c::Sym, ::std::ffi::CString)>,
+}
+
+impl TermKey {
+    pub fn new(fd: c::c_int, flags: c::Flag) -> ::std::result::Result<TermKey, Error> {
+        unsafe {
+            c::CHECK_VERSION();
+            let tk = c::termkey_new(fd, flags.bits());
+            if tk as usize == 0 {
+                return Err(Error::InitFailed(::std::io::Error::last_os_error()));
+            }
+            Ok(TermKey {
+                tk,
+                raw: Vec::new(),
+                is_closed: false,
+                paste: None,
+                paste_max: 1 << 20,
+                string: None,
+                string_max: 1 << 20,
+                mouse_pixels: false,
+                keynames: Vec::new(),
+            })
+        }
+    }
+    pub fn new_abstract(term: &str, flags: c::Flag) -> ::std::result::Result<TermKey, Error> {
+        let c_buffer =
+            ::std::ffi::CString::new(term.as_bytes()).map_err(|_| Error::NulInTermName)?;
+        unsafe {
+            c::CHECK_VERSION();
+            let tk = c::termkey_new_abstract(c_buffer.as_ptr(), flags.bits());
+            if tk as usize == 0 {
+                return Err(Error::InitFailed(::std::io::Error::last_os_error()));
+            }
+            Ok(TermKey {
+                tk,
+                raw: Vec::new(),
+                is_closed: false,
+                paste: None,
+                paste_max: 1 << 20,
+                string: None,
+                string_max: 1 << 20,
+                mouse_pixels: false,
+                keynames: Vec::new(),
+            })
+        }
+    }
+}
+
+impl Drop for TermKey {
+    fn drop(&mut self) {
+        unsafe { c::termkey_destroy(self.tk) }
+    }
+}
+
+impl TermKey {
+    pub fn start(&mut self) -> ::std::result::Result<(), Error> {
+        unsafe {
+            if c::termkey_start(self.tk) == 0 {
+                return Err(Error::StartFailed(::std::io::Error::last_os_error()));
+            }
+        }
+        Ok(())
+    }
+    pub fn stop(&mut self) -> ::std::result::Result<(), Error> {
+        unsafe {
+            if c::termkey_stop(self.tk) == 0 {
+                return Err(Error::StopFailed(::std::io::Error::last_os_error()));
+            }
+        }
+        Ok(())
+    }
+    pub fn is_started(&self) -> bool {
+        unsafe { c::termkey_is_started(self.tk) != 0 }
+    }
+}
+
+impl TermKey {
+    pub fn get_fd(&self) -> c::c_int {
+        unsafe { c::termkey_get_fd(self.tk) }
+    }
+
+    pub fn get_flags(&self) -> c::Flag {
+        unsafe { c::Flag::from_bits_truncate(c::termkey_get_flags(self.tk)) }
+    }
+    pub fn set_flags(&mut self, newflags: c::Flag) {
+        unsafe { c::termkey_set_flags(self.tk, newflags.bits()) }
+    }
+
+    pub fn get_waittime(&self) -> isize {
+        unsafe { c::termkey_get_waittime(self.tk) as isize }
+    }
+    pub fn set_waittime(&mut self, msec: isize) {
+        unsafe { c::termkey_set_waittime(self.tk, msec as c::c_int) }
+    }
+
+    pub fn get_canonflags(&self) -> c::Canon {
+        unsafe { c::Canon::from_bits_truncate(c::termkey_get_canonflags(self.tk)) }
+    }
+    pub fn set_canonflags(&mut self, cflags: c::Canon) {
+        unsafe { c::termkey_set_canonflags(self.tk, cflags.bits()) }
+    }
+
+    pub fn get_buffer_size(&self) -> usize {
+        unsafe { c::termkey_get_buffer_size(self.tk) as usize }
+    }
+    pub fn set_buffer_size(&mut self, size: usize) -> isize {
+        unsafe { c::termkey_set_buffer_size(self.tk, size as c::size_t) as isize }
+    }
+
+    pub fn get_buffer_remaining(&self) -> usize {
+        let remaining = unsafe { c::termkey_get_buffer_remaining(self.tk) as usize };
+        remaining.saturating_sub(self.raw.len())
+    }
+
+    pub fn get_paste_max(&self) -> usize {
+        self.paste_max
+    }
+    pub fn set_paste_max(&mut self, size: usize) {
+        self.paste_max = size.max(1);
+    }
+
+    pub fn get_string_max(&self) -> usize {
+        self.string_max
+    }
+    pub fn set_string_max(&mut self, size: usize) {
+        self.string_max = size;
+    }
+
+    pub fn get_mouse_pixels(&self) -> bool {
+        self.mouse_pixels
+    }
+    pub fn set_mouse_pixels(&mut self, pixels: bool) {
+        self.mouse_pixels = pixels;
+    }
+}
+
+impl TermKey {
+    pub fn getkey(&mut self) -> Result {
+        self.getkey_(false)
+    }
+    pub fn getkey_force(&mut self) -> Result {
+        self.getkey_(true)
+    }
+
+    /// Decodes the next key from the bytes read so far, either here or by
+    /// handing them to libtermkey.
+    fn getkey_(&mut self, force: bool) -> Result {
+        if !self.is_started() {
+            return os_error(libc::EINVAL);
+        }
+        if self.paste.is_some() {
+            return self.getkey_paste();
+        }
+        if self.string.is_some() {
+            return self.getkey_string();
+        }
+        loop {
+            let buffered = unsafe { c::termkey_get_buffer_remaining(self.tk) as usize };
+            if buffered < self.get_buffer_size() {
+                // libtermkey is part way through the bytes it was given
+                match self.getkey_c(c::termkey_getkey) {
+                    Result::Again if self.feed() => continue,
+                    Result::Again if force => return self.getkey_c(c::termkey_getkey_force),
+                    res => return res,
+                }
+            }
+            if self.raw.is_empty() {
+                return if self.is_closed {
+                    Result::Eof
+                } else {
+                    Result::None_
+                };
+            }
+            // libtermkey knows nothing of bracketed paste
+            if self.raw.starts_with(PASTE_START) {
+                self.raw.drain(..PASTE_START.len());
+                self.paste = Some(Vec::new());
+                return self.getkey_paste();
+            }
+            match decode::string_start(&self.raw) {
+                StringStart::String(string, introlen) => {
+                    self.raw.drain(..introlen);
+                    self.string = Some(string);
+                    return self.getkey_string();
+                }
+                // Until the header arrives it might be Alt with a key
+                StringStart::Partial if !force => return Result::Again,
+                _ => {}
+            }
+            if let Some(res) = self.peekkey_raw(force) {
+                return res;
+            }
+            self.feed();
+        }
+    }
+
+    /// Moves read bytes into the paste until its end marker turns up.
+    fn getkey_paste(&mut self) -> Result {
+        let mut paste = self.paste.take().unwrap_or_default();
+        let utf8 = self.get_flags().contains(Flag::UTF8);
+        match decode::fill_paste(
+            &mut paste,
+            &mut self.raw,
+            self.paste_max,
+            utf8,
+            self.is_closed,
+        ) {
+            Some((text, more)) => {
+                if more {
+                    self.paste = Some(paste);
+                }
+                Result::Key(Event::Paste { text, more })
+            }
+            None => {
+                self.paste = Some(paste);
+                Result::None_
+            }
+        }
+    }
+
+    /// Moves read bytes into the string until its terminator turns up.
+    fn getkey_string(&mut self) -> Result {
+        let mut string = self.string.take().unwrap();
+        if !string.fill(&mut self.raw, self.string_max, self.is_closed) {
+            self.string = Some(string);
+            return Result::None_;
+        }
+        let text = decode::text(string.bytes, self.get_flags().contains(Flag::UTF8));
+        Result::Key(crate::ctrl_string(string.kind, text))
+    }
+
+    fn getkey_c(
+        &mut self,
+        getkey: unsafe extern "C" fn(*mut c::TermKey, *mut c::Key) -> c::Result,
+    ) -> Result {
+        let mut key: c::Key = std::default::Default::default();
+        let res = unsafe { getkey(self.tk, &mut key) };
+        unsafe { Result::from_c(self.tk, key, res) }
+    }
+
+    /// Hands libtermkey the bytes at the front of `raw`, up to the next one
+    /// that could start a sequence the wrapper decodes itself. Returns
+    /// whether there were any and libtermkey had room for them.
+    fn feed(&mut self) -> bool {
+        let len = self
+            .raw
+            .iter()
+            .skip(1)
+            .position(|&b| matches!(b, 0x1b | 0x90 | 0x9b | 0x9d | 0x9f))
+            .map_or(self.raw.len(), |pos| pos + 1);
+        if len == 0 {
+            return false;
+        }
+        let pushed = unsafe {
+            c::termkey_push_bytes(
+                self.tk,
+                self.raw.as_ptr() as *const c::c_char,
+                len as c::size_t,
+            ) as usize
+        };
+        if pushed == 0 || pushed == usize::MAX {
+            return false;
+        }
+        self.raw.drain(..pushed);
+        true
+    }
+
+    /// Decodes the sequence at the front of `raw` if libtermkey would get it
+    /// wrong: SGR mouse reports, whose coordinates it clamps, and the kitty
+    /// keyboard protocol's reports. `None` leaves the bytes to libtermkey.
+    fn peekkey_raw(&mut self, force: bool) -> Option<Result> {
+        let introlen = match self.raw[..] {
+            // Whatever follows might make it one
+            [0x1b] if !force => return Some(Result::Again),
+            [0x1b, b'[', ..] => 2,
+            [0x9b, ..] => 1,
+            _ => return None,
+        };
+        let csi = match decode::parse_csi(&self.raw, introlen) {
+            Some(csi) => csi,
+            None if force => return None,
+            None => return Some(Result::Again),
+        };
+        let key = match csi.cmd {
+            0x3c4d | 0x3c6d => decode::csi_mouse(&csi, self.mouse_pixels)?,
+            0x41..=0x46 | 0x48 | 0x50..=0x53 | 0x75 | 0x7e => self.kitty_report(&csi)?,
+            _ => return None,
+        };
+        self.raw.drain(..csi.len);
+        Some(Result::Key(key))
+    }
+
+    /// libtermkey drops the sub-parameters of the kitty keyboard protocol,
+    /// and the text argument and functional key codes of `CSI u`. Reports
+    /// using any of those are decoded here, leaving libtermkey to look up
+    /// only the plain key.
+    fn kitty_report(&mut self, csi: &decode::Csi) -> Option<Event> {
+        let plain = csi.sub.iter().all(Vec::is_empty);
+        if csi.cmd == 0x75 {
+            if csi.nargs == 0 || csi.args[0] < 0 {
+                return None;
+            }
+            let functional = decode::kitty_functional(csi.args[0]);
+            if plain && csi.nargs <= 2 && functional.is_none() {
+                return None;
+            }
+            let key = match functional {
+                Some(Functional::Key(info)) => info.event(c::KeyMod::empty()),
+                Some(Functional::Keypad(cmd)) => self.plain_key(&[0x1b, b'O', cmd])?,
+                None => self.plain_key(format!("\x1b[{}u", csi.args[0]).as_bytes())?,
+            };
+            return Some(decode::csi_u_event(csi, key));
+        }
+        if plain {
+            return None;
+        }
+        let key = match csi.cmd as u8 {
+            b'~' => self.plain_key(format!("\x1b[{}~", csi.args[0]).as_bytes())?,
+            cmd => self.plain_key(&[0x1b, b'[', cmd])?,
+        };
+        Some(decode::kitty_key(
+            decode::add_mods(key, csi.mods()),
+            csi.key_event(),
+            None,
+            None,
+            String::new(),
+        ))
+    }
+
+    /// Has libtermkey decode `bytes`, which must be the whole of a key
+    /// without modifiers, while it has nothing else buffered.
+    fn plain_key(&mut self, bytes: &[u8]) -> Option<Event> {
+        unsafe {
+            c::termkey_push_bytes(
+                self.tk,
+                bytes.as_ptr() as *const c::c_char,
+                bytes.len() as c::size_t,
+            );
+        }
+        match self.getkey_c(c::termkey_getkey_force) {
+            Result::Key(
+                key @ (Event::Unicode { .. } | Event::Function { .. } | Event::KeySym { .. }),
+            ) => Some(key),
+            _ => None,
+        }
+    }
+
+    pub fn waitkey(&mut self) -> Result {
+        let fd = self.get_fd();
+        if fd == -1 {
+            return os_error(libc::EBADF);
+        }
+        loop {
+            match self.getkey() {
+                Result::None_ => {
+                    if let Result::Error { err } = self.advisereadable() {
+                        return Result::Error { err };
+                    }
+                }
+                Result::Again => {
+                    if self.is_closed {
+                        // We're closed now. Never going to get more bytes so just go with what we have
+                        return self.getkey_force();
+                    }
+                    let mut pollfd = libc::pollfd {
+                        fd,
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    let ready = loop {
+                        let ret =
+                            unsafe { libc::poll(&mut pollfd, 1, self.get_waittime() as c::c_int) };
+                        if ret != -1 {
+                            break ret;
+                        }
+                        let err = io::Error::last_os_error();
+                        if err.raw_os_error() != Some(libc::EINTR)
+                            || self.get_flags().contains(Flag::EINTR)
+                        {
+                            return Result::Error { err };
+                        }
+                    };
+                    if ready == 0 {
+                        return self.getkey_force();
+                    }
+                    if pollfd.revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+                        if let Result::Error { err } = self.advisereadable() {
+                            return Result::Error { err };
+                        }
+                    }
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Reads into `raw` rather than letting libtermkey read, so the wrapper
+    /// sees the bytes first. Never returns a key.
+    pub fn advisereadable(&mut self) -> Result {
+        let fd = self.get_fd();
+        if fd == -1 {
+            return os_error(libc::EBADF);
+        }
+        let room = self.get_buffer_remaining();
+        if room == 0 {
+            return os_error(libc::ENOMEM);
+        }
+        let mut chunk = vec![0u8; room];
+        loop {
+            let len = unsafe { libc::read(fd, chunk.as_mut_ptr() as *mut libc::c_void, room) };
+            if len == -1 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EAGAIN) => return Result::None_,
+                    Some(libc::EINTR) if !self.get_flags().contains(Flag::EINTR) => continue,
+                    _ => return Result::Error { err },
+                }
+            } else if len < 1 {
+                self.is_closed = true;
+                return Result::None_;
+            } else {
+                self.raw.extend_from_slice(&chunk[..len as usize]);
+                return Result::Again;
+            }
+        }
+    }
+
+    /// Returns `usize::MAX` if the buffer is already full.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        let room = self.get_buffer_remaining();
+        if room == 0 {
+            return usize::MAX;
+        }
+        let len = bytes.len().min(room);
+        self.raw.extend_from_slice(&bytes[..len]);
+        len
+    }
+}
+
+/// Takes the modifiers libtermkey does not know off a key, and returns
+/// them apart from it.
+fn split_mods(key: Event) -> (c::KeyMod, Event) {
+    let known = c::KeyMod::SHIFT | c::KeyMod::ALT | c::KeyMod::CTRL;
+    match key {
+        Event::Unicode {
+            codepoint,
+            mods,
+            utf8,
+        } => (
+            mods - known,
+            Event::Unicode {
+                codepoint,
+                mods: mods & known,
+                utf8,
+            },
+        ),
+        Event::Function { num, mods } => (
+            mods - known,
+            Event::Function {
+                num,
+                mods: mods & known,
+            },
+        ),
+        Event::KeySym { sym, mods } => (
+            mods - known,
+            Event::KeySym {
+                sym,
+                mods: mods & known,
+            },
+        ),
+        Event::KittyKey {
+            code,
+            mods,
+            event,
+            shifted,
+            base,
+            text,
+        } => (
+            mods - known,
+            Event::KittyKey {
+                code,
+                mods: mods & known,
+                event,
+                shifted,
+                base,
+                text,
+            },
+        ),
+        key => (c::KeyMod::empty(), key),
+    }
+}
+
+impl TermKey {
+    pub fn register_keyname(&mut self, name: &str) -> c::Sym {
+        let name = match ::std::ffi::CString::new(name.as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return c::Sym::UNKNOWN,
+        };
+        let known = unsafe { c::termkey_keyname2sym(self.tk, name.as_ptr()) };
+        if known != c::Sym::UNKNOWN.to_c() {
+            return c::Sym::from_c(known);
+        }
+        let sym =
+            unsafe { c::termkey_register_keyname(self.tk, c::Sym::NONE.to_c(), name.as_ptr()) };
+        let sym = c::Sym::from_c(sym);
+        if sym != c::Sym::UNKNOWN {
+            self.keynames.push((sym, name));
+        }
+        sym
+    }
+
+    pub fn keyname(&self, sym: c::Sym) -> Option<&str> {
+        if let Some((_, name)) = self.keynames.iter().find(|&&(custom, _)| custom == sym) {
+            return name.to_str().ok();
+        }
+        // Including the custom symbols libtermkey registered from terminfo
+        let name = unsafe {
+            let name = c::termkey_get_keyname(self.tk, sym.to_c());
+            if name.is_null() {
+                return None;
+            }
+            ::std::ffi::CStr::from_ptr(name).to_str().ok()?
+        };
+        // What libtermkey names any symbol it has no name for
+        if name == "UNKNOWN" {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    pub fn lookup_keyname<'a>(&mut self, s: &'a str, sym: &mut c::Sym) -> Option<&'a str> {
+        unsafe {
+            ::std::ffi::CString::new(s.as_bytes())
+                .ok()
+                .and_then(|cbuf| {
+                    let mut csym: c::c_int = 0;
+                    let rbuf = c::termkey_lookup_keyname(self.tk, cbuf.as_ptr(), &mut csym);
+                    let ci = cbuf.as_ptr() as usize;
+                    let ri = rbuf as usize;
+                    if ri != 0 {
+                        *sym = c::Sym::from_c(csym);
+                        let off = ri - ci;
+                        let sbytelen = s.len();
+                        Some(s.get_unchecked(off..sbytelen))
+                    } else {
+                        None
+                    }
+                })
+        }
+    }
+
+    pub fn keyname2sym(&mut self, keyname: &str) -> c::Sym {
+        match ::std::ffi::CString::new(keyname.as_bytes()) {
+            Ok(name) => unsafe { c::Sym::from_c(c::termkey_keyname2sym(self.tk, name.as_ptr())) },
+            // No key has a name with a NUL in it
+            Err(_) => c::Sym::UNKNOWN,
+        }
+    }
+}
+
+impl TermKey {
+    pub fn canonicalise(&self, key: Event) -> Event {
+        let mut key_ = match key {
+            Event::Unicode {
+                mods,
+                codepoint,
+                utf8,
+            } => c::Key::from_codepoint(mods, codepoint, utf8.bytes),
+            Event::KeySym { mods, sym } => c::Key::from_sym(mods, sym),
+            // libtermkey only canonicalises spaces and DEL
+            _ => return key,
+        };
+        unsafe {
+            c::termkey_canonicalise(self.tk, &mut key_);
+            Event::from_c(self.tk, key_).unwrap_or(key)
+        }
+    }
+
+    pub fn strfkey(&mut self, key: Event, format: c::Format) -> String {
+        if let Some(name) = crate::strf_focus(&key, format) {
+            return name;
+        }
+        if let Event::KittyKey {
+            code,
+            mods,
+            event: event @ (c::KeyEvent::REPEAT | c::KeyEvent::RELEASE),
+            shifted,
+            base,
+            ref text,
+        } = key
+        {
+            // libtermkey has no notion of the event, so it names the press
+            let press = Event::KittyKey {
+                code,
+                mods,
+                event: c::KeyEvent::PRESS,
+                shifted,
+                base,
+                text: text.clone(),
+            };
+            let name = self.strfkey(press.clone(), format - c::Format::WRAPBRACKET);
+            let suffix = crate::strf_key_event(event, format);
+            return if self.strfkey(press, format) == name {
+                format!("{}{}", name, suffix)
+            } else {
+                format!("<{}{}>", name, suffix)
+            };
+        }
+        if let Event::Mouse {
+            ev,
+            mods,
+            button,
+            line,
+            col,
+            pixels,
+        } = key
+        {
+            // libtermkey only knows buttons 1 to 5 and clamps wide positions,
+            // so it is only left the modifiers, put in front of a plain key
+            let plain = Event::Unicode {
+                mods,
+                codepoint: 'a',
+                utf8: Utf8Char::new('a'),
+            };
+            let mut name = self.strfkey(
+                plain,
+                format - c::Format::CARETCTRL - c::Format::WRAPBRACKET,
+            );
+            name.pop();
+            name.push_str(&crate::strf_mouse(ev, button, line, col, pixels, format));
+            if format.contains(c::Format::WRAPBRACKET) {
+                name = format!("<{}>", name);
+            }
+            return name;
+        }
+        let (unknown, key) = split_mods(key);
+        let extra = crate::strf_extra_mods(unknown, format);
+        if !extra.is_empty() {
+            // libtermkey would take them for a bare key
+            let mut name = extra;
+            name.push_str(
+                &self.strfkey(key, format - c::Format::CARETCTRL - c::Format::WRAPBRACKET),
+            );
+            if format.contains(c::Format::WRAPBRACKET) {
+                name = format!("<{}>", name);
+            }
+            return name;
+        }
+        let mut buf: [c::c_char; 52] = [0; 52];
+        let mut key_ = match key {
+            Event::Unicode {
+                mods,
+                codepoint,
+                utf8,
+            } => c::Key::from_codepoint(mods, codepoint, utf8.bytes),
+            Event::Function { mods, num } => c::Key::from_num(mods, num),
+            Event::KeySym { mods, sym } => c::Key::from_sym(mods, sym),
+            Event::Position { line, col } => {
+                c::Key::from_position(line as c::c_int, col as c::c_int)
+            }
+            Event::ModeReport {
+                initial,
+                mode,
+                value,
+            } => c::Key::from_mode_report(initial as c::c_int, mode as c::c_int, value as c::c_int),
+            Event::KittyKey { code, mods, .. } => match code {
+                KeyCode::Unicode(codepoint) => {
+                    c::Key::from_codepoint(mods, codepoint, Utf8Char::new(codepoint).bytes)
+                }
+                KeyCode::Function(num) => c::Key::from_num(mods, num),
+                KeyCode::KeySym(sym) => c::Key::from_sym(mods, sym),
+            },
+            Event::KittyFlags { flags } => return format!("KittyFlags({})", flags.bits()),
+            Event::Paste { .. } => return "Paste".to_string(),
+            Event::DeviceAttributes { .. } => return "DeviceAttributes".to_string(),
+            Event::TerminalVersion(_) => return "TerminalVersion".to_string(),
+            Event::Osc { .. } => return "Osc".to_string(),
+            Event::Dcs { .. } => return "Dcs".to_string(),
+            Event::Apc { .. } => return "Apc".to_string(),
+            Event::UnknownCsi {
+                args,
+                initial,
+                intermediate,
+                command,
+            } => {
+                let name = crate::strf_csi(&args, initial, intermediate, command);
+                return if format.contains(c::Format::WRAPBRACKET) {
+                    format!("<{}>", name)
+                } else {
+                    name
+                };
+            }
+            Event::Mouse { .. } | Event::FocusGained | Event::FocusLost => unreachable!(),
+        };
+        unsafe {
+            let sz = c::termkey_strfkey(self.tk, &mut buf[0], 52, &mut key_, format) as usize;
+            assert!(sz < 52, "key name should not be that long!");
+            std::str::from_utf8_unchecked(&*(&buf[0..sz] as *const [i8] as *const [u8])).to_string()
+        }
+    }
+
+    pub fn strpkey<'a>(&mut self, s: &'a str, format: c::Format) -> Option<(Event, &'a str)> {
+        if let Some(parsed) = crate::strp_focus(s, format) {
+            return Some(parsed);
+        }
+        let (extra, rest) = crate::strp_extra_mods(s, format);
+        if !extra.is_empty() {
+            return match self.strpkey(rest, format)? {
+                (
+                    key @ (Event::Unicode { .. }
+                    | Event::Function { .. }
+                    | Event::KeySym { .. }
+                    | Event::Mouse { .. }
+                    | Event::KittyKey { .. }),
+                    rest,
+                ) => Some((decode::add_mods(key, extra), rest)),
+                _ => None,
+            };
+        }
+        if let Some(parsed) = self.strp_mouse(s, format) {
+            return Some(parsed);
+        }
+        unsafe {
+            ::std::ffi::CString::new(s.as_bytes())
+                .ok()
+                .and_then(|cbuf| {
+                    let mut ckey: c::Key = std::default::Default::default();
+                    let rbuf = c::termkey_strpkey(self.tk, cbuf.as_ptr(), &mut ckey, format);
+                    let ci = cbuf.as_ptr() as usize;
+                    let ri = rbuf as usize;
+                    if ri != 0 {
+                        let key = Event::from_c(self.tk, ckey)?;
+                        let off = ri - ci;
+                        let sbytelen = s.len();
+                        Some(crate::strp_key_event(
+                            key,
+                            s.get_unchecked(off..sbytelen),
+                            format,
+                        ))
+                    } else {
+                        None
+                    }
+                })
+        }
+    }
+
+    /// libtermkey cannot parse mouse events, so only the modifiers in front
+    /// of one are left to it, by following them with a plain key.
+    fn strp_mouse<'a>(&mut self, s: &'a str, format: c::Format) -> Option<(Event, &'a str)> {
+        let at = s.find("Mouse")?;
+        let mods = if at == 0 {
+            c::KeyMod::empty()
+        } else {
+            match self.strpkey(&format!("{}a", &s[..at]), format)? {
+                (Event::Unicode { mods, .. }, "") => mods,
+                _ => return None,
+            }
+        };
+        crate::strp_mouse(&s[at..], mods)
+    }
+}
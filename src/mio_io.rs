@@ -0,0 +1,120 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+
+use crate::{Event, Result, TermKey};
+
+/// Registers the fd from `get_fd`. As with any `SourceFd`, the fd must be
+/// in non-blocking mode, because `KeyDrain` reads until `EAGAIN`.
+impl Source for TermKey {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
+/// Reads keys out of a `TermKey` registered with mio, and keeps track of
+/// when an ambiguous prefix (such as a lone Escape) has to be forced out
+/// with `getkey_force`.
+///
+/// Call `readable` when the `TermKey`'s token is reported readable, pass
+/// `timeout` to `Poll::poll`, and call `check_timeout` every time `poll`
+/// returns. The deadline is absolute, so events on other sources do not
+/// postpone it.
+#[derive(Default)]
+pub struct KeyDrain {
+    deadline: Option<Instant>,
+}
+
+impl KeyDrain {
+    pub fn new() -> KeyDrain {
+        KeyDrain { deadline: None }
+    }
+
+    /// The instant at which `check_timeout` will force out a pending key.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+    /// The time left until the deadline, suitable for `Poll::poll`.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Reads everything available on the fd, appending the decoded keys to
+    /// `keys`. Returns `Ok(false)` once the fd has reached end of file.
+    pub fn readable(&mut self, tk: &mut TermKey, keys: &mut Vec<Event>) -> io::Result<bool> {
+        loop {
+            match tk.advisereadable() {
+                // new bytes; drain them before the buffer fills up
+                Result::Again => {
+                    if !self.drain(tk, keys)? {
+                        return Ok(false);
+                    }
+                }
+                Result::Error { err } => return Err(err),
+                // EAGAIN, or end of file which the next getkey reports
+                _ => return self.drain(tk, keys),
+            }
+        }
+    }
+
+    /// Forces out the pending key if the deadline has passed, appending it
+    /// to `keys`. Returns `Ok(false)` once the fd has reached end of file.
+    pub fn check_timeout(&mut self, tk: &mut TermKey, keys: &mut Vec<Event>) -> io::Result<bool> {
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => {}
+            _ => return Ok(true),
+        }
+        self.deadline = None;
+        match tk.getkey_force() {
+            Result::Key(key) => keys.push(key),
+            Result::Error { err } => return Err(err),
+            _ => {}
+        }
+        self.drain(tk, keys)
+    }
+
+    fn drain(&mut self, tk: &mut TermKey, keys: &mut Vec<Event>) -> io::Result<bool> {
+        loop {
+            match tk.getkey() {
+                Result::Key(key) => {
+                    self.deadline = None;
+                    keys.push(key);
+                }
+                Result::Again => {
+                    if self.deadline.is_none() {
+                        let waittime = tk.get_waittime().max(0) as u64;
+                        self.deadline = Some(Instant::now() + Duration::from_millis(waittime));
+                    }
+                    return Ok(true);
+                }
+                Result::None_ => {
+                    self.deadline = None;
+                    return Ok(true);
+                }
+                Result::Eof => return Ok(false),
+                Result::Error { err } => return Err(err),
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::convert::TryFrom;
+
+use crate::c::{Flag, KeyMod, Sym};
+use crate::decode::{
+    add_mods, csi_u_event, kitty_functional, kitty_key, mouse_event, parse_csi, Csi, Functional,
+    KeyInfo,
+};
+use crate::{kitty, AttributesKind, CsiArgs, Event, Result};
+
+use super::{unicode, TermKey};
+
+/// Final bytes shared by CSI and SS3, with the modifiers they always carry.
+fn csi_ss3(cmd: u8) -> Option<(KeyInfo, KeyMod)> {
+    let key = match cmd {
+        b'A' => KeyInfo::Sym(Sym::UP),
+        b'B' => KeyInfo::Sym(Sym::DOWN),
+        b'C' => KeyInfo::Sym(Sym::RIGHT),
+        b'D' => KeyInfo::Sym(Sym::LEFT),
+        b'E' => KeyInfo::Sym(Sym::BEGIN),
+        b'F' => KeyInfo::Sym(Sym::END),
+        b'H' => KeyInfo::Sym(Sym::HOME),
+        b'P' => KeyInfo::Function(1),
+        b'Q' => KeyInfo::Function(2),
+        b'R' => KeyInfo::Function(3),
+        b'S' => KeyInfo::Function(4),
+        b'Z' => return Some((KeyInfo::Sym(Sym::TAB), KeyMod::SHIFT)),
+        _ => return None,
+    };
+    Some((key, KeyMod::empty()))
+}
+
+/// SS3 keypad keys, with the character `CONVERTKP` turns them into.
+fn ss3_keypad(cmd: u8) -> Option<(Sym, Option<char>)> {
+    Some(match cmd {
+        b'M' => (Sym::KPENTER, None),
+        b'X' => (Sym::KPEQUALS, Some('=')),
+        b'j' => (Sym::KPMULT, Some('*')),
+        b'k' => (Sym::KPPLUS, Some('+')),
+        b'l' => (Sym::KPCOMMA, Some(',')),
+        b'm' => (Sym::KPMINUS, Some('-')),
+        b'n' => (Sym::KPPERIOD, Some('.')),
+        b'o' => (Sym::KPDIV, Some('/')),
+        b'p' => (Sym::KP0, Some('0')),
+        b'q' => (Sym::KP1, Some('1')),
+        b'r' => (Sym::KP2, Some('2')),
+        b's' => (Sym::KP3, Some('3')),
+        b't' => (Sym::KP4, Some('4')),
+        b'u' => (Sym::KP5, Some('5')),
+        b'v' => (Sym::KP6, Some('6')),
+        b'w' => (Sym::KP7, Some('7')),
+        b'x' => (Sym::KP8, Some('8')),
+        b'y' => (Sym::KP9, Some('9')),
+        _ => return None,
+    })
+}
+
+/// The keys reported as `CSI number ~`.
+fn csifunc(num: isize) -> Option<KeyInfo> {
+    Some(match num {
+        1 => KeyInfo::Sym(Sym::FIND),
+        2 => KeyInfo::Sym(Sym::INSERT),
+        3 => KeyInfo::Sym(Sym::DELETE),
+        4 => KeyInfo::Sym(Sym::SELECT),
+        5 => KeyInfo::Sym(Sym::PAGEUP),
+        6 => KeyInfo::Sym(Sym::PAGEDOWN),
+        7 => KeyInfo::Sym(Sym::HOME),
+        8 => KeyInfo::Sym(Sym::END),
+        11..=15 => KeyInfo::Function(num - 10),
+        17..=21 => KeyInfo::Function(num - 11),
+        23..=26 => KeyInfo::Function(num - 12),
+        28 | 29 => KeyInfo::Function(num - 13),
+        31..=34 => KeyInfo::Function(num - 14),
+        _ => return None,
+    })
+}
+
+pub fn peekkey(tk: &TermKey, buf: &[u8], force: bool, nbytes: &mut usize) -> Result {
+    match buf {
+        [] if tk.is_closed => Result::Eof,
+        [0x1b, b'[', ..] => peekkey_csi(tk, buf, 2, force, nbytes),
+        [0x1b, b'O', ..] => peekkey_ss3(tk, buf, 2, force, nbytes),
+        [0x8f, ..] => peekkey_ss3(tk, buf, 1, force, nbytes),
+        [0x9b, ..] => peekkey_csi(tk, buf, 1, force, nbytes),
+        _ => Result::None_,
+    }
+}
+
+fn peekkey_csi(
+    tk: &TermKey,
+    buf: &[u8],
+    introlen: usize,
+    force: bool,
+    nbytes: &mut usize,
+) -> Result {
+    let csi = match parse_csi(buf, introlen) {
+        Some(csi) => csi,
+        None if !force => return Result::Again,
+        None => {
+            // There must be at least one byte after the introducer for a
+            // CSI; treat what we have as Alt-[
+            *nbytes = introlen;
+            return Result::Key(add_mods(tk.emit_codepoint(u32::from(b'[')), KeyMod::ALT));
+        }
+    };
+
+    if csi.cmd == u32::from(b'M') && csi.nargs < 3 {
+        // Mouse in X10 encoding consumes the next 3 bytes also
+        let res = peekkey_mouse(&buf[csi.len..], nbytes);
+        if let Result::Key(_) = res {
+            *nbytes += csi.len;
+        }
+        return res;
+    }
+
+    let key = match csi.cmd & 0xff {
+        0x7e => handle_csifunc(tk, &csi),
+        _ => match csi.cmd {
+            0x75 => handle_csi_u(tk, &csi),
+            0x4d | 0x6d | 0x3c4d | 0x3c6d => crate::decode::csi_mouse(&csi, tk.mouse_pixels),
+            0x49 if csi.nargs == 0 => Some(Event::FocusGained),
+            0x4f if csi.nargs == 0 => Some(Event::FocusLost),
+            0x3f52 => handle_csi_position(&csi),
+            0x3f75 => handle_kitty_flags(&csi),
+            0x24_0079 | 0x24_3f79 => handle_csi_mode_report(&csi),
+            0x3f63 => handle_device_attributes(&csi, AttributesKind::Primary),
+            0x3e63 => handle_device_attributes(&csi, AttributesKind::Secondary),
+            cmd if cmd < 0x80 => csi_ss3(cmd as u8).map(|(info, set)| {
+                let key = info.event(csi.mods() | set);
+                kitty_key(key, csi.key_event(), None, None, String::new())
+            }),
+            _ => None,
+        },
+    };
+
+    *nbytes = csi.len;
+    Result::Key(key.unwrap_or(Event::UnknownCsi {
+        args: CsiArgs {
+            args: csi.args,
+            len: csi.nargs,
+        },
+        initial: ((csi.cmd >> 8) & 0xff) as isize,
+        intermediate: ((csi.cmd >> 16) & 0xff) as isize,
+        command: (csi.cmd & 0xff) as isize,
+    }))
+}
+
+fn peekkey_ss3(
+    tk: &TermKey,
+    buf: &[u8],
+    introlen: usize,
+    force: bool,
+    nbytes: &mut usize,
+) -> Result {
+    let cmd = match buf.get(introlen) {
+        Some(&cmd) => cmd,
+        None if !force => return Result::Again,
+        None => {
+            // There must be at least one byte after the introducer for an
+            // SS3; treat what we have as Alt-O
+            *nbytes = buf.len();
+            return Result::Key(add_mods(tk.emit_codepoint(u32::from(b'O')), KeyMod::ALT));
+        }
+    };
+
+    let key = if let Some((info, set)) = csi_ss3(cmd) {
+        info.event(set)
+    } else if let Some(key) = keypad_event(tk, cmd) {
+        key
+    } else {
+        return Result::None_;
+    };
+
+    *nbytes = introlen + 1;
+    Result::Key(key)
+}
+
+fn keypad_event(tk: &TermKey, cmd: u8) -> Option<Event> {
+    let (sym, kpalt) = ss3_keypad(cmd)?;
+    Some(match kpalt {
+        Some(ch) if tk.flags.contains(Flag::CONVERTKP) => unicode(ch as u32, KeyMod::empty()),
+        _ => Event::KeySym {
+            sym,
+            mods: KeyMod::empty(),
+        },
+    })
+}
+
+fn peekkey_mouse(buf: &[u8], nbytes: &mut usize) -> Result {
+    if buf.len() < 3 {
+        return Result::Again;
+    }
+    let code = isize::from(buf[0].wrapping_sub(0x20));
+    let col = isize::from(buf[1].wrapping_sub(0x20));
+    let line = isize::from(buf[2].wrapping_sub(0x20));
+    *nbytes = 3;
+    Result::Key(mouse_event(code, false, line, col, false))
+}
+
+fn handle_csifunc(tk: &TermKey, csi: &Csi) -> Option<Event> {
+    let mods = csi.mods();
+    if csi.nargs > 2 && csi.args[0] == 27 {
+        // xterm's modifyOtherKeys: CSI 27 ; mods ; codepoint ~
+        let codepoint = u32::try_from(csi.args[2]).ok()?;
+        return Some(add_mods(tk.emit_codepoint(codepoint), mods));
+    }
+    if csi.nargs == 0 {
+        return None;
+    }
+    csifunc(csi.args[0])
+        .map(|info| kitty_key(info.event(mods), csi.key_event(), None, None, String::new()))
+}
+
+/// Handler for CSI u extended Unicode keys, including the kitty keyboard
+/// protocol's `CSI code:shifted:base ; mods:event ; text u`
+fn handle_csi_u(tk: &TermKey, csi: &Csi) -> Option<Event> {
+    if csi.nargs == 0 {
+        return None;
+    }
+    let key = match kitty_functional(csi.args[0]) {
+        Some(Functional::Key(info)) => info.event(KeyMod::empty()),
+        Some(Functional::Keypad(cmd)) => keypad_event(tk, cmd)?,
+        None => tk.emit_codepoint(u32::try_from(csi.args[0]).ok()?),
+    };
+    Some(csi_u_event(csi, key))
+}
+
+fn handle_kitty_flags(csi: &Csi) -> Option<Event> {
+    if csi.nargs == 0 {
+        return None;
+    }
+    Some(Event::KittyFlags {
+        flags: kitty::Flags::from_bits_truncate(csi.args[0] as libc::c_int),
+    })
+}
+
+fn handle_csi_position(csi: &Csi) -> Option<Event> {
+    if csi.nargs < 2 {
+        return None;
+    }
+    Some(Event::Position {
+        line: csi.args[0],
+        col: csi.args[1],
+    })
+}
+
+fn handle_csi_mode_report(csi: &Csi) -> Option<Event> {
+    if csi.nargs < 2 {
+        return None;
+    }
+    Some(Event::ModeReport {
+        initial: ((csi.cmd >> 8) & 0xff) as isize,
+        mode: csi.args[0],
+        value: csi.args[1],
+    })
+}
+
+fn handle_device_attributes(csi: &Csi, kind: AttributesKind) -> Option<Event> {
+    Some(Event::DeviceAttributes {
+        kind,
+        params: csi.args[..csi.nargs].to_vec(),
+    })
+}
@@ -0,0 +1,368 @@
+use crate::c::{Format, KeyEvent, KeyMod, Sym, KEYNAMES};
+use crate::{Event, KeyCode};
+
+use super::unicode;
+
+struct ModNames {
+    shift: &'static str,
+    alt: &'static str,
+    ctrl: &'static str,
+}
+
+// Indexed by LONGMOD, ALTISMETA and LOWERMOD
+static MODNAMES: [ModNames; 8] = [
+    ModNames {
+        shift: "S",
+        alt: "A",
+        ctrl: "C",
+    },
+    ModNames {
+        shift: "Shift",
+        alt: "Alt",
+        ctrl: "Ctrl",
+    },
+    ModNames {
+        shift: "S",
+        alt: "M",
+        ctrl: "C",
+    },
+    ModNames {
+        shift: "Shift",
+        alt: "Meta",
+        ctrl: "Ctrl",
+    },
+    ModNames {
+        shift: "s",
+        alt: "a",
+        ctrl: "c",
+    },
+    ModNames {
+        shift: "shift",
+        alt: "alt",
+        ctrl: "ctrl",
+    },
+    ModNames {
+        shift: "s",
+        alt: "m",
+        ctrl: "c",
+    },
+    ModNames {
+        shift: "shift",
+        alt: "meta",
+        ctrl: "ctrl",
+    },
+];
+
+const PRINTED_MODS: KeyMod = KeyMod::from_bits_truncate(
+    KeyMod::SHIFT.bits()
+        | KeyMod::ALT.bits()
+        | KeyMod::CTRL.bits()
+        | KeyMod::SUPER.bits()
+        | KeyMod::HYPER.bits()
+        | KeyMod::META.bits(),
+);
+
+fn modnames(format: Format) -> &'static ModNames {
+    let mut index = 0;
+    if format.contains(Format::LONGMOD) {
+        index |= 1;
+    }
+    if format.contains(Format::ALTISMETA) {
+        index |= 2;
+    }
+    if format.contains(Format::LOWERMOD) {
+        index |= 4;
+    }
+    &MODNAMES[index]
+}
+
+/// "PageDown" becomes "page down"
+fn camel_to_spaces(name: &str) -> String {
+    let mut spaced = String::with_capacity(name.len() + 2);
+    let mut prev_lower = false;
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() && prev_lower {
+            spaced.push(' ');
+        }
+        prev_lower = ch.is_ascii_lowercase();
+        spaced.push(ch.to_ascii_lowercase());
+    }
+    spaced
+}
+
+/// The symbol `register_keyname` gives the `index`th name registered.
+pub fn custom_sym(index: usize) -> Sym {
+    Sym::Custom((Sym::N_SYMS.to_c() as usize + index) as u32)
+}
+
+/// `keynames` are the names registered so far, in order.
+pub fn get_keyname(sym: Sym, keynames: &[String]) -> Option<&str> {
+    if let Sym::Custom(custom) = Sym::from_c(sym.to_c()) {
+        let index = (custom as usize).checked_sub(Sym::N_SYMS.to_c() as usize)?;
+        return keynames.get(index).map(String::as_str);
+    }
+    KEYNAMES
+        .iter()
+        .find(|&&(s, _)| s == sym)
+        .map(|&(_, name)| name)
+}
+
+/// Finds the longest key name `s` starts with.
+pub fn lookup_keyname<'a>(
+    s: &'a str,
+    format: Format,
+    keynames: &[String],
+) -> Option<(Sym, &'a str)> {
+    let custom = keynames
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (custom_sym(index), name.as_str()));
+    let mut best: Option<(Sym, &str)> = None;
+    for (sym, name) in KEYNAMES.iter().copied().chain(custom) {
+        let rest = if format.contains(Format::LOWERSPACE) {
+            s.strip_prefix(camel_to_spaces(name).as_str())
+        } else {
+            s.strip_prefix(name)
+        };
+        if let Some(rest) = rest {
+            if best
+                .iter()
+                .all(|&(_, best_rest)| rest.len() < best_rest.len())
+            {
+                best = Some((sym, rest));
+            }
+        }
+    }
+    best
+}
+
+fn event_mods(key: &Event) -> KeyMod {
+    match *key {
+        Event::Unicode { mods, .. }
+        | Event::Function { mods, .. }
+        | Event::KeySym { mods, .. }
+        | Event::Mouse { mods, .. }
+        | Event::KittyKey { mods, .. } => mods,
+        _ => KeyMod::empty(),
+    }
+}
+
+pub fn strfkey(key: Event, format: Format, keynames: &[String]) -> String {
+    match key {
+        Event::Paste { .. } => return "Paste".to_string(),
+        Event::DeviceAttributes { .. } => return "DeviceAttributes".to_string(),
+        Event::TerminalVersion(_) => return "TerminalVersion".to_string(),
+        Event::Osc { .. } => return "Osc".to_string(),
+        Event::Dcs { .. } => return "Dcs".to_string(),
+        Event::Apc { .. } => return "Apc".to_string(),
+        _ => {}
+    }
+    if let Some(name) = crate::strf_focus(&key, format) {
+        return name;
+    }
+
+    let names = modnames(format);
+    let mods = event_mods(&key);
+    let is_unicode = matches!(
+        key,
+        Event::Unicode { .. }
+            | Event::KittyKey {
+                code: KeyCode::Unicode(_),
+                ..
+            }
+    );
+    let wrapbracket =
+        format.contains(Format::WRAPBRACKET) && (!is_unicode || mods.intersects(PRINTED_MODS));
+    let sep = if format.contains(Format::SPACEMOD) {
+        ' '
+    } else {
+        '-'
+    };
+
+    if format.contains(Format::CARETCTRL) {
+        if let Event::Unicode {
+            codepoint,
+            mods: KeyMod::CTRL,
+            ..
+        } = key
+        {
+            let caret = match codepoint {
+                'a'..='z' => Some(codepoint.to_ascii_uppercase()),
+                '@' | '['..='_' => Some(codepoint),
+                _ => None,
+            };
+            if let Some(caret) = caret {
+                return if wrapbracket {
+                    format!("<^{}>", caret)
+                } else {
+                    format!("^{}", caret)
+                };
+            }
+        }
+    }
+
+    let mut buf = String::new();
+    if wrapbracket {
+        buf.push('<');
+    }
+    buf.push_str(&crate::strf_extra_mods(mods, format));
+    if mods.contains(KeyMod::ALT) {
+        buf.push_str(names.alt);
+        buf.push(sep);
+    }
+    if mods.contains(KeyMod::CTRL) {
+        buf.push_str(names.ctrl);
+        buf.push(sep);
+    }
+    if mods.contains(KeyMod::SHIFT) {
+        buf.push_str(names.shift);
+        buf.push(sep);
+    }
+
+    let event = match key {
+        Event::KittyKey { event, .. } => event,
+        _ => KeyEvent::PRESS,
+    };
+    let key = match key {
+        Event::KittyKey { code, mods, .. } => match code {
+            KeyCode::Unicode(codepoint) => unicode(codepoint as u32, mods),
+            KeyCode::Function(num) => Event::Function { num, mods },
+            KeyCode::KeySym(sym) => Event::KeySym { sym, mods },
+        },
+        key => key,
+    };
+    match key {
+        Event::Unicode { codepoint, .. } => buf.push(codepoint),
+        Event::KeySym { sym, .. } => {
+            let name = get_keyname(sym, keynames).unwrap_or("UNKNOWN");
+            if format.contains(Format::LOWERSPACE) {
+                buf.push_str(&camel_to_spaces(name));
+            } else {
+                buf.push_str(name);
+            }
+        }
+        Event::Function { num, .. } => {
+            buf.push(if format.contains(Format::LOWERSPACE) {
+                'f'
+            } else {
+                'F'
+            });
+            buf.push_str(&num.to_string());
+        }
+        Event::Mouse {
+            ev,
+            button,
+            line,
+            col,
+            pixels,
+            ..
+        } => buf.push_str(&crate::strf_mouse(ev, button, line, col, pixels, format)),
+        Event::Position { .. } => buf.push_str("Position"),
+        Event::ModeReport {
+            initial,
+            mode,
+            value,
+        } => {
+            if initial != 0 {
+                buf.push_str(&format!(
+                    "Mode({}{}={})",
+                    initial as u8 as char, mode, value
+                ));
+            } else {
+                buf.push_str(&format!("Mode({}={})", mode, value));
+            }
+        }
+        Event::KittyFlags { flags } => buf.push_str(&format!("KittyFlags({})", flags.bits())),
+        Event::UnknownCsi {
+            args,
+            initial,
+            intermediate,
+            command,
+        } => buf.push_str(&crate::strf_csi(&args, initial, intermediate, command)),
+        Event::KittyKey { .. }
+        | Event::Paste { .. }
+        | Event::DeviceAttributes { .. }
+        | Event::TerminalVersion(_)
+        | Event::Osc { .. }
+        | Event::Dcs { .. }
+        | Event::Apc { .. }
+        | Event::FocusGained
+        | Event::FocusLost => unreachable!(),
+    }
+    buf.push_str(crate::strf_key_event(event, format));
+
+    if wrapbracket {
+        buf.push('>');
+    }
+    buf
+}
+
+/// Parses "F" (or "f" with `LOWERSPACE`) followed by a number.
+fn parse_function(s: &str, format: Format) -> Option<(isize, &str)> {
+    let digits = s.strip_prefix('F').or_else(|| {
+        s.strip_prefix('f')
+            .filter(|_| format.contains(Format::LOWERSPACE))
+    })?;
+    let len = digits
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let num = digits[..len].parse().ok()?;
+    Some((num, &digits[len..]))
+}
+
+/// The key is returned before canonicalisation, which needs the instance's
+/// flags.
+pub fn strpkey<'a>(s: &'a str, format: Format, keynames: &[String]) -> Option<(Event, &'a str)> {
+    if let Some(parsed) = crate::strp_focus(s, format) {
+        return Some(parsed);
+    }
+    if format.contains(Format::CARETCTRL) && s.len() > 1 {
+        if let Some(caret) = s.strip_prefix('^') {
+            let (key, rest) = strpkey(caret, format - Format::CARETCTRL, keynames)?;
+            return match key {
+                Event::Unicode {
+                    codepoint, mods, ..
+                } if mods.is_empty() && ('@'..='_').contains(&codepoint) => Some((
+                    unicode(codepoint.to_ascii_lowercase() as u32, KeyMod::CTRL),
+                    rest,
+                )),
+                _ => None,
+            };
+        }
+    }
+
+    let names = modnames(format);
+    let sep = if format.contains(Format::SPACEMOD) {
+        ' '
+    } else {
+        '-'
+    };
+
+    let (mut mods, mut s) = crate::strp_extra_mods(s, format);
+    while let Some(n) = s.find(sep) {
+        let name = &s[..n];
+        if name == names.alt {
+            mods |= KeyMod::ALT;
+        } else if name == names.ctrl {
+            mods |= KeyMod::CTRL;
+        } else if name == names.shift {
+            mods |= KeyMod::SHIFT;
+        } else {
+            break;
+        }
+        s = &s[n + 1..];
+    }
+
+    let (key, rest) = if let Some(parsed) = crate::strp_mouse(s, mods) {
+        parsed
+    } else if let Some((sym, rest)) = lookup_keyname(s, format, keynames) {
+        (Event::KeySym { sym, mods }, rest)
+    } else if let Some((num, rest)) = parse_function(s, format) {
+        (Event::Function { num, mods }, rest)
+    } else {
+        // Unicode must be last
+        let codepoint = s.chars().next()?;
+        (unicode(codepoint as u32, mods), &s[codepoint.len_utf8()..])
+    };
+    Some(crate::strp_key_event(key, rest, format))
+}
@@ -0,0 +1,647 @@
+//! A port of the core of libtermkey 0.17 and its CSI driver. terminfo is not
+//! consulted, so only the sequences understood by the CSI driver are
+//! recognised; in practice that covers every xterm-alike.
+
+mod csi;
+mod format;
+
+use std::io;
+
+use crate::c::{Canon, Flag, KeyMod, Sym};
+use crate::decode::{self, CtrlString, StringStart, PASTE_START};
+use crate::{c, Error, Event, Result, Utf8Char};
+
+const UTF8_INVALID: u32 = 0xfffd;
+
+// libc does not expose _POSIX_VDISABLE
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const VDISABLE: libc::cc_t = 0;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const VDISABLE: libc::cc_t = 0xff;
+
+pub struct TermKey {
+    fd: c::c_int,
+    flags: Flag,
+    canonflags: Canon,
+    buffer: Vec<u8>,
+    buffsize: usize,
+    waittime: isize,
+    is_closed: bool,
+    is_started: bool,
+    restore_termios: Option<libc::termios>,
+    /// The bytes of a bracketed paste received so far
+    paste: Option<Vec<u8>>,
+    paste_max: usize,
+    /// The DCS, OSC or APC string received so far
+    string: Option<CtrlString>,
+    string_max: usize,
+    mouse_pixels: bool,
+    /// Names given to `register_keyname`, in order
+    keynames: Vec<String>,
+}
+
+fn os_error(errno: c::c_int) -> Result {
+    Result::Error {
+        err: io::Error::from_raw_os_error(errno),
+    }
+}
+
+fn locale_is_utf8() -> bool {
+    let locale = ["LANG", "LC_MESSAGES", "LC_ALL"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .find(|value| !value.is_empty());
+    let locale = match locale {
+        Some(locale) => locale.to_string_lossy().into_owned(),
+        None => return false,
+    };
+    match locale.rfind('.') {
+        Some(dot) => {
+            let charset = &locale[dot + 1..];
+            charset.eq_ignore_ascii_case("UTF-8") || charset.eq_ignore_ascii_case("UTF8")
+        }
+        None => false,
+    }
+}
+
+/// Builds a `Unicode` event, substituting U+FFFD for anything that is not a
+/// valid scalar value.
+fn unicode(codepoint: u32, mods: KeyMod) -> Event {
+    let codepoint = std::char::from_u32(codepoint).unwrap_or('\u{fffd}');
+    Event::Unicode {
+        codepoint,
+        mods,
+        utf8: Utf8Char::new(codepoint),
+    }
+}
+
+/// The length of the shortest UTF-8 encoding of `codepoint`.
+fn utf8_seqlen(codepoint: u32) -> usize {
+    match codepoint {
+        0..=0x7f => 1,
+        0x80..=0x7ff => 2,
+        0x800..=0xffff => 3,
+        0x10000..=0x1f_ffff => 4,
+        0x20_0000..=0x3ff_ffff => 5,
+        _ => 6,
+    }
+}
+
+impl TermKey {
+    pub fn new(fd: c::c_int, flags: Flag) -> ::std::result::Result<TermKey, Error> {
+        let mut tk = TermKey::init(fd, flags);
+        tk.start_termios().map_err(Error::InitFailed)?;
+        Ok(tk)
+    }
+    /// There is no terminfo support, so `term` is only checked for
+    /// compatibility with the libtermkey backend.
+    pub fn new_abstract(term: &str, flags: Flag) -> ::std::result::Result<TermKey, Error> {
+        if term.contains('\0') {
+            return Err(Error::NulInTermName);
+        }
+        let mut tk = TermKey::init(-1, flags);
+        tk.start_termios().map_err(Error::InitFailed)?;
+        Ok(tk)
+    }
+
+    fn init(fd: c::c_int, mut flags: Flag) -> TermKey {
+        // Without explicit flags, guess UTF-8 or raw from the locale
+        if !flags.intersects(Flag::RAW | Flag::UTF8) {
+            flags |= if locale_is_utf8() {
+                Flag::UTF8
+            } else {
+                Flag::RAW
+            };
+        }
+        let mut tk = TermKey {
+            fd,
+            flags: Flag::empty(),
+            canonflags: Canon::empty(),
+            buffer: Vec::new(),
+            buffsize: 256,
+            waittime: 50,
+            is_closed: false,
+            is_started: false,
+            restore_termios: None,
+            paste: None,
+            paste_max: 1 << 20,
+            string: None,
+            string_max: 1 << 20,
+            mouse_pixels: false,
+            keynames: Vec::new(),
+        };
+        tk.set_flags(flags);
+        tk
+    }
+
+    fn start_termios(&mut self) -> io::Result<()> {
+        if self.is_started {
+            return Ok(());
+        }
+        if self.fd != -1 && !self.flags.contains(Flag::NOTERMIOS) {
+            let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(self.fd, &mut termios) } == 0 {
+                self.restore_termios = Some(termios);
+
+                termios.c_iflag &= !(libc::IXON | libc::INLCR | libc::ICRNL);
+                termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+                termios.c_cc[libc::VMIN] = 1;
+                termios.c_cc[libc::VTIME] = 0;
+
+                if self.flags.contains(Flag::CTRLC) {
+                    // want no signal keys at all, so just disable ISIG
+                    termios.c_lflag &= !libc::ISIG;
+                } else {
+                    // Disable Ctrl-\==VQUIT and Ctrl-D==VSUSP but leave Ctrl-C as SIGINT
+                    termios.c_cc[libc::VQUIT] = VDISABLE;
+                    termios.c_cc[libc::VSUSP] = VDISABLE;
+                }
+
+                if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &termios) } == -1 {
+                    self.restore_termios = None;
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        self.is_started = true;
+        Ok(())
+    }
+}
+
+impl Drop for TermKey {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl TermKey {
+    pub fn start(&mut self) -> ::std::result::Result<(), Error> {
+        self.start_termios().map_err(Error::StartFailed)
+    }
+    pub fn stop(&mut self) -> ::std::result::Result<(), Error> {
+        if !self.is_started {
+            return Ok(());
+        }
+        if let Some(termios) = self.restore_termios.take() {
+            if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &termios) } == -1 {
+                return Err(Error::StopFailed(io::Error::last_os_error()));
+            }
+        }
+        self.is_started = false;
+        Ok(())
+    }
+    pub fn is_started(&self) -> bool {
+        self.is_started
+    }
+}
+
+impl TermKey {
+    pub fn get_fd(&self) -> c::c_int {
+        self.fd
+    }
+
+    pub fn get_flags(&self) -> Flag {
+        self.flags
+    }
+    pub fn set_flags(&mut self, newflags: Flag) {
+        self.flags = newflags;
+        if newflags.contains(Flag::SPACESYMBOL) {
+            self.canonflags |= Canon::SPACESYMBOL;
+        } else {
+            self.canonflags &= !Canon::SPACESYMBOL;
+        }
+    }
+
+    pub fn get_waittime(&self) -> isize {
+        self.waittime
+    }
+    pub fn set_waittime(&mut self, msec: isize) {
+        self.waittime = msec;
+    }
+
+    pub fn get_canonflags(&self) -> Canon {
+        self.canonflags
+    }
+    pub fn set_canonflags(&mut self, cflags: Canon) {
+        self.canonflags = cflags;
+        if cflags.contains(Canon::SPACESYMBOL) {
+            self.flags |= Flag::SPACESYMBOL;
+        } else {
+            self.flags &= !Flag::SPACESYMBOL;
+        }
+    }
+
+    pub fn get_buffer_size(&self) -> usize {
+        self.buffsize
+    }
+    /// Returns 0 if the bytes already buffered would not fit, 1 otherwise.
+    pub fn set_buffer_size(&mut self, size: usize) -> isize {
+        if size < self.buffer.len() {
+            return 0;
+        }
+        self.buffsize = size;
+        1
+    }
+
+    pub fn get_buffer_remaining(&self) -> usize {
+        self.buffsize - self.buffer.len()
+    }
+
+    pub fn get_paste_max(&self) -> usize {
+        self.paste_max
+    }
+    pub fn set_paste_max(&mut self, size: usize) {
+        self.paste_max = size.max(1);
+    }
+
+    pub fn get_string_max(&self) -> usize {
+        self.string_max
+    }
+    pub fn set_string_max(&mut self, size: usize) {
+        self.string_max = size;
+    }
+
+    pub fn get_mouse_pixels(&self) -> bool {
+        self.mouse_pixels
+    }
+    pub fn set_mouse_pixels(&mut self, pixels: bool) {
+        self.mouse_pixels = pixels;
+    }
+}
+
+impl TermKey {
+    pub fn getkey(&mut self) -> Result {
+        self.getkey_(false)
+    }
+    pub fn getkey_force(&mut self) -> Result {
+        self.getkey_(true)
+    }
+    fn getkey_(&mut self, force: bool) -> Result {
+        if self.paste.is_none() && self.string.is_none() && self.is_started {
+            if self.buffer.starts_with(PASTE_START) {
+                self.buffer.drain(..PASTE_START.len());
+                self.paste = Some(Vec::new());
+            } else {
+                match decode::string_start(&self.buffer) {
+                    StringStart::String(string, introlen) => {
+                        self.buffer.drain(..introlen);
+                        self.string = Some(string);
+                    }
+                    // Until the header arrives it might be Alt with a key
+                    StringStart::Partial if !force => return Result::Again,
+                    _ => {}
+                }
+            }
+        }
+        if self.paste.is_some() {
+            return self.getkey_paste();
+        }
+        if self.string.is_some() {
+            return self.getkey_string();
+        }
+
+        let mut nbytes = 0;
+        let res = self.peekkey(&self.buffer, force, &mut nbytes);
+        if let Result::Key(_) = res {
+            let nbytes = nbytes.min(self.buffer.len());
+            self.buffer.drain(..nbytes);
+        }
+        res
+    }
+
+    /// Moves buffered bytes into the paste until its end marker turns up.
+    /// Until then there is no key to report, so more bytes are needed.
+    fn getkey_paste(&mut self) -> Result {
+        let mut paste = self.paste.take().unwrap_or_default();
+        let utf8 = self.flags.contains(Flag::UTF8);
+        match decode::fill_paste(
+            &mut paste,
+            &mut self.buffer,
+            self.paste_max,
+            utf8,
+            self.is_closed,
+        ) {
+            Some((text, more)) => {
+                if more {
+                    self.paste = Some(paste);
+                }
+                Result::Key(Event::Paste { text, more })
+            }
+            None => {
+                self.paste = Some(paste);
+                Result::None_
+            }
+        }
+    }
+
+    /// Moves buffered bytes into the string until its terminator turns up.
+    fn getkey_string(&mut self) -> Result {
+        let mut string = self.string.take().unwrap();
+        if !string.fill(&mut self.buffer, self.string_max, self.is_closed) {
+            self.string = Some(string);
+            return Result::None_;
+        }
+        let text = decode::text(string.bytes, self.flags.contains(Flag::UTF8));
+        Result::Key(crate::ctrl_string(string.kind, text))
+    }
+
+    pub fn waitkey(&mut self) -> Result {
+        if self.fd == -1 {
+            return os_error(libc::EBADF);
+        }
+        loop {
+            match self.getkey() {
+                Result::None_ => {
+                    if let Result::Error { err } = self.advisereadable() {
+                        return Result::Error { err };
+                    }
+                }
+                Result::Again => {
+                    if self.is_closed {
+                        // We're closed now. Never going to get more bytes so just go with what we have
+                        return self.getkey_force();
+                    }
+                    let mut fd = libc::pollfd {
+                        fd: self.fd,
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    let ready = loop {
+                        let ret = unsafe { libc::poll(&mut fd, 1, self.waittime as c::c_int) };
+                        if ret != -1 {
+                            break ret;
+                        }
+                        let err = io::Error::last_os_error();
+                        if err.raw_os_error() != Some(libc::EINTR)
+                            || self.flags.contains(Flag::EINTR)
+                        {
+                            return Result::Error { err };
+                        }
+                    };
+                    if ready == 0 {
+                        return self.getkey_force();
+                    }
+                    if fd.revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+                        if let Result::Error { err } = self.advisereadable() {
+                            return Result::Error { err };
+                        }
+                    }
+                }
+                res => return res,
+            }
+        }
+    }
+
+    // will never return Key
+    pub fn advisereadable(&mut self) -> Result {
+        if self.fd == -1 {
+            return os_error(libc::EBADF);
+        }
+        if self.buffer.len() >= self.buffsize {
+            return os_error(libc::ENOMEM);
+        }
+        let mut chunk = vec![0u8; self.buffsize - self.buffer.len()];
+        loop {
+            let len = unsafe {
+                libc::read(
+                    self.fd,
+                    chunk.as_mut_ptr() as *mut libc::c_void,
+                    chunk.len(),
+                )
+            };
+            if len == -1 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EAGAIN) => return Result::None_,
+                    Some(libc::EINTR) if !self.flags.contains(Flag::EINTR) => continue,
+                    _ => return Result::Error { err },
+                }
+            } else if len < 1 {
+                self.is_closed = true;
+                return Result::None_;
+            } else {
+                self.buffer.extend_from_slice(&chunk[..len as usize]);
+                return Result::Again;
+            }
+        }
+    }
+
+    /// Returns `usize::MAX` if the buffer is already full.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        if self.buffer.len() >= self.buffsize {
+            return usize::MAX;
+        }
+        let len = bytes.len().min(self.buffsize - self.buffer.len());
+        self.buffer.extend_from_slice(&bytes[..len]);
+        len
+    }
+}
+
+impl TermKey {
+    fn peekkey(&self, buf: &[u8], force: bool, nbytes: &mut usize) -> Result {
+        if !self.is_started {
+            return os_error(libc::EINVAL);
+        }
+        match csi::peekkey(self, buf, force, nbytes) {
+            Result::None_ => {}
+            // The CSI driver wants more bytes; only fall back to the simple
+            // decoding below if it was told not to wait for them
+            Result::Again if !force => return Result::Again,
+            Result::Again => {}
+            res => return res,
+        }
+        self.peekkey_simple(buf, force, nbytes)
+    }
+
+    fn peekkey_simple(&self, buf: &[u8], force: bool, nbytes: &mut usize) -> Result {
+        let b0 = match buf.first() {
+            Some(&b0) => b0,
+            None if self.is_closed => return Result::Eof,
+            None => return Result::None_,
+        };
+
+        if b0 == 0x1b {
+            // Escape-prefixed value? Might therefore be Alt+key
+            if buf.len() == 1 {
+                // This might be an <Esc> press, or it may want to be part of a
+                // longer sequence
+                if !force {
+                    return Result::Again;
+                }
+                *nbytes = 1;
+                return Result::Key(self.emit_codepoint(0x1b));
+            }
+
+            let res = self.peekkey(&buf[1..], force, nbytes);
+            if let Result::Key(key) = res {
+                *nbytes += 1;
+                return Result::Key(decode::add_mods(key, KeyMod::ALT));
+            }
+            res
+        } else if b0 < 0xa0 {
+            // Single byte C0, G0 or C1 - C1 is never UTF-8 initial byte
+            *nbytes = 1;
+            Result::Key(self.emit_codepoint(u32::from(b0)))
+        } else if self.flags.contains(Flag::UTF8) {
+            self.peekkey_utf8(buf, force, nbytes)
+        } else {
+            // Non UTF-8 case - just report the raw byte
+            *nbytes = 1;
+            Result::Key(unicode(u32::from(b0), KeyMod::empty()))
+        }
+    }
+
+    fn peekkey_utf8(&self, buf: &[u8], force: bool, nbytes: &mut usize) -> Result {
+        let b0 = buf[0];
+        let (nbytes_need, mut codepoint) = match b0 {
+            0xc0..=0xdf => (2, u32::from(b0 & 0x1f)),
+            0xe0..=0xef => (3, u32::from(b0 & 0x0f)),
+            0xf0..=0xf7 => (4, u32::from(b0 & 0x07)),
+            0xf8..=0xfb => (5, u32::from(b0 & 0x03)),
+            0xfc..=0xfd => (6, u32::from(b0 & 0x01)),
+            // Starts with a continuation byte, or is 0xfe or 0xff
+            _ => (1, UTF8_INVALID),
+        };
+
+        for (b, &cb) in buf.iter().enumerate().take(nbytes_need).skip(1) {
+            if !(0x80..0xc0).contains(&cb) {
+                *nbytes = b;
+                return Result::Key(self.emit_codepoint(UTF8_INVALID));
+            }
+            codepoint = codepoint << 6 | u32::from(cb & 0x3f);
+        }
+
+        if buf.len() < nbytes_need {
+            if !force {
+                return Result::Again;
+            }
+            // There weren't enough bytes for a complete UTF-8 sequence but
+            // caller demands an answer. About the best thing we can do here
+            // is eat as many bytes as we have, and emit an invalid marker
+            *nbytes = buf.len();
+            return Result::Key(self.emit_codepoint(UTF8_INVALID));
+        }
+
+        // Check for overlong sequences, UTF-16 surrogates and invalid
+        // codepoints
+        if nbytes_need > utf8_seqlen(codepoint)
+            || (0xd800..=0xdfff).contains(&codepoint)
+            || codepoint == 0xfffe
+            || codepoint == 0xffff
+        {
+            codepoint = UTF8_INVALID;
+        }
+
+        *nbytes = nbytes_need;
+        Result::Key(self.emit_codepoint(codepoint))
+    }
+
+    fn emit_codepoint(&self, codepoint: u32) -> Event {
+        let key = if codepoint == 0 {
+            // ASCII NUL = Ctrl-Space
+            Event::KeySym {
+                sym: Sym::SPACE,
+                mods: KeyMod::CTRL,
+            }
+        } else if codepoint < 0x20 {
+            // C0 range
+            let sym = match codepoint {
+                0x09 => Some(Sym::TAB),
+                0x0d => Some(Sym::ENTER),
+                0x1b => Some(Sym::ESCAPE),
+                _ => None,
+            };
+            match sym {
+                Some(sym) if !self.flags.contains(Flag::NOINTERPRET) => Event::KeySym {
+                    sym,
+                    mods: KeyMod::empty(),
+                },
+                // Ctrl-letters are reported lowercase, other C0 controls
+                // as their G0 counterpart
+                _ if (0x01..=0x1a).contains(&codepoint) => unicode(codepoint + 0x60, KeyMod::CTRL),
+                _ => unicode(codepoint + 0x40, KeyMod::CTRL),
+            }
+        } else if codepoint == 0x7f && !self.flags.contains(Flag::NOINTERPRET) {
+            // ASCII DEL
+            Event::KeySym {
+                sym: Sym::DEL,
+                mods: KeyMod::empty(),
+            }
+        } else if (0x80..0xa0).contains(&codepoint) {
+            // C1 range
+            unicode(codepoint - 0x40, KeyMod::CTRL | KeyMod::ALT)
+        } else {
+            unicode(codepoint, KeyMod::empty())
+        };
+        self.canonicalise(key)
+    }
+
+    pub fn canonicalise(&self, key: Event) -> Event {
+        let flags = self.canonflags;
+        match key {
+            Event::Unicode {
+                codepoint: ' ',
+                mods,
+                ..
+            } if flags.contains(Canon::SPACESYMBOL) => Event::KeySym {
+                sym: Sym::SPACE,
+                mods,
+            },
+            Event::KeySym {
+                sym: Sym::SPACE,
+                mods,
+            } if !flags.contains(Canon::SPACESYMBOL) => unicode(0x20, mods),
+            Event::KeySym {
+                sym: Sym::DEL,
+                mods,
+            } if flags.contains(Canon::DELBS) => Event::KeySym {
+                sym: Sym::BACKSPACE,
+                mods,
+            },
+            key => key,
+        }
+    }
+}
+
+impl TermKey {
+    pub fn register_keyname(&mut self, name: &str) -> Sym {
+        if name.contains('\0') {
+            return Sym::UNKNOWN;
+        }
+        match self.keyname2sym(name) {
+            Sym::UNKNOWN => {
+                self.keynames.push(name.to_string());
+                format::custom_sym(self.keynames.len() - 1)
+            }
+            known => known,
+        }
+    }
+
+    pub fn keyname(&self, sym: Sym) -> Option<&str> {
+        format::get_keyname(sym, &self.keynames)
+    }
+
+    pub fn lookup_keyname<'a>(&self, s: &'a str, sym: &mut Sym) -> Option<&'a str> {
+        format::lookup_keyname(s, c::Format::empty(), &self.keynames).map(|(found, rest)| {
+            *sym = found;
+            rest
+        })
+    }
+
+    pub fn keyname2sym(&self, keyname: &str) -> Sym {
+        match format::lookup_keyname(keyname, c::Format::empty(), &self.keynames) {
+            Some((sym, "")) => sym,
+            _ => Sym::UNKNOWN,
+        }
+    }
+}
+
+impl TermKey {
+    pub fn strfkey(&self, key: Event, format: c::Format) -> String {
+        format::strfkey(key, format, &self.keynames)
+    }
+
+    pub fn strpkey<'a>(&self, s: &'a str, format: c::Format) -> Option<(Event, &'a str)> {
+        format::strpkey(s, format, &self.keynames).map(|(key, rest)| (self.canonicalise(key), rest))
+    }
+}
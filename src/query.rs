@@ -0,0 +1,362 @@
+//! Requests that terminals answer with a report among the keys, and
+//! `TermKey::query` to send one and wait for its answer.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::c::{self, Flag};
+use crate::{AttributesKind, Event, Result, TermKey};
+
+/// A request, and what answers it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Query {
+    /// DECXCPR, answered by `Event::Position`. Plain DSR 6 is not used, as
+    /// its answer cannot be told from F3 with modifiers.
+    CursorPosition,
+    /// DECRQM for an ANSI mode, answered by `Event::ModeReport` with an
+    /// `initial` of 0.
+    AnsiMode(u32),
+    /// DECRQM for a DEC private mode, answered by `Event::ModeReport` with
+    /// an `initial` of `'?'`.
+    DecMode(u32),
+    /// Primary device attributes (DA1), answered by `Event::DeviceAttributes`.
+    PrimaryDeviceAttributes,
+    /// Secondary device attributes (DA2), answered by
+    /// `Event::DeviceAttributes`.
+    SecondaryDeviceAttributes,
+    /// Tertiary device attributes (DA3), answered by
+    /// `Event::DeviceAttributes`.
+    TertiaryDeviceAttributes,
+    /// XTVERSION, answered by `Event::TerminalVersion`.
+    TerminalVersion,
+    /// The default foreground colour (OSC 10), answered by `Event::Osc`.
+    ForegroundColour,
+    /// The default background colour (OSC 11), answered by `Event::Osc`.
+    BackgroundColour,
+    /// A colour of the 256-colour palette (OSC 4), answered by `Event::Osc`.
+    PaletteColour(u8),
+    /// The contents of a selection (OSC 52), answered by `Event::Osc`.
+    /// Terminals often refuse this, and then do not answer at all.
+    Clipboard(Selection),
+}
+
+/// Which selection `Query::Clipboard` reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The clipboard, filled by explicit copying.
+    Clipboard,
+    /// The primary selection, filled by selecting text.
+    Primary,
+}
+
+impl Selection {
+    /// The letter OSC 52 names the selection with.
+    fn letter(self) -> &'static str {
+        match self {
+            Selection::Clipboard => "c",
+            Selection::Primary => "p",
+        }
+    }
+}
+
+impl Query {
+    /// The escape sequence to write to the terminal.
+    pub fn request(self) -> String {
+        match self {
+            Query::CursorPosition => "\x1b[?6n".to_string(),
+            Query::AnsiMode(mode) => format!("\x1b[{}$p", mode),
+            Query::DecMode(mode) => format!("\x1b[?{}$p", mode),
+            Query::PrimaryDeviceAttributes => "\x1b[c".to_string(),
+            Query::SecondaryDeviceAttributes => "\x1b[>c".to_string(),
+            Query::TertiaryDeviceAttributes => "\x1b[=c".to_string(),
+            Query::TerminalVersion => "\x1b[>q".to_string(),
+            Query::ForegroundColour => "\x1b]10;?\x1b\\".to_string(),
+            Query::BackgroundColour => "\x1b]11;?\x1b\\".to_string(),
+            Query::PaletteColour(index) => format!("\x1b]4;{};?\x1b\\", index),
+            Query::Clipboard(selection) => format!("\x1b]52;{};?\x1b\\", selection.letter()),
+        }
+    }
+
+    /// Whether `key` is the answer to this request.
+    pub fn is_reply(self, key: &Event) -> bool {
+        match (self, key) {
+            (Query::CursorPosition, &Event::Position { .. }) => true,
+            (
+                Query::AnsiMode(mode),
+                &Event::ModeReport {
+                    initial, mode: got, ..
+                },
+            ) => initial == 0 && got == mode as isize,
+            (
+                Query::DecMode(mode),
+                &Event::ModeReport {
+                    initial, mode: got, ..
+                },
+            ) => initial == '?' as isize && got == mode as isize,
+            (Query::PrimaryDeviceAttributes, &Event::DeviceAttributes { kind, .. }) => {
+                kind == AttributesKind::Primary
+            }
+            (Query::SecondaryDeviceAttributes, &Event::DeviceAttributes { kind, .. }) => {
+                kind == AttributesKind::Secondary
+            }
+            (Query::TertiaryDeviceAttributes, &Event::DeviceAttributes { kind, .. }) => {
+                kind == AttributesKind::Tertiary
+            }
+            (Query::TerminalVersion, &Event::TerminalVersion(_)) => true,
+            (Query::ForegroundColour, &Event::Osc { number, .. }) => number == 10,
+            (Query::BackgroundColour, &Event::Osc { number, .. }) => number == 11,
+            (
+                Query::PaletteColour(index),
+                &Event::Osc {
+                    number,
+                    ref payload,
+                },
+            ) => {
+                number == 4 && payload.split(';').next().and_then(|n| n.parse().ok()) == Some(index)
+            }
+            (
+                Query::Clipboard(selection),
+                &Event::Osc {
+                    number,
+                    ref payload,
+                },
+            ) => number == 52 && payload.split(';').next() == Some(selection.letter()),
+            _ => false,
+        }
+    }
+
+    /// The colour in `key` if it is the answer to this request, which must
+    /// be one of the colour queries.
+    pub fn colour(self, key: &Event) -> Option<Rgb> {
+        if !self.is_reply(key) {
+            return None;
+        }
+        match (self, key) {
+            (Query::PaletteColour(_), Event::Osc { payload, .. }) => {
+                Rgb::parse(payload.split_once(';')?.1)
+            }
+            (_, Event::Osc { payload, .. }) => Rgb::parse(payload),
+            _ => None,
+        }
+    }
+
+    /// The contents of the selection in `key` if it is the answer to this
+    /// request, which must be `Clipboard`. They are bytes as the terminal
+    /// sends them, usually UTF-8.
+    pub fn clipboard(self, key: &Event) -> Option<Vec<u8>> {
+        match (self, key) {
+            (Query::Clipboard(_), Event::Osc { payload, .. }) if self.is_reply(key) => {
+                base64_decode(payload.split_once(';')?.1)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Decodes standard base64, with or without padding.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for b in text.trim_end_matches('=').bytes() {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = acc << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    // a single character left over cannot make up a byte
+    if bits >= 6 {
+        return None;
+    }
+    Some(bytes)
+}
+
+/// A colour as terminals report it, with 16 bits to each channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+}
+
+impl Rgb {
+    /// Parses an X11 colour specification like `rgb:ffff/8000/0000`, as
+    /// terminals answer colour queries with. Each channel has from one to
+    /// four hex digits, and is scaled to 16 bits. The alpha channel of
+    /// `rgba:` is ignored.
+    pub fn parse(spec: &str) -> Option<Rgb> {
+        let (channels, count) = if let Some(channels) = spec.strip_prefix("rgb:") {
+            (channels, 3)
+        } else if let Some(channels) = spec.strip_prefix("rgba:") {
+            (channels, 4)
+        } else {
+            return None;
+        };
+        let mut values = [0u16; 4];
+        let mut parts = channels.split('/');
+        for value in &mut values[..count] {
+            *value = channel(parts.next()?)?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Rgb {
+            red: values[0],
+            green: values[1],
+            blue: values[2],
+        })
+    }
+}
+
+fn channel(hex: &str) -> Option<u16> {
+    if hex.is_empty() || hex.len() > 4 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let max = (1u32 << (4 * hex.len())) - 1;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(((value * 0xffff + max / 2) / max) as u16)
+}
+
+impl TermKey {
+    /// The fd `query` writes requests to, or -1 if there is none.
+    pub fn get_output_fd(&self) -> c::c_int {
+        self.output_fd
+    }
+    /// Sets the fd `query` writes requests to, usually the terminal's
+    /// output when keys are read from its input.
+    pub fn set_output_fd(&mut self, fd: c::c_int) {
+        self.output_fd = fd;
+    }
+
+    /// Writes the request for `query` to the output fd and reads keys until
+    /// its answer comes, returning `None` if it does not within `timeout`.
+    /// Keys read in the meantime are kept for `getkey` and `waitkey` to
+    /// return first, so call `getkey` until it returns `None_` afterwards
+    /// even if the fd is not readable.
+    pub fn query(&mut self, query: Query, timeout: Duration) -> io::Result<Option<Event>> {
+        if self.output_fd == -1 || self.tk.get_fd() == -1 {
+            return Err(io::Error::from_raw_os_error(libc::EBADF));
+        }
+        write_all(self.output_fd, query.request().as_bytes())?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let res = match self.tk.getkey() {
+                Result::None_ => {
+                    if !self.wait_readable(left)? {
+                        return Ok(None);
+                    }
+                    match self.tk.advisereadable() {
+                        Result::Error { err } => return Err(err),
+                        _ => continue,
+                    }
+                }
+                Result::Again => {
+                    let waittime = Duration::from_millis(self.tk.get_waittime().max(0) as u64);
+                    if self.wait_readable(waittime.min(left))? {
+                        match self.tk.advisereadable() {
+                            Result::Error { err } => return Err(err),
+                            _ => continue,
+                        }
+                    }
+                    if waittime > left {
+                        return Ok(None);
+                    }
+                    self.tk.getkey_force()
+                }
+                res => res,
+            };
+            match res {
+                Result::Key(key) if query.is_reply(&key) => return Ok(Some(key)),
+                Result::Key(key) => self.queued.push_back(key),
+                Result::Error { err } => return Err(err),
+                Result::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Asks for the default foreground colour, returning `None` if the
+    /// terminal does not answer within `timeout` or the answer cannot be
+    /// read.
+    pub fn query_foreground(&mut self, timeout: Duration) -> io::Result<Option<Rgb>> {
+        self.query_colour(Query::ForegroundColour, timeout)
+    }
+    /// Asks for the default background colour, as for `query_foreground`.
+    pub fn query_background(&mut self, timeout: Duration) -> io::Result<Option<Rgb>> {
+        self.query_colour(Query::BackgroundColour, timeout)
+    }
+    /// Asks for colour `index` of the palette, as for `query_foreground`.
+    pub fn query_palette(&mut self, index: u8, timeout: Duration) -> io::Result<Option<Rgb>> {
+        self.query_colour(Query::PaletteColour(index), timeout)
+    }
+
+    /// Asks for the contents of `selection`, returning `None` if the
+    /// terminal does not answer within `timeout` or the answer cannot be
+    /// read. Keys typed in the meantime are kept for `getkey` in the order
+    /// they came.
+    pub fn query_clipboard(
+        &mut self,
+        selection: Selection,
+        timeout: Duration,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let query = Query::Clipboard(selection);
+        Ok(self
+            .query(query, timeout)?
+            .and_then(|key| query.clipboard(&key)))
+    }
+
+    fn query_colour(&mut self, query: Query, timeout: Duration) -> io::Result<Option<Rgb>> {
+        Ok(self
+            .query(query, timeout)?
+            .and_then(|key| query.colour(&key)))
+    }
+
+    /// Waits up to `timeout` for the input fd to become readable.
+    fn wait_readable(&mut self, timeout: Duration) -> io::Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: self.tk.get_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // rounded up, so as not to wake before the deadline
+        let millis = timeout.as_micros().div_ceil(1000) as c::c_int;
+        loop {
+            let ret = unsafe { libc::poll(&mut fd, 1, millis) };
+            if ret != -1 {
+                return Ok(ret > 0);
+            }
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINTR) || self.get_flags().contains(Flag::EINTR) {
+                return Err(err);
+            }
+        }
+    }
+}
+
+fn write_all(fd: c::c_int, mut bytes: &[u8]) -> io::Result<()> {
+    while !bytes.is_empty() {
+        let len = unsafe { libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+        if len == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EINTR) {
+                continue;
+            }
+            return Err(err);
+        }
+        bytes = &bytes[len as usize..];
+    }
+    Ok(())
+}
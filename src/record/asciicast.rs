@@ -0,0 +1,93 @@
+//! asciicast v2 files, as asciinema writes them, for the `asciicast`
+//! feature. See <https://docs.asciinema.org/manual/asciicast/v2/>.
+
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+use super::{Chunk, Recording, Replayer};
+use crate::c::Format;
+use crate::{Event, Result};
+
+fn invalid(line: usize, what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, what),
+    )
+}
+
+impl Recording {
+    /// Reads the input (`"i"`) events of an asciicast v2 file, which
+    /// asciinema records with `--stdin`. The input ends with the last
+    /// event of any kind, and the wait time is libtermkey's default of
+    /// 50 milliseconds, as casts do not record one.
+    pub fn from_asciicast<R: BufRead>(input: R) -> io::Result<Recording> {
+        let mut lines = input.lines();
+        let header: serde_json::Value = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(|_| invalid(1, "bad header"))?,
+            None => return Err(invalid(1, "no header")),
+        };
+        if header["version"] != 2 {
+            return Err(invalid(1, "not asciicast version 2"));
+        }
+
+        let mut recording = Recording {
+            waittime: 50,
+            chunks: Vec::new(),
+        };
+        let mut end = Duration::from_secs(0);
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, kind, data): (f64, String, String) =
+                serde_json::from_str(&line).map_err(|_| invalid(index + 2, "bad event"))?;
+            let time = Duration::try_from_secs_f64(time)
+                .map_err(|_| invalid(index + 2, "bad event time"))?;
+            if time < end {
+                return Err(invalid(index + 2, "event out of order"));
+            }
+            end = time;
+            if kind == "i" && !data.is_empty() {
+                recording.chunks.push(Chunk {
+                    time,
+                    bytes: data.into_bytes(),
+                });
+            }
+        }
+        recording.chunks.push(Chunk {
+            time: end,
+            bytes: Vec::new(),
+        });
+        Ok(recording)
+    }
+}
+
+/// Writes every key `replay` reads, one to a line after the virtual time
+/// in seconds it was read at, as in `0.250000 Escape`, for comparing with
+/// a golden file. Keys are written in `strfkey` notation with
+/// `Format::MOUSE_POS`, and events it has no notation for, like pastes, as
+/// their `Debug` representation.
+pub fn write_keys<W: Write>(replay: &mut Replayer, mut out: W) -> io::Result<()> {
+    loop {
+        let key = match replay.waitkey() {
+            Result::Key(key) => key,
+            Result::Again => continue,
+            Result::Error { err } => return Err(err),
+            _ => return Ok(()),
+        };
+        let time = replay.now().as_secs_f64();
+        match key {
+            Event::Unicode { .. }
+            | Event::Function { .. }
+            | Event::KeySym { .. }
+            | Event::Mouse { .. }
+            | Event::FocusGained
+            | Event::FocusLost => {
+                let name = replay.termkey().strfkey(key, Format::MOUSE_POS);
+                writeln!(out, "{:.6} {}", time, name)?;
+            }
+            _ => writeln!(out, "{:.6} {:?}", time, key)?,
+        }
+    }
+}
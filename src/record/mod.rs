@@ -0,0 +1,367 @@
+//! Recording the input a `TermKey` reads, and replaying it in virtual time
+//! to reproduce how it was decoded, down to which keys were forced out by
+//! `getkey_force` after the wait time ran out.
+//!
+//! A recording starts with the six bytes `TKREC1` and the wait time in
+//! milliseconds. Each chunk read follows as the microseconds since the one
+//! before (or since the start), its length, and its bytes; a chunk of no
+//! bytes marks the end of input. Numbers are unsigned LEB128.
+//!
+//! With the `asciicast` feature, recordings can also be read from the
+//! input asciinema captures, and `write_keys` writes the keys a replay
+//! decodes for golden-file tests.
+
+#[cfg(feature = "asciicast")]
+mod asciicast;
+#[cfg(feature = "asciicast")]
+pub use self::asciicast::write_keys;
+
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use crate::c::Flag;
+use crate::{Result, TermKey};
+
+const MAGIC: &[u8] = b"TKREC1";
+
+/// Bytes read at one time, or the end of input if there are none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    /// The time since recording started.
+    pub time: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// The chunks a `Recorder` read, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    /// The wait time of the recorded `TermKey`, in milliseconds.
+    pub waittime: isize,
+    pub chunks: Vec<Chunk>,
+}
+
+impl Recording {
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<Recording> {
+        let mut magic = [0; 6];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a recording",
+            ));
+        }
+        let mut recording = Recording {
+            waittime: read_number(&mut input)?.ok_or_else(truncated)? as isize,
+            chunks: Vec::new(),
+        };
+        let mut time = Duration::from_secs(0);
+        while let Some(micros) = read_number(&mut input)? {
+            time += Duration::from_micros(micros);
+            let len = read_number(&mut input)?.ok_or_else(truncated)?;
+            let mut bytes = Vec::new();
+            input.by_ref().take(len).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 != len {
+                return Err(truncated());
+            }
+            recording.chunks.push(Chunk { time, bytes });
+        }
+        Ok(recording)
+    }
+
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write_header(&mut out, self.waittime)?;
+        let mut last = Duration::from_secs(0);
+        for chunk in &self.chunks {
+            write_chunk(&mut out, chunk.time.saturating_sub(last), &chunk.bytes)?;
+            last = chunk.time;
+        }
+        Ok(())
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated recording")
+}
+
+/// Reads a number, or `None` at the end of input.
+fn read_number<R: Read>(input: &mut R) -> io::Result<Option<u64>> {
+    let mut number = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if input.read(&mut byte)? == 0 {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(truncated())
+            };
+        }
+        number |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(number));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "number too large",
+    ))
+}
+
+fn write_number<W: Write>(out: &mut W, mut number: u64) -> io::Result<()> {
+    loop {
+        let byte = (number & 0x7f) as u8;
+        number >>= 7;
+        if number == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, waittime: isize) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    write_number(out, waittime.max(0) as u64)
+}
+
+fn write_chunk<W: Write>(out: &mut W, delay: Duration, bytes: &[u8]) -> io::Result<()> {
+    write_number(out, delay.as_micros() as u64)?;
+    write_number(out, bytes.len() as u64)?;
+    out.write_all(bytes)
+}
+
+/// Reads a `TermKey`'s fd in its stead, writing what it reads to `out`
+/// before passing it on with `push_bytes`. Use its `advisereadable` and
+/// `waitkey` instead of the `TermKey`'s own; its `getkey` reports `Eof`
+/// once the input has ended and every key has been read.
+pub struct Recorder<W: Write> {
+    tk: TermKey,
+    out: W,
+    start: Instant,
+    last: Duration,
+    closed: bool,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Starts recording, writing the header to `out` at once.
+    pub fn new(mut tk: TermKey, mut out: W) -> io::Result<Recorder<W>> {
+        write_header(&mut out, tk.get_waittime())?;
+        Ok(Recorder {
+            tk,
+            out,
+            start: Instant::now(),
+            last: Duration::from_secs(0),
+            closed: false,
+        })
+    }
+
+    pub fn termkey(&mut self) -> &mut TermKey {
+        &mut self.tk
+    }
+    pub fn into_inner(self) -> (TermKey, W) {
+        (self.tk, self.out)
+    }
+
+    pub fn getkey(&mut self) -> Result {
+        match self.tk.getkey() {
+            Result::None_ if self.closed => Result::Eof,
+            res => res,
+        }
+    }
+    pub fn getkey_force(&mut self) -> Result {
+        match self.tk.getkey_force() {
+            Result::None_ if self.closed => Result::Eof,
+            res => res,
+        }
+    }
+
+    /// Like `TermKey::waitkey`, blocking until a key can be read.
+    pub fn waitkey(&mut self) -> Result {
+        loop {
+            let waittime = match self.getkey() {
+                Result::None_ => -1,
+                Result::Again if self.closed => return self.getkey_force(),
+                Result::Again => self.tk.get_waittime(),
+                res => return res,
+            };
+            let mut fd = libc::pollfd {
+                fd: self.tk.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut fd, 1, waittime as libc::c_int) };
+            if ready == -1 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::EINTR)
+                    || self.tk.get_flags().contains(Flag::EINTR)
+                {
+                    return Result::Error { err };
+                }
+            } else if ready == 0 {
+                return self.getkey_force();
+            } else if let Result::Error { err } = self.advisereadable() {
+                return Result::Error { err };
+            }
+        }
+    }
+
+    /// Like `TermKey::advisereadable`: reads what is available on the fd,
+    /// returning `Again` if that was anything.
+    pub fn advisereadable(&mut self) -> Result {
+        let mut chunk = vec![0u8; self.tk.get_buffer_remaining()];
+        if chunk.is_empty() {
+            return Result::Error {
+                err: io::Error::from_raw_os_error(libc::ENOMEM),
+            };
+        }
+        let len = loop {
+            let len = unsafe {
+                libc::read(
+                    self.tk.as_raw_fd(),
+                    chunk.as_mut_ptr() as *mut libc::c_void,
+                    chunk.len(),
+                )
+            };
+            if len != -1 {
+                break len as usize;
+            }
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EAGAIN) => return Result::None_,
+                Some(libc::EINTR) if !self.tk.get_flags().contains(Flag::EINTR) => {}
+                _ => return Result::Error { err },
+            }
+        };
+        if len == 0 && self.closed {
+            return Result::None_;
+        }
+        let now = self.start.elapsed();
+        if let Err(err) = write_chunk(&mut self.out, now - self.last, &chunk[..len]) {
+            return Result::Error { err };
+        }
+        self.last = now;
+        if len == 0 {
+            self.closed = true;
+            return Result::None_;
+        }
+        self.tk.push_bytes(&chunk[..len]);
+        Result::Again
+    }
+}
+
+/// Feeds a `Recording` to a `TermKey` with `push_bytes`, in virtual time
+/// that advances only as far as decoding the keys needs.
+pub struct Replayer {
+    tk: TermKey,
+    chunks: ::std::vec::IntoIter<Chunk>,
+    next: Option<Chunk>,
+    now: Duration,
+    closed: bool,
+}
+
+impl Replayer {
+    /// Replays `recording` into `tk`, which should be made with
+    /// `new_abstract` for the recorded terminal, with the same flags. Its
+    /// wait time is set to the recorded one.
+    pub fn new(mut tk: TermKey, recording: Recording) -> Replayer {
+        tk.set_waittime(recording.waittime);
+        let mut chunks = recording.chunks.into_iter();
+        Replayer {
+            tk,
+            next: chunks.next(),
+            chunks,
+            now: Duration::from_secs(0),
+            closed: false,
+        }
+    }
+
+    pub fn termkey(&mut self) -> &mut TermKey {
+        &mut self.tk
+    }
+    /// The virtual time since recording started.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Gives the `TermKey` every chunk recorded up to `time`, returning
+    /// whether there were any.
+    pub fn advance_to(&mut self, time: Duration) -> bool {
+        let mut pushed = false;
+        self.now = self.now.max(time);
+        while let Some(chunk) = self.next.take() {
+            if chunk.time > self.now {
+                self.next = Some(chunk);
+                break;
+            }
+            if chunk.bytes.is_empty() {
+                self.closed = true;
+            } else {
+                self.tk.push_bytes(&chunk.bytes);
+            }
+            pushed = true;
+            self.next = self.chunks.next();
+        }
+        pushed
+    }
+
+    pub fn getkey(&mut self) -> Result {
+        match self.tk.getkey() {
+            Result::None_ if self.closed => Result::Eof,
+            res => res,
+        }
+    }
+    pub fn getkey_force(&mut self) -> Result {
+        match self.tk.getkey_force() {
+            Result::None_ if self.closed => Result::Eof,
+            res => res,
+        }
+    }
+
+    /// Like `TermKey::waitkey`, moving virtual time on to the next chunk,
+    /// or by the wait time when a key is ambiguous and the next chunk does
+    /// not come sooner. Returns `None_` when the recording has run out
+    /// without an end of input.
+    pub fn waitkey(&mut self) -> Result {
+        loop {
+            match self.getkey() {
+                Result::None_ => match self.next {
+                    Some(ref chunk) => {
+                        let time = chunk.time;
+                        self.advance_to(time);
+                    }
+                    None => return Result::None_,
+                },
+                Result::Again if self.closed => return self.getkey_force(),
+                Result::Again => {
+                    let waittime = Duration::from_millis(self.tk.get_waittime().max(0) as u64);
+                    let deadline = self.now + waittime;
+                    match self.next {
+                        Some(ref chunk) if chunk.time <= deadline => {
+                            let time = chunk.time;
+                            self.advance_to(time);
+                        }
+                        _ => {
+                            self.now = deadline;
+                            return self.getkey_force();
+                        }
+                    }
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Iterator for Replayer {
+    type Item = crate::Event;
+
+    /// The keys `waitkey` returns, up to the end of the recording.
+    fn next(&mut self) -> Option<crate::Event> {
+        loop {
+            match self.waitkey() {
+                Result::Key(key) => return Some(key),
+                Result::Again => {}
+                _ => return None,
+            }
+        }
+    }
+}
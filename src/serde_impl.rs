@@ -0,0 +1,620 @@
+//! A human-readable representation of keys and flags for the `serde`
+//! feature, meant for keymaps and recordings kept in files:
+//!
+//! * Flag sets (`KeyMod`, `Flag`, `Canon`, `Format` and `kitty::Flags`) are
+//!   lists of their lowercase constant names, like `["ctrl", "shift"]`.
+//!   Combinations such as `Format::VIM` are written out as their parts.
+//! * A `Sym` is its name as `strfkey` prints it, like `"PageDown"`, or its
+//!   number if it has none, as for `Sym::Custom`. Custom numbers depend on
+//!   the backend and the terminal, as `TermKey::register_keyname` says, so
+//!   they only read back as the same key where they were written.
+//! * `Unicode`, `Function` and `KeySym` keys are `{"key": "a"}`,
+//!   `{"key": "F5"}` and `{"key": "PageDown", "mods": ["ctrl"]}`.
+//! * Any other event is a map from its snake_case variant name to its
+//!   fields, like `{"mouse": {"event": "press", "button": "left", "line": 1,
+//!   "col": 1}}`, `{"paste": "text"}` or `{"focus": "gained"}`. All but the
+//!   last piece of a split paste are `{"paste_part": "text"}`.
+//!
+//! Empty modifiers, alternate keys and text are left out, and default to
+//! empty when reading. `compact` writes keys in `strfkey` notation instead.
+//!
+//! What one release writes, every later release with the same major
+//! version reads back as the same value, custom symbol numbers aside. Names for new symbols, flags or
+//! variants may be added in minor releases, so older readers can reject
+//! data from newer writers, but nothing is renamed or removed.
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::c::{self, Canon, Flag, Format, KeyEvent, KeyMod, MouseEvent, Sym, KEYNAMES};
+use crate::{kitty, AttributesKind, CsiArgs, Event, KeyCode, MouseButton, TermKey, Utf8Char};
+
+const KEYMOD_NAMES: &[(c::c_int, &str)] = &[
+    (KeyMod::SHIFT.bits(), "shift"),
+    (KeyMod::ALT.bits(), "alt"),
+    (KeyMod::CTRL.bits(), "ctrl"),
+    (KeyMod::SUPER.bits(), "super"),
+    (KeyMod::HYPER.bits(), "hyper"),
+    (KeyMod::META.bits(), "meta"),
+    (KeyMod::CAPS_LOCK.bits(), "caps_lock"),
+    (KeyMod::NUM_LOCK.bits(), "num_lock"),
+];
+
+const FLAG_NAMES: &[(c::c_int, &str)] = &[
+    (Flag::NOINTERPRET.bits(), "nointerpret"),
+    (Flag::CONVERTKP.bits(), "convertkp"),
+    (Flag::RAW.bits(), "raw"),
+    (Flag::UTF8.bits(), "utf8"),
+    (Flag::NOTERMIOS.bits(), "notermios"),
+    (Flag::SPACESYMBOL.bits(), "spacesymbol"),
+    (Flag::CTRLC.bits(), "ctrlc"),
+    (Flag::EINTR.bits(), "eintr"),
+];
+
+const CANON_NAMES: &[(c::c_int, &str)] = &[
+    (Canon::SPACESYMBOL.bits(), "spacesymbol"),
+    (Canon::DELBS.bits(), "delbs"),
+];
+
+const FORMAT_NAMES: &[(c::c_int, &str)] = &[
+    (Format::LONGMOD.bits(), "longmod"),
+    (Format::CARETCTRL.bits(), "caretctrl"),
+    (Format::ALTISMETA.bits(), "altismeta"),
+    (Format::WRAPBRACKET.bits(), "wrapbracket"),
+    (Format::SPACEMOD.bits(), "spacemod"),
+    (Format::LOWERMOD.bits(), "lowermod"),
+    (Format::LOWERSPACE.bits(), "lowerspace"),
+    (Format::MOUSE_POS.bits(), "mouse_pos"),
+];
+
+const KITTY_FLAG_NAMES: &[(c::c_int, &str)] = &[
+    (
+        kitty::Flags::DISAMBIGUATE_ESCAPE_CODES.bits(),
+        "disambiguate_escape_codes",
+    ),
+    (
+        kitty::Flags::REPORT_EVENT_TYPES.bits(),
+        "report_event_types",
+    ),
+    (
+        kitty::Flags::REPORT_ALTERNATE_KEYS.bits(),
+        "report_alternate_keys",
+    ),
+    (
+        kitty::Flags::REPORT_ALL_KEYS_AS_ESCAPE_CODES.bits(),
+        "report_all_keys_as_escape_codes",
+    ),
+    (
+        kitty::Flags::REPORT_ASSOCIATED_TEXT.bits(),
+        "report_associated_text",
+    ),
+];
+
+macro_rules! serde_flags {
+    ($flags:ty, $names:expr) => {
+        impl Serialize for $flags {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(
+                    $names
+                        .iter()
+                        .filter(|&&(bits, _)| self.bits() & bits == bits)
+                        .map(|&(_, name)| name),
+                )
+            }
+        }
+        impl<'de> Deserialize<'de> for $flags {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$flags, D::Error> {
+                let mut bits = 0;
+                for name in Vec::<String>::deserialize(deserializer)? {
+                    match $names.iter().find(|&&(_, known)| known == name) {
+                        Some(&(flag, _)) => bits |= flag,
+                        None => {
+                            return Err(de::Error::custom(format_args!("unknown flag `{}`", name)))
+                        }
+                    }
+                }
+                Ok(<$flags>::from_bits_truncate(bits))
+            }
+        }
+    };
+}
+
+serde_flags!(KeyMod, KEYMOD_NAMES);
+serde_flags!(Flag, FLAG_NAMES);
+serde_flags!(Canon, CANON_NAMES);
+serde_flags!(Format, FORMAT_NAMES);
+serde_flags!(kitty::Flags, KITTY_FLAG_NAMES);
+
+macro_rules! serde_names {
+    ($type:ty, $names:expr) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let &(_, name) = $names.iter().find(|(value, _)| value == self).unwrap();
+                serializer.serialize_str(name)
+            }
+        }
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$type, D::Error> {
+                let name = String::deserialize(deserializer)?;
+                $names
+                    .iter()
+                    .find(|&&(_, known)| known == name)
+                    .map(|&(value, _)| value)
+                    .ok_or_else(|| de::Error::custom(format_args!("unknown name `{}`", name)))
+            }
+        }
+    };
+}
+
+const MOUSE_EVENT_NAMES: &[(MouseEvent, &str)] = &[
+    (MouseEvent::UNKNOWN, "unknown"),
+    (MouseEvent::PRESS, "press"),
+    (MouseEvent::DRAG, "drag"),
+    (MouseEvent::RELEASE, "release"),
+];
+
+const KEY_EVENT_NAMES: &[(KeyEvent, &str)] = &[
+    (KeyEvent::PRESS, "press"),
+    (KeyEvent::REPEAT, "repeat"),
+    (KeyEvent::RELEASE, "release"),
+];
+
+const ATTRIBUTES_KIND_NAMES: &[(AttributesKind, &str)] = &[
+    (AttributesKind::Primary, "primary"),
+    (AttributesKind::Secondary, "secondary"),
+    (AttributesKind::Tertiary, "tertiary"),
+];
+
+serde_names!(MouseEvent, MOUSE_EVENT_NAMES);
+serde_names!(KeyEvent, KEY_EVENT_NAMES);
+serde_names!(AttributesKind, ATTRIBUTES_KIND_NAMES);
+
+/// A name or, for what has none, a number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NameOrNumber {
+    Name(String),
+    Number(c::c_int),
+}
+
+impl Serialize for Sym {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match KEYNAMES.iter().find(|&&(sym, _)| sym == *self) {
+            Some(&(_, name)) => serializer.serialize_str(name),
+            None => serializer.serialize_i32(self.to_c()),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for Sym {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Sym, D::Error> {
+        match NameOrNumber::deserialize(deserializer)? {
+            NameOrNumber::Name(name) => sym_named(&name)
+                .ok_or_else(|| de::Error::custom(format_args!("unknown key name `{}`", name))),
+            NameOrNumber::Number(sym) => Ok(Sym::from_c(sym)),
+        }
+    }
+}
+
+fn sym_named(name: &str) -> Option<Sym> {
+    KEYNAMES
+        .iter()
+        .find(|&&(_, known)| known == name)
+        .map(|&(sym, _)| sym)
+}
+
+/// Written like `strfkey` writes keys without modifiers: a single
+/// character, `F` and a number, or the name of a `Sym`.
+impl Serialize for KeyCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            KeyCode::Unicode(codepoint) => serializer.collect_str(&codepoint),
+            KeyCode::Function(num) => serializer.collect_str(&format_args!("F{}", num)),
+            KeyCode::KeySym(sym) => sym.serialize(serializer),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for KeyCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+        let name = match NameOrNumber::deserialize(deserializer)? {
+            NameOrNumber::Name(name) => name,
+            NameOrNumber::Number(sym) => return Ok(KeyCode::KeySym(Sym::from_c(sym))),
+        };
+        let mut chars = name.chars();
+        if let (Some(codepoint), None) = (chars.next(), chars.next()) {
+            return Ok(KeyCode::Unicode(codepoint));
+        }
+        if let Some(num) = name.strip_prefix('F').and_then(|num| num.parse().ok()) {
+            return Ok(KeyCode::Function(num));
+        }
+        sym_named(&name)
+            .map(KeyCode::KeySym)
+            .ok_or_else(|| de::Error::custom(format_args!("unknown key name `{}`", name)))
+    }
+}
+
+const MOUSE_BUTTON_NAMES: &[(MouseButton, &str)] = &[
+    (MouseButton::Left, "left"),
+    (MouseButton::Middle, "middle"),
+    (MouseButton::Right, "right"),
+    (MouseButton::WheelUp, "wheel_up"),
+    (MouseButton::WheelDown, "wheel_down"),
+    (MouseButton::WheelLeft, "wheel_left"),
+    (MouseButton::WheelRight, "wheel_right"),
+    (MouseButton::Back, "back"),
+    (MouseButton::Forward, "forward"),
+];
+
+impl Serialize for MouseButton {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match MOUSE_BUTTON_NAMES.iter().find(|(button, _)| button == self) {
+            Some(&(_, name)) => serializer.serialize_str(name),
+            None => serializer.serialize_u8(self.number()),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for MouseButton {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MouseButton, D::Error> {
+        match NameOrNumber::deserialize(deserializer)? {
+            NameOrNumber::Name(name) => MOUSE_BUTTON_NAMES
+                .iter()
+                .find(|&&(_, known)| known == name)
+                .map(|&(button, _)| button)
+                .ok_or_else(|| de::Error::custom(format_args!("unknown mouse button `{}`", name))),
+            NameOrNumber::Number(number) => u8::try_from(number)
+                .map(MouseButton::new)
+                .map_err(|_| de::Error::custom(format_args!("no mouse button {}", number))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EventRepr {
+    Key {
+        key: KeyCode,
+        #[serde(default = "KeyMod::empty", skip_serializing_if = "KeyMod::is_empty")]
+        mods: KeyMod,
+    },
+    Other(OtherRepr),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OtherRepr {
+    Mouse {
+        event: MouseEvent,
+        button: MouseButton,
+        line: isize,
+        col: isize,
+        #[serde(default, skip_serializing_if = "is_false")]
+        pixels: bool,
+        #[serde(default = "KeyMod::empty", skip_serializing_if = "KeyMod::is_empty")]
+        mods: KeyMod,
+    },
+    Position {
+        line: isize,
+        col: isize,
+    },
+    ModeReport {
+        initial: isize,
+        mode: isize,
+        value: isize,
+    },
+    UnknownCsi {
+        initial: isize,
+        intermediate: isize,
+        command: isize,
+        args: Vec<isize>,
+    },
+    KittyKey {
+        key: KeyCode,
+        #[serde(default = "KeyMod::empty", skip_serializing_if = "KeyMod::is_empty")]
+        mods: KeyMod,
+        event: KeyEvent,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shifted: Option<char>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base: Option<char>,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        text: String,
+    },
+    KittyFlags(kitty::Flags),
+    Paste(String),
+    /// A piece of a paste that more pieces follow
+    PastePart(String),
+    Focus(Focus),
+    DeviceAttributes {
+        kind: AttributesKind,
+        params: Vec<isize>,
+    },
+    TerminalVersion(String),
+    Osc {
+        number: isize,
+        payload: String,
+    },
+    Dcs {
+        initial: isize,
+        intermediate: isize,
+        command: isize,
+        args: Vec<isize>,
+        payload: String,
+    },
+    Apc(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Focus {
+    Gained,
+    Lost,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl Serialize for Event {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let key = |key, mods| EventRepr::Key { key, mods };
+        let other = EventRepr::Other;
+        let repr = match *self {
+            Event::Unicode {
+                codepoint, mods, ..
+            } => key(KeyCode::Unicode(codepoint), mods),
+            Event::Function { num, mods } => key(KeyCode::Function(num), mods),
+            Event::KeySym { sym, mods } => key(KeyCode::KeySym(sym), mods),
+            Event::Mouse {
+                ev,
+                mods,
+                button,
+                line,
+                col,
+                pixels,
+            } => other(OtherRepr::Mouse {
+                event: ev,
+                button,
+                line,
+                col,
+                pixels,
+                mods,
+            }),
+            Event::Position { line, col } => other(OtherRepr::Position { line, col }),
+            Event::ModeReport {
+                initial,
+                mode,
+                value,
+            } => other(OtherRepr::ModeReport {
+                initial,
+                mode,
+                value,
+            }),
+            Event::UnknownCsi {
+                args,
+                initial,
+                intermediate,
+                command,
+            } => other(OtherRepr::UnknownCsi {
+                initial,
+                intermediate,
+                command,
+                args: args.as_slice().to_vec(),
+            }),
+            Event::KittyKey {
+                code,
+                mods,
+                event,
+                shifted,
+                base,
+                ref text,
+            } => other(OtherRepr::KittyKey {
+                key: code,
+                mods,
+                event,
+                shifted,
+                base,
+                text: text.clone(),
+            }),
+            Event::KittyFlags { flags } => other(OtherRepr::KittyFlags(flags)),
+            Event::Paste {
+                ref text,
+                more: false,
+            } => other(OtherRepr::Paste(text.clone())),
+            Event::Paste {
+                ref text,
+                more: true,
+            } => other(OtherRepr::PastePart(text.clone())),
+            Event::FocusGained => other(OtherRepr::Focus(Focus::Gained)),
+            Event::FocusLost => other(OtherRepr::Focus(Focus::Lost)),
+            Event::DeviceAttributes { kind, ref params } => other(OtherRepr::DeviceAttributes {
+                kind,
+                params: params.clone(),
+            }),
+            Event::TerminalVersion(ref version) => {
+                other(OtherRepr::TerminalVersion(version.clone()))
+            }
+            Event::Osc {
+                number,
+                ref payload,
+            } => other(OtherRepr::Osc {
+                number,
+                payload: payload.clone(),
+            }),
+            Event::Dcs {
+                args,
+                initial,
+                intermediate,
+                command,
+                ref payload,
+            } => other(OtherRepr::Dcs {
+                initial,
+                intermediate,
+                command,
+                args: args.as_slice().to_vec(),
+                payload: payload.clone(),
+            }),
+            Event::Apc { ref payload } => other(OtherRepr::Apc(payload.clone())),
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Event, D::Error> {
+        let repr = match EventRepr::deserialize(deserializer)? {
+            EventRepr::Key { key, mods } => {
+                return Ok(match key {
+                    KeyCode::Unicode(codepoint) => Event::Unicode {
+                        codepoint,
+                        mods,
+                        utf8: Utf8Char::new(codepoint),
+                    },
+                    KeyCode::Function(num) => Event::Function { num, mods },
+                    KeyCode::KeySym(sym) => Event::KeySym { sym, mods },
+                })
+            }
+            EventRepr::Other(repr) => repr,
+        };
+        Ok(match repr {
+            OtherRepr::Mouse {
+                event,
+                button,
+                line,
+                col,
+                pixels,
+                mods,
+            } => Event::Mouse {
+                ev: event,
+                mods,
+                button,
+                line,
+                col,
+                pixels,
+            },
+            OtherRepr::Position { line, col } => Event::Position { line, col },
+            OtherRepr::ModeReport {
+                initial,
+                mode,
+                value,
+            } => Event::ModeReport {
+                initial,
+                mode,
+                value,
+            },
+            OtherRepr::UnknownCsi {
+                initial,
+                intermediate,
+                command,
+                args,
+            } => Event::UnknownCsi {
+                args: csi_args(&args)?,
+                initial,
+                intermediate,
+                command,
+            },
+            OtherRepr::KittyKey {
+                key,
+                mods,
+                event,
+                shifted,
+                base,
+                text,
+            } => Event::KittyKey {
+                code: key,
+                mods,
+                event,
+                shifted,
+                base,
+                text,
+            },
+            OtherRepr::KittyFlags(flags) => Event::KittyFlags { flags },
+            OtherRepr::Paste(text) => Event::Paste { text, more: false },
+            OtherRepr::PastePart(text) => Event::Paste { text, more: true },
+            OtherRepr::Focus(Focus::Gained) => Event::FocusGained,
+            OtherRepr::Focus(Focus::Lost) => Event::FocusLost,
+            OtherRepr::DeviceAttributes { kind, params } => {
+                Event::DeviceAttributes { kind, params }
+            }
+            OtherRepr::TerminalVersion(version) => Event::TerminalVersion(version),
+            OtherRepr::Osc { number, payload } => Event::Osc { number, payload },
+            OtherRepr::Dcs {
+                initial,
+                intermediate,
+                command,
+                args,
+                payload,
+            } => Event::Dcs {
+                args: csi_args(&args)?,
+                initial,
+                intermediate,
+                command,
+                payload,
+            },
+            OtherRepr::Apc(payload) => Event::Apc { payload },
+        })
+    }
+}
+
+fn csi_args<E: de::Error>(args: &[isize]) -> Result<CsiArgs, E> {
+    let mut csi = CsiArgs {
+        args: [0; 16],
+        len: args.len(),
+    };
+    if args.len() > csi.args.len() {
+        return Err(E::invalid_length(args.len(), &"at most 16 arguments"));
+    }
+    csi.args[..args.len()].copy_from_slice(args);
+    Ok(csi)
+}
+
+/// Use with `#[serde(with = "termkey::compact")]` to write an `Event` as a
+/// string in `strfkey` notation with `Format::MOUSE_POS`, like `"C-a"` or
+/// `"MousePress(1) @ (3,4)"`. Events that `strpkey` would not read back as
+/// the same value, such as pastes or keys named with `register_keyname`,
+/// are written in the usual representation instead. Both are read back.
+pub mod compact {
+    use super::*;
+
+    thread_local! {
+        /// Only the built-in key names are used, so any terminal will do.
+        static TK: RefCell<TermKey> = RefCell::new(TermKey::new_abstract("vt100", Flag::empty()));
+    }
+
+    pub fn serialize<S: Serializer>(key: &Event, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = TK.with(|tk| {
+            let mut tk = tk.borrow_mut();
+            let name = tk.strfkey(key.clone(), Format::MOUSE_POS);
+            match tk.strpkey(&name, Format::MOUSE_POS) {
+                Some((parsed, "")) if parsed == *key => Some(name),
+                _ => None,
+            }
+        });
+        match name {
+            Some(name) => serializer.serialize_str(&name),
+            None => key.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Event, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Compact {
+            Name(String),
+            Event(Event),
+        }
+
+        let name = match Compact::deserialize(deserializer)? {
+            Compact::Name(name) => name,
+            Compact::Event(key) => return Ok(key),
+        };
+        TK.with(
+            |tk| match tk.borrow_mut().strpkey(&name, Format::MOUSE_POS) {
+                Some((key, "")) => Ok(key),
+                _ => Err(de::Error::custom(format_args!("invalid key `{}`", name))),
+            },
+        )
+    }
+}
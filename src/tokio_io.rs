@@ -0,0 +1,118 @@
+use std::future::Future;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::io::unix::AsyncFd;
+use tokio::time::Sleep;
+
+use crate::{Event, Result, TermKey};
+
+/// A `Stream` of key events read from the file descriptor of a `TermKey`.
+///
+/// This performs the same dance as `examples/demo-async.rs`: bytes are read
+/// with `advisereadable` whenever the fd becomes readable, and when `getkey`
+/// reports an ambiguous prefix (such as a lone Escape) a timer of
+/// `get_waittime` milliseconds is armed, after which `getkey_force` is used.
+///
+/// The fd is switched to non-blocking mode for as long as the `AsyncTermKey`
+/// lives, and its previous file status flags are restored on drop.
+pub struct AsyncTermKey {
+    fd: AsyncFd<TermKey>,
+    fd_flags: libc::c_int,
+    timeout: Option<Pin<Box<Sleep>>>,
+}
+
+impl AsyncTermKey {
+    /// Must be called from within a tokio runtime. Fails for abstract
+    /// instances, which have no fd to wait on.
+    pub fn new(tk: TermKey) -> io::Result<AsyncTermKey> {
+        let raw_fd = tk.as_raw_fd();
+        let fd_flags = unsafe { libc::fcntl(raw_fd, libc::F_GETFL) };
+        if fd_flags == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if unsafe { libc::fcntl(raw_fd, libc::F_SETFL, fd_flags | libc::O_NONBLOCK) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = match AsyncFd::new(tk) {
+            Ok(fd) => fd,
+            Err(err) => {
+                unsafe { libc::fcntl(raw_fd, libc::F_SETFL, fd_flags) };
+                return Err(err);
+            }
+        };
+        Ok(AsyncTermKey {
+            fd,
+            fd_flags,
+            timeout: None,
+        })
+    }
+
+    pub fn get_ref(&self) -> &TermKey {
+        self.fd.get_ref()
+    }
+    /// Access to the wrapped instance, e.g. for `strfkey`. Reading keys
+    /// through it directly will confuse the pending timeout.
+    pub fn get_mut(&mut self) -> &mut TermKey {
+        self.fd.get_mut()
+    }
+}
+
+impl Drop for AsyncTermKey {
+    fn drop(&mut self) {
+        unsafe { libc::fcntl(self.fd.as_raw_fd(), libc::F_SETFL, self.fd_flags) };
+    }
+}
+
+impl Stream for AsyncTermKey {
+    type Item = io::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<io::Result<Event>>> {
+        let this = self.get_mut();
+        loop {
+            match this.fd.get_mut().getkey() {
+                Result::Key(key) => {
+                    this.timeout = None;
+                    return Poll::Ready(Some(Ok(key)));
+                }
+                Result::Eof => return Poll::Ready(None),
+                Result::Error { err } => return Poll::Ready(Some(Err(err))),
+                Result::Again => {
+                    if this.timeout.is_none() {
+                        let waittime = this.fd.get_mut().get_waittime().max(0) as u64;
+                        let sleep = tokio::time::sleep(Duration::from_millis(waittime));
+                        this.timeout = Some(Box::pin(sleep));
+                    }
+                }
+                Result::None_ => this.timeout = None,
+            }
+
+            if let Some(timeout) = this.timeout.as_mut() {
+                if timeout.as_mut().poll(cx).is_ready() {
+                    this.timeout = None;
+                    match this.fd.get_mut().getkey_force() {
+                        Result::Key(key) => return Poll::Ready(Some(Ok(key))),
+                        Result::Error { err } => return Poll::Ready(Some(Err(err))),
+                        _ => continue,
+                    }
+                }
+            }
+
+            let mut guard = match this.fd.poll_read_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.get_inner_mut().advisereadable() {
+                // advisereadable only reports NONE when read(2) hit EAGAIN
+                Result::None_ => guard.clear_ready(),
+                Result::Error { err } => return Poll::Ready(Some(Err(err))),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+extern crate termkey;
+
+use termkey::c::Flag;
+use termkey::record::{write_keys, Recording, Replayer};
+use termkey::TermKey;
+
+const CAST: &str = r#"{"version": 2, "width": 80, "height": 24, "timestamp": 1700000000}
+[0.1, "o", "$ "]
+[0.5, "i", "ls\r"]
+[0.6, "o", "ls\r\n"]
+[1.0, "i", "\u001b"]
+[1.01, "i", "[A"]
+[2.0, "i", "\u001b"]
+[2.5, "i", "\u001b[200~pasted\u001b[201~"]
+[3.0, "o", "bye"]
+"#;
+
+#[test]
+fn test_import() {
+    let recording = Recording::from_asciicast(CAST.as_bytes()).unwrap();
+    assert_eq!(recording.waittime, 50);
+    assert_eq!(recording.chunks.len(), 6, "five inputs and the end");
+    assert_eq!(recording.chunks[0].bytes, b"ls\r");
+    assert_eq!(recording.chunks[1].time.as_millis(), 1000);
+    assert!(recording.chunks[5].bytes.is_empty());
+    assert_eq!(recording.chunks[5].time.as_millis(), 3000);
+
+    assert!(Recording::from_asciicast(&b"{\"version\": 1}\n"[..]).is_err());
+    let err = Recording::from_asciicast(
+        &b"{\"version\": 2}\n[1.0, \"i\", \"a\"]\n[0.5, \"i\", \"b\"]\n"[..],
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "line 3: event out of order");
+}
+
+#[test]
+fn test_golden() {
+    let recording = Recording::from_asciicast(CAST.as_bytes()).unwrap();
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    tk.start();
+    let mut replay = Replayer::new(tk, recording);
+    let mut out = Vec::new();
+    write_keys(&mut replay, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "0.500000 l\n\
+         0.500000 s\n\
+         0.500000 Enter\n\
+         1.010000 Up\n\
+         2.050000 Escape\n\
+         2.500000 Paste { text: \"pasted\", more: false }\n"
+    );
+}
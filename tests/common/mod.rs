@@ -0,0 +1,26 @@
+//! Helpers shared by the test files; each uses only some of them.
+#![allow(dead_code)]
+
+use termkey::c::{KeyMod, Sym};
+use termkey::Event;
+
+pub fn unicode(codepoint: char, mods: KeyMod) -> Event {
+    Event::Unicode {
+        codepoint,
+        mods,
+        utf8: termkey::Utf8Char::new(codepoint),
+    }
+}
+
+pub fn keysym(sym: Sym) -> Event {
+    Event::KeySym {
+        sym,
+        mods: KeyMod::empty(),
+    }
+}
+
+pub fn fd_write(fd: libc::c_int, s: &str) {
+    unsafe {
+        libc::write(fd, s.as_ptr() as *const libc::c_void, s.len());
+    }
+}
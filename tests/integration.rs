@@ -1,6 +1,10 @@
 extern crate libc;
 extern crate termkey;
 
+mod common;
+
+use common::fd_write;
+
 macro_rules! diag {
     ($($arg:tt)*) => ({
         let dst: &mut dyn ::std::io::Write = &mut ::std::io::stderr();
@@ -835,16 +839,6 @@ fn test_04flags() {
     }
 }
 
-fn fd_write(fd: libc::c_int, s: &str) {
-    let s: &[u8] = s.as_bytes();
-    let l: usize = s.len();
-    let s: *const u8 = &s[0];
-    let l: libc::size_t = l as libc::size_t;
-    unsafe {
-        libc::write(fd, s as *const libc::c_void, l);
-    }
-}
-
 #[test]
 fn test_05read() {
     let mut tap = taplib::Tap::new();
@@ -1032,12 +1026,37 @@ fn test_06buffer() {
     }
 }
 
+#[test]
+fn test_07errors() {
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(6);
+
+    match termkey::TermKey::try_new_abstract("vt\u{0}100", termkey::c::Flag::empty()) {
+        Err(termkey::Error::NulInTermName) => {
+            tap.pass("try_new_abstract rejects NUL in terminal name");
+        }
+        _ => tap.fail("try_new_abstract rejects NUL in terminal name"),
+    }
+
+    match termkey::TermKey::try_new_abstract("vt100", termkey::c::Flag::empty()) {
+        Ok(mut tk) => {
+            tap.pass("try_new_abstract succeeds for vt100");
+
+            tap.ok(tk.try_stop().is_ok(), "try_stop succeeds");
+            tap.ok(!tk.is_started(), "is_started false after try_stop");
+            tap.ok(tk.try_start().is_ok(), "try_start succeeds");
+            tap.ok(tk.is_started(), "is_started true after try_start");
+        }
+        Err(_) => tap.bypass(5, "try_new_abstract succeeds for vt100"),
+    }
+}
+
 pub fn breakpoint() {}
 
 #[test]
 fn test_10keyname() {
     let mut tap = taplib::Tap::new();
-    tap.plan_tests(10);
+    tap.plan_tests(20);
 
     let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
 
@@ -1089,8 +1108,71 @@ fn test_10keyname() {
         Some(_) => tap.bypass(1, "termkey_get_keyname SomeUnknownKey returns NULL"),
     }
 
-    //tap.is_str(tk.get_keyname(termkey::c::Sym::SPACE), "Space", "get_keyname SPACE");
-    tap.is_str("Space", "Space", "get_keyname SPACE");
+    tap.is_str(
+        tk.keyname(termkey::c::Sym::SPACE).unwrap_or(""),
+        "Space",
+        "get_keyname SPACE",
+    );
+
+    let play = tk.register_keyname("AudioPlay");
+    tap.ok(
+        matches!(play, termkey::c::Sym::Custom(_)),
+        "register_keyname AudioPlay yields a custom symbol",
+    );
+    tap.is_int(
+        tk.register_keyname("AudioPlay"),
+        play,
+        "register_keyname AudioPlay again yields the same symbol",
+    );
+    tap.is_int(
+        tk.register_keyname("Space"),
+        termkey::c::Sym::SPACE,
+        "register_keyname Space yields SPACE",
+    );
+    tap.is_str(
+        tk.keyname(play).unwrap_or(""),
+        "AudioPlay",
+        "get_keyname AudioPlay",
+    );
+    tap.is_str(
+        tk.strfkey(
+            termkey::Event::KeySym {
+                sym: play,
+                mods: termkey::c::KeyMod::CTRL,
+            },
+            termkey::c::Format::empty(),
+        ),
+        "C-AudioPlay",
+        "strfkey C-AudioPlay",
+    );
+    match tk.strpkey("C-AudioPlay", termkey::c::Format::empty()) {
+        Some((termkey::Event::KeySym { sym, mods }, "")) => tap.ok(
+            sym == play && mods == termkey::c::KeyMod::CTRL,
+            "strpkey C-AudioPlay",
+        ),
+        _ => tap.fail("strpkey C-AudioPlay"),
+    }
+
+    let unregistered = termkey::c::Sym::Custom(play.to_c() as u32 + 1000);
+    tap.ok(
+        tk.keyname(unregistered).is_none(),
+        "get_keyname of an unregistered symbol is NULL",
+    );
+    tap.is_int(
+        tk.keyname2sym("Sp\0ace"),
+        termkey::c::Sym::UNKNOWN,
+        "keyname2sym with a NUL yields UNKNOWN",
+    );
+    let escape = termkey::c::Sym::Custom(termkey::c::Sym::ESCAPE.to_c() as u32);
+    tap.ok(
+        escape == termkey::c::Sym::ESCAPE,
+        "custom symbol with a named number equals the named symbol",
+    );
+    tap.is_str(
+        tk.keyname(escape).unwrap_or(""),
+        "Escape",
+        "get_keyname custom symbol with a named number",
+    );
 }
 
 #[test]
@@ -1108,11 +1190,11 @@ fn test_11strfkey() {
         },
     };
 
-    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
     tap.is_int(buffer.len(), 1, "length for unicode/A/0");
     tap.is_str(buffer, "A", "buffer for unicode/A/0");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::WRAPBRACKET);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::WRAPBRACKET);
     tap.is_int(buffer.len(), 1, "length for unicode/A/0 wrapbracket");
     tap.is_str(buffer, "A", "buffer for unicode/A/0 wrapbracket");
 
@@ -1124,16 +1206,16 @@ fn test_11strfkey() {
         },
     };
 
-    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
     tap.is_int(buffer.len(), 3, "length for unicode/b/CTRL");
     tap.is_str(buffer, "C-b", "buffer for unicode/b/CTRL");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::LONGMOD);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::LONGMOD);
     tap.is_int(buffer.len(), 6, "length for unicode/b/CTRL longmod");
     tap.is_str(buffer, "Ctrl-b", "buffer for unicode/b/CTRL longmod");
 
     let buffer = tk.strfkey(
-        key,
+        key.clone(),
         termkey::c::Format::LONGMOD | termkey::c::Format::SPACEMOD,
     );
     tap.is_int(
@@ -1148,7 +1230,7 @@ fn test_11strfkey() {
     );
 
     let buffer = tk.strfkey(
-        key,
+        key.clone(),
         termkey::c::Format::LONGMOD | termkey::c::Format::LOWERMOD,
     );
     tap.is_int(
@@ -1163,7 +1245,7 @@ fn test_11strfkey() {
     );
 
     let buffer = tk.strfkey(
-        key,
+        key.clone(),
         termkey::c::Format::LONGMOD | termkey::c::Format::SPACEMOD | termkey::c::Format::LOWERMOD,
     );
     tap.is_int(
@@ -1177,11 +1259,11 @@ fn test_11strfkey() {
         "buffer for unicode/b/CTRL longmod|spacemod|lowermode",
     );
 
-    let buffer = tk.strfkey(key, termkey::c::Format::CARETCTRL);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::CARETCTRL);
     tap.is_int(buffer.len(), 2, "length for unicode/b/CTRL caretctrl");
     tap.is_str(buffer, "^B", "buffer for unicode/b/CTRL caretctrl");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::WRAPBRACKET);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::WRAPBRACKET);
     tap.is_int(buffer.len(), 5, "length for unicode/b/CTRL wrapbracket");
     tap.is_str(buffer, "<C-b>", "buffer for unicode/b/CTRL wrapbracket");
 
@@ -1193,20 +1275,20 @@ fn test_11strfkey() {
         },
     };
 
-    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
     tap.is_int(buffer.len(), 3, "length for unicode/c/ALT");
     tap.is_str(buffer, "A-c", "buffer for unicode/c/ALT");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::LONGMOD);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::LONGMOD);
     tap.is_int(buffer.len(), 5, "length for unicode/c/ALT longmod");
     tap.is_str(buffer, "Alt-c", "buffer for unicode/c/ALT longmod");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::ALTISMETA);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::ALTISMETA);
     tap.is_int(buffer.len(), 3, "length for unicode/c/ALT altismeta");
     tap.is_str(buffer, "M-c", "buffer for unicode/c/ALT altismeta");
 
     let buffer = tk.strfkey(
-        key,
+        key.clone(),
         termkey::c::Format::LONGMOD | termkey::c::Format::ALTISMETA,
     );
     tap.is_int(
@@ -1225,11 +1307,11 @@ fn test_11strfkey() {
         mods: termkey::c::KeyMod::empty(),
     };
 
-    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
     tap.is_int(buffer.len(), 2, "length for sym/Up/0");
     tap.is_str(buffer, "Up", "buffer for sym/Up/0");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::WRAPBRACKET);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::WRAPBRACKET);
     tap.is_int(buffer.len(), 4, "length for sym/Up/0 wrapbracket");
     tap.is_str(buffer, "<Up>", "buffer for sym/Up/0 wrapbracket");
 
@@ -1238,11 +1320,11 @@ fn test_11strfkey() {
         mods: termkey::c::KeyMod::empty(),
     };
 
-    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
     tap.is_int(buffer.len(), 6, "length for sym/PageUp/0");
     tap.is_str(buffer, "PageUp", "buffer for sym/PageUp/0");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::LOWERSPACE);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::LOWERSPACE);
     tap.is_int(buffer.len(), 7, "length for sym/PageUp/0 lowerspace");
     tap.is_str(buffer, "page up", "buffer for sym/PageUp/0 lowerspace");
 
@@ -1255,11 +1337,11 @@ fn test_11strfkey() {
         // strfkey internals are not exposed; this is done internally.
 
         /* If size of buffer is too small, strfkey should return something consistent */
-        let buffer = tk.strfkey(/*4*/ key, termkey::c::Format::empty());
+        let buffer = tk.strfkey(/*4*/ key.clone(), termkey::c::Format::empty());
         tap.is_int(buffer.len(), 6, "length for sym/PageUp/0");
         tap.is_str(buffer, "Pag", "buffer of len 4 for sym/PageUp/0");
 
-        let buffer = tk.strfkey(/*4*/ key, termkey::c::Format::LOWERSPACE);
+        let buffer = tk.strfkey(/*4*/ key.clone(), termkey::c::Format::LOWERSPACE);
         tap.is_int(buffer.len(), 7, "length for sym/PageUp/0 lowerspace");
         tap.is_str(buffer, "pag", "buffer of len 4 for sym/PageUp/0 lowerspace");
     }
@@ -1269,15 +1351,15 @@ fn test_11strfkey() {
         mods: termkey::c::KeyMod::empty(),
     };
 
-    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
     tap.is_int(buffer.len(), 2, "length for func/5/0");
     tap.is_str(buffer, "F5", "buffer for func/5/0");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::WRAPBRACKET);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::WRAPBRACKET);
     tap.is_int(buffer.len(), 4, "length for func/5/0 wrapbracket");
     tap.is_str(buffer, "<F5>", "buffer for func/5/0 wrapbracket");
 
-    let buffer = tk.strfkey(key, termkey::c::Format::LOWERSPACE);
+    let buffer = tk.strfkey(key.clone(), termkey::c::Format::LOWERSPACE);
     tap.is_int(buffer.len(), 2, "length for func/5/0 lowerspace");
     tap.is_str(buffer, "f5", "buffer for func/5/0 lowerspace");
 }
@@ -1620,7 +1702,7 @@ fn test_12strpkey() {
 #[test]
 fn test_13cmpkey() {
     let mut tap = taplib::Tap::new();
-    tap.plan_tests(12);
+    tap.plan_tests(16);
 
     let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
 
@@ -1678,34 +1760,74 @@ fn test_13cmpkey() {
     tap.ok(key1 < key2, "cmpkey orders KEYSYM after UNICODE");
     tap.ok(key2 > key1, "cmpkey orders UNICODE before KEYSYM");
 
-    if true {
-        tap.pass("skipping unsupported space test 1");
-        tap.pass("skipping unsupported space test 2");
-    } else {
-        key1 = termkey::Event::KeySym {
-            sym: termkey::c::Sym::SPACE,
-            mods: termkey::c::KeyMod::empty(),
-        };
-        key2 = termkey::Event::Unicode {
-            codepoint: ' ',
-            mods: termkey::c::KeyMod::empty(),
-            utf8: termkey::Utf8Char { bytes: [0; 7] },
-        };
+    key1 = termkey::Event::KeySym {
+        sym: termkey::c::Sym::SPACE,
+        mods: termkey::c::KeyMod::empty(),
+    };
+    key2 = termkey::Event::Unicode {
+        codepoint: ' ',
+        mods: termkey::c::KeyMod::empty(),
+        utf8: termkey::Utf8Char { bytes: [0; 7] },
+    };
 
-        tap.ok(
-            key1 == key2,
-            "cmpkey considers KEYSYM/SPACE and UNICODE/SP identical",
-        );
+    tap.ok(
+        tk.keycmp(&key1, &key2) == std::cmp::Ordering::Equal,
+        "cmpkey considers KEYSYM/SPACE and UNICODE/SP identical",
+    );
 
-        // Rust is being too smart for its own good, and forbids multiple
-        // borrows in one line, even though only one borrow happens at a time.
-        let cflags = tk.get_canonflags();
-        tk.set_canonflags(cflags | termkey::c::Canon::SPACESYMBOL);
-        tap.ok(
-            key1 == key2,
-            "cmpkey considers KEYSYM/SPACE and UNICODE/SP identical under SPACESYMBOL",
-        );
-    }
+    // Rust is being too smart for its own good, and forbids multiple
+    // borrows in one line, even though only one borrow happens at a time.
+    let cflags = tk.get_canonflags();
+    tk.set_canonflags(cflags | termkey::c::Canon::SPACESYMBOL);
+    tap.ok(
+        tk.keycmp(&key1, &key2) == std::cmp::Ordering::Equal,
+        "cmpkey considers KEYSYM/SPACE and UNICODE/SP identical under SPACESYMBOL",
+    );
+
+    key1 = termkey::Event::Unicode {
+        codepoint: 'a',
+        mods: termkey::c::KeyMod::empty(),
+        utf8: termkey::Utf8Char::new('a'),
+    };
+    key2 = termkey::Event::Unicode {
+        codepoint: 'a',
+        mods: termkey::c::KeyMod::empty(),
+        utf8: termkey::Utf8Char {
+            bytes: [b'a' as _, 0, 1, 2, 3, 4, 5],
+        },
+    };
+    tap.ok(
+        key1 == key2,
+        "cmpkey ignores bytes after the UTF-8 encoding",
+    );
+
+    let mut hashed = std::collections::HashMap::new();
+    hashed.insert(key1.clone(), "hashed");
+    tap.is_str(
+        hashed.get(&key2).copied().unwrap_or(""),
+        "hashed",
+        "equal keys hash alike",
+    );
+    let mut sorted = std::collections::BTreeMap::new();
+    sorted.insert(key1.clone(), "sorted");
+    tap.is_str(
+        sorted.get(&key2).copied().unwrap_or(""),
+        "sorted",
+        "equal keys sort alike",
+    );
+
+    tap.is_str(
+        format!(
+            "{:?}",
+            termkey::Event::Unicode {
+                codepoint: 'a',
+                mods: termkey::c::KeyMod::CTRL,
+                utf8: termkey::Utf8Char::new('a'),
+            }
+        ),
+        "Unicode { codepoint: 'a', mods: CTRL, utf8: \"a\" }",
+        "Debug of C-a",
+    );
 }
 
 #[test]
@@ -1820,9 +1942,85 @@ fn test_20canon() {
 }
 
 #[test]
+fn test_21canonicalise() {
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(6);
+
+    let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
+
+    let space = termkey::Event::KeySym {
+        sym: termkey::c::Sym::SPACE,
+        mods: termkey::c::KeyMod::empty(),
+    };
+    let sp = termkey::Event::Unicode {
+        codepoint: ' ',
+        mods: termkey::c::KeyMod::empty(),
+        utf8: termkey::Utf8Char::new(' '),
+    };
+
+    tk.set_canonflags(termkey::c::Canon::empty());
+    tap.ok(
+        tk.canonicalise(space.clone()) == sp,
+        "canonicalise Space/symbol to unicode",
+    );
+    tk.push_bytes(" ".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => {
+            let (parsed, _) = tk.strpkey("Space", termkey::c::Format::empty()).unwrap();
+            tap.ok(
+                tk.canonicalise(parsed) == tk.canonicalise(key),
+                "strpkey Space and pushed SP canonicalise alike",
+            )
+        }
+        _ => tap.fail("strpkey Space and pushed SP canonicalise alike"),
+    }
+
+    tk.set_canonflags(termkey::c::Canon::SPACESYMBOL);
+    tap.ok(
+        tk.canonicalise(sp) == space,
+        "canonicalise SP/unicode to symbol under SPACESYMBOL",
+    );
+    tk.push_bytes(" ".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => {
+            let (parsed, _) = tk.strpkey("Space", termkey::c::Format::empty()).unwrap();
+            tap.ok(
+                tk.canonicalise(parsed) == tk.canonicalise(key),
+                "strpkey Space and pushed SP canonicalise alike under SPACESYMBOL",
+            )
+        }
+        _ => tap.fail("strpkey Space and pushed SP canonicalise alike under SPACESYMBOL"),
+    }
+
+    tk.set_canonflags(termkey::c::Canon::DELBS);
+    let del = termkey::Event::KeySym {
+        sym: termkey::c::Sym::DEL,
+        mods: termkey::c::KeyMod::CTRL,
+    };
+    tap.ok(
+        tk.canonicalise(del)
+            == termkey::Event::KeySym {
+                sym: termkey::c::Sym::BACKSPACE,
+                mods: termkey::c::KeyMod::CTRL,
+            },
+        "canonicalise DEL to Backspace under DELBS",
+    );
+    let f1 = termkey::Event::Function {
+        num: 1,
+        mods: termkey::c::KeyMod::empty(),
+    };
+    tap.ok(
+        tk.canonicalise(f1.clone()) == f1,
+        "canonicalise leaves F1 alone",
+    );
+}
+
+#[test]
+// nested like the other tests, each level bypassing what it cannot check
+#[allow(clippy::collapsible_match)]
 fn test_30mouse() {
     let mut tap = taplib::Tap::new();
-    tap.plan_tests(60);
+    tap.plan_tests(75);
 
     let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
 
@@ -1840,22 +2038,23 @@ fn test_30mouse() {
                         line,
                         col,
                         mods,
+                        pixels: _,
                     } => {
                         tap.pass("key.type for mouse press");
 
                         tap.pass("interpret_mouse yields RES_KEY");
 
                         tap.is_int(ev, termkey::c::MouseEvent::PRESS, "mouse event for press");
-                        tap.is_int(button, 1, "mouse button for press");
+                        tap.is_int(button, termkey::MouseButton::Left, "mouse button for press");
                         tap.is_int(line, 1, "mouse line for press");
                         tap.is_int(col, 1, "mouse column for press");
                         tap.ok(mods.is_empty(), "modifiers for press");
 
-                        let buffer = tk.strfkey(key, termkey::c::Format::empty());
+                        let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
                         tap.is_int(buffer.len(), 13, "string length for press");
                         tap.is_str(buffer, "MousePress(1)", "string buffer for press");
 
-                        let buffer = tk.strfkey(key, termkey::c::Format::MOUSE_POS);
+                        let buffer = tk.strfkey(key.clone(), termkey::c::Format::MOUSE_POS);
                         tap.is_int(buffer.len(), 21, "string length for press");
                         tap.is_str(buffer, "MousePress(1) @ (1,1)", "string buffer for press");
                     }
@@ -1877,11 +2076,12 @@ fn test_30mouse() {
                     line,
                     col,
                     mods,
+                    pixels: _,
                 } => {
                     tap.pass("interpret_mouse yields RES_KEY");
 
                     tap.is_int(ev, termkey::c::MouseEvent::DRAG, "mouse event for drag");
-                    tap.is_int(button, 1, "mouse button for drag");
+                    tap.is_int(button, termkey::MouseButton::Left, "mouse button for drag");
                     tap.is_int(line, 1, "mouse line for drag");
                     tap.is_int(col, 2, "mouse column for drag");
                     tap.ok(mods.is_empty(), "modifiers for press");
@@ -1901,6 +2101,7 @@ fn test_30mouse() {
                     line,
                     col,
                     mods,
+                    pixels: _,
                 } => {
                     tap.pass("interpret_mouse yields RES_KEY");
 
@@ -1930,6 +2131,7 @@ fn test_30mouse() {
                     line,
                     col,
                     mods,
+                    pixels: _,
                 } => {
                     tap.pass("interpret_mouse yields RES_KEY");
 
@@ -1938,12 +2140,16 @@ fn test_30mouse() {
                         termkey::c::MouseEvent::PRESS,
                         "mouse event for Ctrl-press",
                     );
-                    tap.is_int(button, 1, "mouse button for Ctrl-press");
+                    tap.is_int(
+                        button,
+                        termkey::MouseButton::Left,
+                        "mouse button for Ctrl-press",
+                    );
                     tap.is_int(line, 11, "mouse line for Ctrl-press");
                     tap.is_int(col, 11, "mouse column for Ctrl-press");
                     tap.is_int(mods, termkey::c::KeyMod::CTRL, "modifiers for Ctrl-press");
 
-                    let buffer = tk.strfkey(key, termkey::c::Format::empty());
+                    let buffer = tk.strfkey(key.clone(), termkey::c::Format::empty());
                     tap.is_int(buffer.len(), 15, "string length for Ctrl-press");
                     tap.is_str(buffer, "C-MousePress(1)", "string buffer for Ctrl-press");
                 }
@@ -1967,6 +2173,7 @@ fn test_30mouse() {
                         line,
                         col,
                         mods,
+                        pixels: _,
                     } => {
                         tap.pass("key.type for mouse press rxvt protocol");
 
@@ -1977,7 +2184,11 @@ fn test_30mouse() {
                             termkey::c::MouseEvent::PRESS,
                             "mouse event for press rxvt protocol",
                         );
-                        tap.is_int(button, 1, "mouse button for press rxvt protocol");
+                        tap.is_int(
+                            button,
+                            termkey::MouseButton::Left,
+                            "mouse button for press rxvt protocol",
+                        );
                         tap.is_int(line, 20, "mouse line for press rxvt protocol");
                         tap.is_int(col, 20, "mouse column for press rxvt protocol");
                         tap.ok(mods.is_empty(), "modifiers for press rxvt protocol");
@@ -2002,6 +2213,7 @@ fn test_30mouse() {
                         line,
                         col,
                         mods,
+                        pixels: _,
                     } => {
                         tap.pass("key.type for mouse release rxvt protocol");
 
@@ -2037,6 +2249,7 @@ fn test_30mouse() {
                         line,
                         col,
                         mods,
+                        pixels: _,
                     } => {
                         tap.pass("key.type for mouse press SGR encoding");
 
@@ -2047,7 +2260,11 @@ fn test_30mouse() {
                             termkey::c::MouseEvent::PRESS,
                             "mouse event for press SGR",
                         );
-                        tap.is_int(button, 1, "mouse button for press SGR");
+                        tap.is_int(
+                            button,
+                            termkey::MouseButton::Left,
+                            "mouse button for press SGR",
+                        );
                         tap.is_int(line, 30, "mouse line for press SGR");
                         tap.is_int(col, 30, "mouse column for press SGR");
                         tap.ok(mods.is_empty(), "modifiers for press SGR");
@@ -2072,6 +2289,7 @@ fn test_30mouse() {
                         line: _,
                         col: _,
                         mods: _,
+                        pixels: _,
                     } => {
                         tap.pass("key.type for mouse release SGR encoding");
 
@@ -2101,6 +2319,7 @@ fn test_30mouse() {
                     line,
                     col,
                     mods: _,
+                    pixels: _,
                 } => {
                     tap.is_int(line, 300, "mouse line for press SGR wide");
                     tap.is_int(col, 500, "mouse column for press SGR wide");
@@ -2110,6 +2329,127 @@ fn test_30mouse() {
             _ => tap.bypass(2, "mouse line/column for press SGR wide"),
         }
     }
+
+    {
+        tk.push_bytes("\x1b[<0;5000;3000M".as_bytes());
+
+        match tk.getkey() {
+            termkey::Result::Key(termkey::Event::Mouse { line, col, .. }) => {
+                tap.is_int(line, 3000, "mouse line for press SGR above 4095");
+                tap.is_int(col, 5000, "mouse column for press SGR above 4095");
+            }
+            _ => tap.bypass(2, "mouse line/column for press SGR above 4095"),
+        }
+    }
+
+    {
+        let key = termkey::Event::Mouse {
+            ev: termkey::c::MouseEvent::PRESS,
+            mods: termkey::c::KeyMod::empty(),
+            button: termkey::MouseButton::Left,
+            line: 3000,
+            col: 5000,
+            pixels: false,
+        };
+        let buffer = tk.strfkey(key.clone(), termkey::c::Format::MOUSE_POS);
+        tap.is_str(
+            buffer.clone(),
+            "MousePress(1) @ (5000,3000)",
+            "string buffer for press above 4095",
+        );
+        match tk.strpkey(&buffer, termkey::c::Format::MOUSE_POS) {
+            Some((parsed, "")) => tap.ok(parsed == key, "strpkey round-trips press above 4095"),
+            _ => tap.fail("strpkey round-trips press above 4095"),
+        }
+    }
+
+    //// SGR-Pixels protocol
+    {
+        tk.set_mouse_pixels(true);
+        tk.push_bytes("\x1b[<16;640;480M".as_bytes());
+
+        match tk.getkey() {
+            termkey::Result::Key(key) => {
+                match key {
+                    termkey::Event::Mouse { pixels, .. } => {
+                        tap.ok(pixels, "mouse pixels for press SGR-Pixels")
+                    }
+                    _ => tap.fail("mouse pixels for press SGR-Pixels"),
+                }
+
+                let buffer = tk.strfkey(key.clone(), termkey::c::Format::MOUSE_POS);
+                tap.is_str(
+                    buffer.clone(),
+                    "C-MousePress(1) @ (640,480)px",
+                    "string buffer for press SGR-Pixels",
+                );
+                match tk.strpkey(&buffer, termkey::c::Format::MOUSE_POS) {
+                    Some((parsed, "")) => {
+                        tap.ok(parsed == key, "strpkey round-trips press SGR-Pixels")
+                    }
+                    _ => tap.fail("strpkey round-trips press SGR-Pixels"),
+                }
+            }
+            _ => tap.bypass(3, "getkey yields RES_KEY for mouse press SGR-Pixels"),
+        }
+        tk.set_mouse_pixels(false);
+    }
+
+    //// Extended buttons
+    {
+        tk.push_bytes(
+            "\x1b[<66;1;1M\x1b[Mc!!\x1b[<128;1;1M\x1b[<129;1;1m\x1b[<130;1;1M".as_bytes(),
+        );
+
+        let buttons = [
+            (termkey::MouseButton::WheelLeft, "wheel left"),
+            (termkey::MouseButton::WheelRight, "wheel right"),
+            (termkey::MouseButton::Back, "back"),
+            (termkey::MouseButton::Forward, "forward"),
+            (termkey::MouseButton::Other(10), "button 10"),
+        ];
+        for &(expect, name) in buttons.iter() {
+            match tk.getkey() {
+                termkey::Result::Key(termkey::Event::Mouse { ev, button, .. }) => {
+                    tap.is_int(button, expect, &format!("mouse button for {}", name));
+                    if expect == termkey::MouseButton::Forward {
+                        tap.is_int(
+                            ev,
+                            termkey::c::MouseEvent::RELEASE,
+                            "mouse event for forward release",
+                        );
+                    }
+                }
+                _ => tap.fail(&format!("mouse button for {}", name)),
+            }
+        }
+
+        let key = termkey::Event::Mouse {
+            ev: termkey::c::MouseEvent::PRESS,
+            mods: termkey::c::KeyMod::empty(),
+            button: termkey::MouseButton::WheelLeft,
+            line: 1,
+            col: 1,
+            pixels: false,
+        };
+        tap.is_str(
+            tk.strfkey(key, termkey::c::Format::empty()),
+            "MousePress(6)",
+            "string buffer for wheel left",
+        );
+        match tk.strpkey("S-MouseDrag(9)", termkey::c::Format::empty()) {
+            Some((
+                termkey::Event::Mouse {
+                    ev: termkey::c::MouseEvent::DRAG,
+                    button: termkey::MouseButton::Forward,
+                    mods: termkey::c::KeyMod::SHIFT,
+                    ..
+                },
+                "",
+            )) => tap.pass("strpkey forward drag"),
+            _ => tap.fail("strpkey forward drag"),
+        }
+    }
 }
 
 #[test]
@@ -2219,10 +2559,295 @@ fn test_32modereport() {
     }
 }
 
+#[test]
+fn test_33devattrs() {
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(11);
+
+    let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
+
+    tk.push_bytes("\x1b[?62;22c".as_bytes());
+
+    match tk.getkey() {
+        termkey::Result::Key(key) => {
+            tap.pass("getkey yields RES_KEY for primary device attributes");
+            match key {
+                termkey::Event::DeviceAttributes { kind, params } => {
+                    tap.ok(
+                        kind == termkey::AttributesKind::Primary,
+                        "kind from primary device attributes",
+                    );
+                    tap.ok(params == [62, 22], "params from primary device attributes");
+                }
+                _ => tap.bypass(2, "key.type for primary device attributes"),
+            }
+        }
+        _ => tap.bypass(3, "getkey yields RES_KEY for primary device attributes"),
+    }
+
+    tk.push_bytes("\x1b[>41;388;0c".as_bytes());
+
+    match tk.getkey() {
+        termkey::Result::Key(key) => {
+            tap.pass("getkey yields RES_KEY for secondary device attributes");
+            match key {
+                termkey::Event::DeviceAttributes { kind, params } => {
+                    tap.ok(
+                        kind == termkey::AttributesKind::Secondary,
+                        "kind from secondary device attributes",
+                    );
+                    tap.ok(
+                        params == [41, 388, 0],
+                        "params from secondary device attributes",
+                    );
+                }
+                _ => tap.bypass(2, "key.type for secondary device attributes"),
+            }
+        }
+        _ => tap.bypass(3, "getkey yields RES_KEY for secondary device attributes"),
+    }
+
+    tk.push_bytes("\x1bP!|7E565445\x1b\\".as_bytes());
+
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::DeviceAttributes { kind, params }) => {
+            tap.ok(
+                kind == termkey::AttributesKind::Tertiary,
+                "kind from tertiary device attributes",
+            );
+            tap.ok(
+                params == [0x7E565445],
+                "unit ID from tertiary device attributes",
+            );
+        }
+        _ => tap.bypass(2, "key.type for tertiary device attributes"),
+    }
+
+    tk.push_bytes("\x1bP>|xterm(388)".as_bytes());
+    tap.ok(
+        matches!(tk.getkey(), termkey::Result::None_),
+        "getkey yields RES_NONE for partial XTVERSION",
+    );
+
+    tk.push_bytes("\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::TerminalVersion(version)) => {
+            tap.ok(version == "xterm(388)", "version from XTVERSION")
+        }
+        _ => tap.fail("version from XTVERSION"),
+    }
+
+    tk.push_bytes("\x1bP".as_bytes());
+    match tk.getkey_force() {
+        termkey::Result::Key(termkey::Event::Unicode {
+            codepoint, mods, ..
+        }) => tap.ok(
+            codepoint == 'P' && mods == termkey::c::KeyMod::ALT,
+            "getkey_force yields Alt-P",
+        ),
+        _ => tap.fail("getkey_force yields Alt-P"),
+    }
+}
+
+#[test]
+fn test_34strings() {
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(12);
+
+    let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::UTF8);
+
+    tk.push_bytes("\x1b]11;rgb:ffff/ffff/ffff\x07".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Osc { number, payload }) => tap.ok(
+            number == 11 && payload == "rgb:ffff/ffff/ffff",
+            "OSC ended by BEL",
+        ),
+        _ => tap.fail("OSC ended by BEL"),
+    }
+
+    tk.push_bytes("\x1b]52;c;aGVsbG8=\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Osc { number, payload }) => {
+            tap.ok(number == 52 && payload == "c;aGVsbG8=", "OSC ended by ST")
+        }
+        _ => tap.fail("OSC ended by ST"),
+    }
+
+    // Alt-] typed before some text is not an OSC
+    tk.push_bytes("\x1b]abc".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Unicode {
+            codepoint, mods, ..
+        }) => tap.ok(
+            codepoint == ']' && mods == termkey::c::KeyMod::ALT,
+            "Alt-] followed by text",
+        ),
+        _ => tap.fail("Alt-] followed by text"),
+    }
+    let mut typed = String::new();
+    while let termkey::Result::Key(termkey::Event::Unicode { codepoint, .. }) = tk.getkey() {
+        typed.push(codepoint);
+    }
+    tap.is_str(typed, "abc", "text typed after Alt-]");
+
+    tk.push_bytes("\x1b]1".as_bytes());
+    tap.ok(
+        matches!(tk.getkey(), termkey::Result::Again),
+        "getkey yields RES_AGAIN for partial OSC header",
+    );
+    match tk.getkey_force() {
+        termkey::Result::Key(termkey::Event::Unicode {
+            codepoint, mods, ..
+        }) => tap.ok(
+            codepoint == ']' && mods == termkey::c::KeyMod::ALT,
+            "getkey_force yields Alt-] for partial OSC header",
+        ),
+        _ => tap.fail("getkey_force yields Alt-] for partial OSC header"),
+    }
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Unicode { codepoint, .. }) => {
+            tap.ok(codepoint == '1', "key after partial OSC header")
+        }
+        _ => tap.fail("key after partial OSC header"),
+    }
+
+    tk.push_bytes("\x1bP1$r0;1m\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Dcs {
+            args,
+            initial,
+            intermediate,
+            command,
+            payload,
+        }) => {
+            tap.ok(
+                args.as_slice() == [1]
+                    && initial == 0
+                    && intermediate == '$' as isize
+                    && command == 'r' as isize,
+                "header of DECRQSS reply",
+            );
+            tap.ok(payload == "0;1m", "payload of DECRQSS reply");
+        }
+        _ => tap.bypass(2, "DECRQSS reply"),
+    }
+
+    tk.push_bytes("\x1b_Gi=1;OK\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Apc { payload }) => {
+            tap.ok(payload == "Gi=1;OK", "APC")
+        }
+        _ => tap.fail("APC"),
+    }
+
+    tk.set_string_max(4);
+    tk.push_bytes("\x1b]2;title".as_bytes());
+    tap.ok(
+        matches!(tk.getkey(), termkey::Result::None_),
+        "getkey yields RES_NONE for partial OSC",
+    );
+    tk.push_bytes("\x1b\\a".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Osc { number, payload }) => {
+            tap.ok(number == 2 && payload == "ti", "OSC longer than string_max")
+        }
+        _ => tap.fail("OSC longer than string_max"),
+    }
+}
+
+#[test]
+fn test_35colours() {
+    use termkey::query::{Query, Rgb};
+
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(8);
+
+    let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
+
+    tk.push_bytes("\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => {
+            tap.pass("getkey yields RES_KEY for background colour");
+            tap.ok(
+                Query::BackgroundColour.colour(&key)
+                    == Some(Rgb {
+                        red: 0x1e1e,
+                        green: 0x1e1e,
+                        blue: 0x2e2e,
+                    }),
+                "colour from background colour",
+            );
+            tap.ok(
+                Query::ForegroundColour.colour(&key).is_none(),
+                "background colour does not answer foreground query",
+            );
+        }
+        _ => tap.bypass(3, "getkey yields RES_KEY for background colour"),
+    }
+
+    tk.push_bytes("\x1b]10;rgb:ff/80/0\x07".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => tap.ok(
+            Query::ForegroundColour.colour(&key)
+                == Some(Rgb {
+                    red: 0xffff,
+                    green: 0x8080,
+                    blue: 0,
+                }),
+            "colour from foreground colour with short channels",
+        ),
+        _ => tap.fail("colour from foreground colour with short channels"),
+    }
+
+    tk.push_bytes("\x1b]4;1;rgb:cdcd/0000/0000\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => {
+            tap.ok(
+                Query::PaletteColour(1).colour(&key)
+                    == Some(Rgb {
+                        red: 0xcdcd,
+                        green: 0,
+                        blue: 0,
+                    }),
+                "colour from palette colour",
+            );
+            tap.ok(
+                Query::PaletteColour(11).colour(&key).is_none(),
+                "palette colour does not answer query for another index",
+            );
+        }
+        _ => tap.bypass(2, "colour from palette colour"),
+    }
+
+    tk.push_bytes("\x1b]11;rgba:0000/0000/0000/c0c0\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => tap.ok(
+            Query::BackgroundColour.colour(&key)
+                == Some(Rgb {
+                    red: 0,
+                    green: 0,
+                    blue: 0,
+                }),
+            "colour from background colour with alpha",
+        ),
+        _ => tap.fail("colour from background colour with alpha"),
+    }
+
+    tk.push_bytes("\x1b]11;#000000\x1b\\".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => tap.ok(
+            Query::BackgroundColour.is_reply(&key)
+                && Query::BackgroundColour.colour(&key).is_none(),
+            "unreadable background colour",
+        ),
+        _ => tap.fail("unreadable background colour"),
+    }
+}
+
 #[test]
 fn test_39csi() {
     let mut tap = taplib::Tap::new();
-    tap.plan_tests(15);
+    tap.plan_tests(17);
 
     let mut tk = termkey::TermKey::new_abstract("vt100", termkey::c::Flag::empty());
 
@@ -2233,20 +2858,39 @@ fn test_39csi() {
             tap.pass("getkey yields RES_KEY for CSI v");
 
             match key {
-                termkey::Event::UnknownCsi => {
+                termkey::Event::UnknownCsi {
+                    args,
+                    initial,
+                    intermediate,
+                    command,
+                } => {
                     tap.pass("key.type for unknown CSI");
 
-                    tap.pass("skipping interpret_csi"); //is_int(tk.interpret_csi(key, &mut args, &mut command), KEY, "interpret_csi yields RES_KEY");
+                    tap.ok(
+                        args.as_slice() == [5, 25]
+                            && initial == 0
+                            && intermediate == 0
+                            && command == 'v' as isize,
+                        "interpret_csi yields RES_KEY",
+                    );
 
-                    tap.pass("skipping nargs == 2"); //is_int(nargs, 2, "nargs for unknown CSI");
-                    tap.pass("skipping args[0] == 5"); //is_int(args[0], 5, "args[0] for unknown CSI");
-                    tap.pass("skipping args[1] == 25"); //is_int(args[1], 25, "args[1] for unknown CSI");
-                    tap.pass("skipping cmd == 'v'"); //is_int(command, 'v', "command for unknown CSI");
+                    tap.is_int(args.len, 2, "nargs for unknown CSI");
+                    tap.is_int(args.as_slice()[0], 5, "args[0] for unknown CSI");
+                    tap.is_int(args.as_slice()[1], 25, "args[1] for unknown CSI");
+                    tap.ok(
+                        initial == 0 && intermediate == 0 && command == 'v' as isize,
+                        "command for unknown CSI",
+                    );
+                    tap.is_str(
+                        tk.strfkey(key, termkey::c::Format::empty()),
+                        "CSI 5;25v",
+                        "strfkey for unknown CSI",
+                    );
                 }
-                _ => tap.bypass(6, "key.type for unknown CSI"),
+                _ => tap.bypass(7, "key.type for unknown CSI"),
             }
         }
-        _ => tap.bypass(7, "getkey yields RES_KEY for CSI v"),
+        _ => tap.bypass(8, "getkey yields RES_KEY for CSI v"),
     }
 
     tk.push_bytes("\x1b[?w".as_bytes());
@@ -2255,10 +2899,24 @@ fn test_39csi() {
         termkey::Result::Key(key) => {
             tap.pass("getkey yields RES_KEY for CSI ? w");
             match key {
-                termkey::Event::UnknownCsi => {
+                termkey::Event::UnknownCsi {
+                    args,
+                    initial,
+                    intermediate,
+                    command,
+                } => {
                     tap.pass("key.type for unknown CSI");
-                    tap.pass("skipping interpret_csi"); //is_int(tk.interpret_csi(&key, args, &nargs, &command), KEY, "interpret_csi yields RES_KEY");
-                    tap.pass("skipping cmd == '?' cat 'w'") //is_int(command, '?'<<8 | 'w', "command for unknown CSI");
+                    tap.ok(
+                        args.as_slice().is_empty()
+                            && initial == '?' as isize
+                            && intermediate == 0
+                            && command == 'w' as isize,
+                        "interpret_csi yields RES_KEY",
+                    );
+                    tap.ok(
+                        initial == '?' as isize && intermediate == 0 && command == 'w' as isize,
+                        "command for unknown CSI",
+                    );
                 }
                 _ => tap.bypass(3, "key.type for unknown CSI"),
             }
@@ -2272,14 +2930,358 @@ fn test_39csi() {
         termkey::Result::Key(key) => {
             tap.pass("getkey yields RES_KEY for CSI ? $x");
             match key {
-                termkey::Event::UnknownCsi => {
+                termkey::Event::UnknownCsi {
+                    args,
+                    initial,
+                    intermediate,
+                    command,
+                } => {
                     tap.pass("key.type for unknown CSI");
-                    tap.pass("skipping interpret_csi"); //is_int(tk.interpret_csi(&key, args, &nargs, &command), KEY, "interpret_csi yields RES_KEY");
-                    tap.pass("skipping cmd == '$' cat '?' cat 'x'"); //is_int(command, '$'<<16 | '?'<<8 | 'x', "command for unknown CSI");
+                    tap.ok(
+                        args.as_slice().is_empty()
+                            && initial == '?' as isize
+                            && intermediate == '$' as isize
+                            && command == 'x' as isize,
+                        "interpret_csi yields RES_KEY",
+                    );
+                    tap.ok(
+                        initial == '?' as isize
+                            && intermediate == '$' as isize
+                            && command == 'x' as isize,
+                        "command for unknown CSI",
+                    );
+                    tap.is_str(
+                        tk.strfkey(key, termkey::c::Format::WRAPBRACKET),
+                        "<CSI ?$x>",
+                        "strfkey for unknown CSI with WRAPBRACKET",
+                    );
                 }
-                _ => tap.bypass(3, "key.type for unknown CSI"),
+                _ => tap.bypass(4, "key.type for unknown CSI"),
             }
         }
-        _ => tap.bypass(4, "getkey yields RES_KEY for CSI ? $x"),
+        _ => tap.bypass(5, "getkey yields RES_KEY for CSI ? $x"),
+    }
+}
+
+#[test]
+fn test_40kitty() {
+    use termkey::c::{KeyEvent, KeyMod, Sym};
+    use termkey::{Event, KeyCode};
+
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(22);
+
+    let mut tk = termkey::TermKey::new_abstract("xterm", termkey::c::Flag::empty());
+
+    tk.push_bytes("\x1b[97;5u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::Unicode {
+            codepoint, mods, ..
+        }) => tap.ok(
+            codepoint == 'a' && mods == KeyMod::CTRL,
+            "plain press stays Unicode",
+        ),
+        _ => tap.fail("plain press stays Unicode"),
+    }
+
+    tk.push_bytes("\x1b[97;1:3u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey {
+            code, mods, event, ..
+        }) => tap.ok(
+            code == KeyCode::Unicode('a') && mods.is_empty() && event == KeyEvent::RELEASE,
+            "release of a",
+        ),
+        _ => tap.fail("release of a"),
+    }
+
+    tk.push_bytes("\x1b[97;5:3u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey {
+            code, mods, event, ..
+        }) => tap.ok(
+            code == KeyCode::Unicode('a') && mods == KeyMod::CTRL && event == KeyEvent::RELEASE,
+            "release of Ctrl-a",
+        ),
+        _ => tap.fail("release of Ctrl-a"),
+    }
+
+    tk.push_bytes("\x1b[1;5:2A".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey {
+            code, mods, event, ..
+        }) => tap.ok(
+            code == KeyCode::KeySym(Sym::UP) && mods == KeyMod::CTRL && event == KeyEvent::REPEAT,
+            "repeat of Ctrl-Up",
+        ),
+        _ => tap.fail("repeat of Ctrl-Up"),
+    }
+
+    tk.push_bytes("\x1b[5;1:3~".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey { code, event, .. }) => tap.ok(
+            code == KeyCode::KeySym(Sym::PAGEUP) && event == KeyEvent::RELEASE,
+            "release of PageUp",
+        ),
+        _ => tap.fail("release of PageUp"),
+    }
+
+    tk.push_bytes("\x1b[97:65:113;2u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey {
+            code,
+            mods,
+            event,
+            shifted,
+            base,
+            ..
+        }) => {
+            tap.ok(
+                code == KeyCode::Unicode('a') && mods == KeyMod::SHIFT && event == KeyEvent::PRESS,
+                "key for alternate keys",
+            );
+            tap.ok(shifted == Some('A') && base == Some('q'), "alternate keys");
+        }
+        _ => tap.bypass(2, "key for alternate keys"),
+    }
+
+    tk.push_bytes("\x1b[97::113u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey { shifted, base, .. }) => tap.ok(
+            shifted.is_none() && base == Some('q'),
+            "missing shifted key",
+        ),
+        _ => tap.fail("missing shifted key"),
+    }
+
+    tk.push_bytes("\x1b[101;;233:769u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyKey { text, .. }) => {
+            tap.is_str(text, "\u{e9}\u{301}", "associated text")
+        }
+        _ => tap.fail("associated text"),
+    }
+
+    tk.push_bytes("\x1b[57399u\x1b[57376u\x1b[27u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KeySym { sym, .. }) => {
+            tap.ok(sym == Sym::KP0, "kitty keypad 0")
+        }
+        _ => tap.fail("kitty keypad 0"),
+    }
+    match tk.getkey() {
+        termkey::Result::Key(Event::Function { num, .. }) => tap.is_int(num, 13, "kitty F13"),
+        _ => tap.fail("kitty F13"),
+    }
+    match tk.getkey() {
+        termkey::Result::Key(Event::KeySym { sym, .. }) => {
+            tap.ok(sym == Sym::ESCAPE, "kitty Escape")
+        }
+        _ => tap.fail("kitty Escape"),
+    }
+
+    tk.push_bytes("\x1b[97;9u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(key) => tap.is_str(
+            tk.strfkey(key, termkey::c::Format::empty()),
+            "Super-a",
+            "strfkey for Super-a",
+        ),
+        _ => tap.fail("strfkey for Super-a"),
+    }
+
+    {
+        let key = Event::Unicode {
+            codepoint: 'a',
+            mods: KeyMod::META | KeyMod::ALT,
+            utf8: termkey::Utf8Char::new('a'),
+        };
+        let format = termkey::c::Format::LONGMOD | termkey::c::Format::ALTISMETA;
+        let buffer = tk.strfkey(key.clone(), format);
+        tap.is_str(
+            buffer.clone(),
+            "KittyMeta-Meta-a",
+            "strfkey tells META from Alt as Meta",
+        );
+        match tk.strpkey(&buffer, format) {
+            Some((parsed, "")) => tap.ok(parsed == key, "strpkey round-trips META with Alt"),
+            _ => tap.fail("strpkey round-trips META with Alt"),
+        }
+    }
+
+    for &(event, format, name) in &[
+        (
+            KeyEvent::RELEASE,
+            termkey::c::Format::empty(),
+            "C-a:Release",
+        ),
+        (
+            KeyEvent::REPEAT,
+            termkey::c::Format::LONGMOD
+                | termkey::c::Format::LOWERMOD
+                | termkey::c::Format::LOWERSPACE,
+            "ctrl-a:repeat",
+        ),
+    ] {
+        let key = Event::KittyKey {
+            code: KeyCode::Unicode('a'),
+            mods: KeyMod::CTRL,
+            event,
+            shifted: None,
+            base: None,
+            text: String::new(),
+        };
+        let buffer = tk.strfkey(key.clone(), format);
+        tap.is_str(buffer.clone(), name, "strfkey marks the event type");
+        match tk.strpkey(&buffer, format) {
+            Some((parsed, "")) => tap.ok(parsed == key, "strpkey round-trips the event type"),
+            _ => tap.fail("strpkey round-trips the event type"),
+        }
+    }
+    tap.is_str(
+        tk.strfkey(
+            Event::KittyKey {
+                code: KeyCode::KeySym(Sym::UP),
+                mods: KeyMod::empty(),
+                event: KeyEvent::RELEASE,
+                shifted: None,
+                base: None,
+                text: String::new(),
+            },
+            termkey::c::Format::WRAPBRACKET,
+        ),
+        "<Up:Release>",
+        "strfkey marks the event type inside brackets",
+    );
+
+    tk.push_bytes("\x1b[?5u".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(Event::KittyFlags { flags }) => tap.ok(
+            flags
+                == termkey::kitty::Flags::DISAMBIGUATE_ESCAPE_CODES
+                    | termkey::kitty::Flags::REPORT_ALTERNATE_KEYS,
+            "flag query reply",
+        ),
+        _ => tap.fail("flag query reply"),
+    }
+
+    tap.is_str(
+        termkey::kitty::push_flags(termkey::kitty::Flags::REPORT_EVENT_TYPES),
+        "\x1b[>2u",
+        "push_flags",
+    );
+}
+
+#[test]
+fn test_41paste() {
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(11);
+
+    let mut tk = termkey::TermKey::new_abstract("xterm", termkey::c::Flag::UTF8);
+
+    tk.push_bytes("\x1b[200~hello\r\n\tw\u{f6}rld\x1b[201~x".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Paste { text, more }) => {
+            tap.is_str(text, "hello\r\n\tw\u{f6}rld", "text of paste");
+            tap.ok(!more, "paste is whole");
+        }
+        _ => tap.bypass(2, "text of paste"),
+    }
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Unicode { codepoint, .. }) => {
+            tap.is_int(codepoint, 'x', "key after paste")
+        }
+        _ => tap.fail("key after paste"),
+    }
+
+    tk.push_bytes("\x1b[200~abc".as_bytes());
+    match tk.getkey() {
+        termkey::Result::None_ => tap.pass("getkey yields RES_NONE for unfinished paste"),
+        _ => tap.fail("getkey yields RES_NONE for unfinished paste"),
+    }
+    tk.push_bytes("def\x1b[2".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(_) => tap.fail("no key for partial end marker"),
+        _ => tap.pass("no key for partial end marker"),
+    }
+    tk.push_bytes("01~".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Paste { text, .. }) => {
+            tap.is_str(text, "abcdef", "text of paste in pieces")
+        }
+        _ => tap.fail("text of paste in pieces"),
+    }
+
+    tk.push_bytes("\x1b[200~a\x1b[Ab\x1b[201~".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Paste { text, .. }) => {
+            tap.is_str(text, "a\x1b[Ab", "escape sequence kept in paste")
+        }
+        _ => tap.fail("escape sequence kept in paste"),
+    }
+
+    tk.set_paste_max(4);
+    tap.is_int(tk.get_paste_max(), 4, "get_paste_max");
+    tk.push_bytes("\x1b[200~abcdefg\x1b[201~".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Paste { text, more }) => tap.ok(
+            text == "abcd" && more,
+            "first part of long paste, with more to follow",
+        ),
+        _ => tap.fail("first part of long paste, with more to follow"),
+    }
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::Paste { text, more }) => tap.ok(
+            text == "efg" && !more,
+            "second part of long paste, which ends it",
+        ),
+        _ => tap.fail("second part of long paste, which ends it"),
+    }
+    match tk.getkey() {
+        termkey::Result::None_ => tap.pass("getkey yields RES_NONE after long paste"),
+        _ => tap.fail("getkey yields RES_NONE after long paste"),
+    }
+}
+
+#[test]
+fn test_42focus() {
+    let mut tap = taplib::Tap::new();
+    tap.plan_tests(7);
+
+    let mut tk = termkey::TermKey::new_abstract("xterm", termkey::c::Flag::UTF8);
+
+    tk.push_bytes("\x1b[I\x1b[O".as_bytes());
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::FocusGained) => tap.pass("FocusGained"),
+        _ => tap.fail("FocusGained"),
+    }
+    match tk.getkey() {
+        termkey::Result::Key(termkey::Event::FocusLost) => tap.pass("FocusLost"),
+        _ => tap.fail("FocusLost"),
+    }
+    match tk.getkey() {
+        termkey::Result::None_ => tap.pass("getkey yields RES_NONE after focus events"),
+        _ => tap.fail("getkey yields RES_NONE after focus events"),
+    }
+
+    tap.is_str(
+        tk.strfkey(termkey::Event::FocusGained, termkey::c::Format::empty()),
+        "FocusGained",
+        "strfkey FocusGained",
+    );
+    tap.is_str(
+        tk.strfkey(termkey::Event::FocusGained, termkey::c::Format::WRAPBRACKET),
+        "<FocusGained>",
+        "strfkey FocusGained with WRAPBRACKET",
+    );
+    tap.is_str(
+        tk.strfkey(termkey::Event::FocusLost, termkey::c::Format::LOWERSPACE),
+        "focus lost",
+        "strfkey FocusLost with LOWERSPACE",
+    );
+
+    match tk.strpkey("FocusLost", termkey::c::Format::empty()) {
+        Some((termkey::Event::FocusLost, "")) => tap.pass("strpkey FocusLost"),
+        _ => tap.fail("strpkey FocusLost"),
     }
 }
@@ -0,0 +1,369 @@
+extern crate termkey;
+
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use termkey::c::{Canon, Flag, Format, KeyMod, Sym};
+use termkey::keymap::{
+    analyse, parse_keys, Conflict, KeySequenceMatcher, Keymap, Match, Mode, ModeStack, Unknown,
+};
+use termkey::{Error, Event, TermKey};
+
+use common::unicode;
+
+#[test]
+fn test_parse_keys() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    let cx = tk.canonicalise(unicode('x', KeyMod::CTRL));
+    let cs = tk.canonicalise(unicode('s', KeyMod::CTRL));
+
+    assert_eq!(
+        parse_keys(&mut tk, "C-x C-s", Format::empty()).unwrap(),
+        vec![cx.clone(), cs.clone()]
+    );
+    assert_eq!(
+        parse_keys(&mut tk, "<C-x><C-s>", Format::VIM).unwrap(),
+        vec![cx, cs]
+    );
+    assert_eq!(
+        parse_keys(&mut tk, " <PageDown> a <<>", Format::empty()).unwrap(),
+        vec![
+            Event::KeySym {
+                sym: Sym::PAGEDOWN,
+                mods: KeyMod::empty(),
+            },
+            tk.canonicalise(unicode('a', KeyMod::empty())),
+            tk.canonicalise(unicode('<', KeyMod::empty())),
+        ]
+    );
+    assert_eq!(
+        parse_keys(&mut tk, "<Space>", Format::empty()).unwrap(),
+        vec![tk.canonicalise(unicode(' ', KeyMod::empty()))],
+        "keys are canonicalised"
+    );
+
+    let err = parse_keys(&mut tk, "C-x <C-s", Format::empty()).unwrap_err();
+    assert_eq!(err.offset, 4);
+    assert_eq!(err.to_string(), "expected a key at byte 4");
+    assert_eq!(
+        parse_keys(&mut tk, "   ", Format::empty())
+            .unwrap_err()
+            .offset,
+        3
+    );
+}
+
+#[test]
+fn test_keymap() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    let mut keymap = Keymap::new();
+    assert!(keymap.is_empty());
+    assert_eq!(
+        keymap.bind(&mut tk, "C-x C-s", Format::empty(), "save"),
+        Ok(None)
+    );
+    assert_eq!(
+        keymap.bind(&mut tk, "C-x C-c", Format::empty(), "quit"),
+        Ok(None)
+    );
+    assert_eq!(
+        keymap.bind(&mut tk, "C-x C-s", Format::empty(), "save-all"),
+        Ok(Some("save"))
+    );
+
+    let cx = parse_keys(&mut tk, "C-x", Format::empty()).unwrap();
+    let cxcc = parse_keys(&mut tk, "C-x C-c", Format::empty()).unwrap();
+    assert_eq!(keymap.get(&cx), None);
+    assert!(keymap.is_prefix(&cx));
+    assert_eq!(keymap.get(&cxcc), Some(&"quit"));
+    assert!(!keymap.is_prefix(&cxcc));
+    assert_eq!(keymap.bindings().len(), 2);
+    assert_eq!(keymap.bindings_after(&cx).len(), 2);
+
+    assert_eq!(keymap.remove(&cxcc), Some("quit"));
+    assert_eq!(keymap.remove(&cxcc), None);
+    let cxcs = parse_keys(&mut tk, "C-x C-s", Format::empty()).unwrap();
+    assert_eq!(keymap.remove(&cxcs), Some("save-all"));
+    assert!(keymap.is_empty(), "emptied prefixes are pruned");
+}
+
+#[test]
+fn test_matcher() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    let mut keymap = Keymap::new();
+    keymap.bind(&mut tk, "C-x C-s", Format::empty(), 1).unwrap();
+    keymap.bind(&mut tk, "C-x", Format::empty(), 2).unwrap();
+    keymap.bind(&mut tk, "q", Format::empty(), 3).unwrap();
+    keymap.bind(&mut tk, "g g", Format::empty(), 4).unwrap();
+
+    let key = |tk: &TermKey, c| tk.canonicalise(unicode(c, KeyMod::empty()));
+    let cx = tk.canonicalise(unicode('x', KeyMod::CTRL));
+    let cs = tk.canonicalise(unicode('s', KeyMod::CTRL));
+
+    let mut matcher = KeySequenceMatcher::new();
+    assert_eq!(matcher.feed(&keymap, cx.clone()), Match::Pending);
+    assert_eq!(matcher.pending().to_vec(), vec![cx.clone()]);
+    assert_eq!(matcher.feed(&keymap, cs), Match::Matched(1));
+    assert_eq!(matcher.resume(&keymap), None);
+
+    assert_eq!(
+        matcher.feed(&keymap, key(&tk, 'a')),
+        Match::NoMatch(vec![key(&tk, 'a')])
+    );
+    assert_eq!(matcher.feed(&keymap, key(&tk, 'q')), Match::Matched(3));
+
+    // an ambiguous prefix ended by another binding
+    assert_eq!(matcher.feed(&keymap, cx.clone()), Match::Pending);
+    assert_eq!(matcher.feed(&keymap, key(&tk, 'q')), Match::Matched(2));
+    assert_eq!(matcher.resume(&keymap), Some(Match::Matched(3)));
+    assert_eq!(matcher.resume(&keymap), None);
+
+    // an unbound prefix ended by an unbound key
+    assert_eq!(matcher.feed(&keymap, key(&tk, 'g')), Match::Pending);
+    assert_eq!(
+        matcher.feed(&keymap, key(&tk, 'z')),
+        Match::NoMatch(vec![key(&tk, 'g')])
+    );
+    assert_eq!(
+        matcher.resume(&keymap),
+        Some(Match::NoMatch(vec![key(&tk, 'z')]))
+    );
+
+    assert_eq!(matcher.feed(&keymap, cx.clone()), Match::Pending);
+    assert_eq!(matcher.deadline(), None, "no timeout by default");
+    let waittime = Instant::now() + Duration::from_millis(tk.get_waittime() as u64);
+    assert!(matcher.timeout_with(None).is_none());
+    assert!(
+        matcher.timeout_with(Some(waittime)).unwrap()
+            <= Duration::from_millis(tk.get_waittime() as u64),
+        "TermKey's waittime alone"
+    );
+    assert_eq!(matcher.check_timeout(&keymap), None);
+    assert_eq!(matcher.flush(&keymap), Some(Match::Matched(2)));
+    assert_eq!(matcher.flush(&keymap), None);
+
+    matcher.set_timeout(10);
+    assert_eq!(matcher.get_timeout(), 10);
+    assert_eq!(matcher.feed(&keymap, cx), Match::Pending);
+    assert!(matcher.timeout().unwrap() <= Duration::from_millis(10));
+    let waittime = Instant::now() + Duration::from_millis(tk.get_waittime() as u64);
+    assert!(
+        matcher.timeout_with(Some(waittime)).unwrap() <= Duration::from_millis(10),
+        "the matcher's deadline comes first"
+    );
+    let soon = Instant::now() + Duration::from_millis(1);
+    assert!(
+        matcher.timeout_with(Some(soon)).unwrap() <= Duration::from_millis(1),
+        "the other deadline comes first"
+    );
+    assert_eq!(matcher.check_timeout(&keymap), None);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(matcher.check_timeout(&keymap), Some(Match::Matched(2)));
+    assert_eq!(matcher.deadline(), None);
+    assert!(matcher.pending().is_empty());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Action {
+    Insert(char),
+    Mode(&'static str),
+    Leave,
+    Yank,
+    Delete,
+    DeleteLine,
+}
+
+fn insert_text(key: &Event) -> Option<Action> {
+    match *key {
+        Event::Unicode {
+            codepoint, mods, ..
+        } if mods.is_empty() => Some(Action::Insert(codepoint)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_modes() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    let key = |tk: &mut TermKey, s| parse_keys(tk, s, Format::empty()).unwrap().remove(0);
+
+    let mut normal = Mode::new();
+    normal
+        .keymap
+        .bind(&mut tk, "i", Format::empty(), Action::Mode("insert"))
+        .unwrap();
+    normal
+        .keymap
+        .bind(&mut tk, "v", Format::empty(), Action::Mode("visual"))
+        .unwrap();
+    normal
+        .keymap
+        .bind(&mut tk, "y", Format::empty(), Action::Yank)
+        .unwrap();
+    normal
+        .keymap
+        .bind(&mut tk, "d d", Format::empty(), Action::DeleteLine)
+        .unwrap();
+    let mut insert = Mode::new();
+    insert
+        .keymap
+        .bind(&mut tk, "Escape", Format::empty(), Action::Leave)
+        .unwrap();
+    insert.unknown = Unknown::Handle(insert_text);
+    let mut visual = Mode::new();
+    visual.parent = Some("normal".to_string());
+    visual
+        .keymap
+        .bind(&mut tk, "Escape", Format::empty(), Action::Leave)
+        .unwrap();
+    visual
+        .keymap
+        .bind(&mut tk, "x", Format::empty(), Action::Delete)
+        .unwrap();
+
+    let mut modes = ModeStack::new("normal", normal);
+    assert!(modes.add_mode("insert", insert).is_none());
+    assert!(modes.add_mode("visual", visual).is_none());
+    assert_eq!(modes.mode_names(), vec!["insert", "normal", "visual"]);
+    assert_eq!(modes.current(), "normal");
+    assert!(!modes.push("command"), "no such mode");
+    assert_eq!(modes.pop(), None, "the base mode stays");
+
+    let a = key(&mut tk, "a");
+    assert_eq!(modes.feed(a.clone()), Match::NoMatch(vec![a.clone()]));
+    assert_eq!(modes.feed(key(&mut tk, "d")), Match::Pending);
+    let next = modes.next_keys();
+    assert_eq!(next.len(), 1);
+    assert_eq!(next[0].0, vec![key(&mut tk, "d")]);
+    assert_eq!(next[0].1, &Action::DeleteLine);
+    assert_eq!(next[0].2, "normal");
+    assert_eq!(
+        modes.feed(key(&mut tk, "d")),
+        Match::Matched(Action::DeleteLine)
+    );
+
+    assert_eq!(
+        modes.feed(key(&mut tk, "i")),
+        Match::Matched(Action::Mode("insert"))
+    );
+    assert!(modes.push("insert"));
+    assert_eq!(modes.feed(a), Match::Matched(Action::Insert('a')));
+    assert_eq!(
+        modes.feed(key(&mut tk, "i")),
+        Match::Matched(Action::Insert('i'))
+    );
+    let cx = key(&mut tk, "C-x");
+    assert_eq!(modes.feed(cx.clone()), Match::NoMatch(vec![cx]));
+    assert_eq!(
+        modes.feed(key(&mut tk, "Escape")),
+        Match::Matched(Action::Leave)
+    );
+    assert_eq!(modes.pop(), Some("insert".to_string()));
+
+    assert!(modes.push("visual"));
+    assert_eq!(modes.stack(), &["normal".to_string(), "visual".to_string()]);
+    assert_eq!(modes.layers(), vec!["visual", "normal"]);
+    assert_eq!(
+        modes.feed(key(&mut tk, "x")),
+        Match::Matched(Action::Delete)
+    );
+    assert_eq!(
+        modes.feed(key(&mut tk, "y")),
+        Match::Matched(Action::Yank),
+        "inherited"
+    );
+    let next = modes.next_keys();
+    assert_eq!(next.len(), 6);
+    assert!(next.contains(&(vec![key(&mut tk, "Escape")], &Action::Leave, "visual")));
+    assert!(next.contains(&(vec![key(&mut tk, "y")], &Action::Yank, "normal")));
+
+    // a parent cycle does not loop forever
+    modes.mode_mut("normal").unwrap().parent = Some("visual".to_string());
+    assert_eq!(modes.layers(), vec!["visual", "normal"]);
+    assert_eq!(
+        modes.feed(key(&mut tk, "q")),
+        Match::NoMatch(vec![key(&mut tk, "q")])
+    );
+}
+
+#[test]
+fn test_analyse() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    let bindings: Vec<Vec<Event>> = [
+        "C-x C-s",
+        "C-x",
+        "<DEL>",
+        "<Backspace>",
+        "<C-x>",
+        "<C-Up> <F12>",
+        "S-a",
+        "é A-é",
+        "C-é",
+        "C-Tab",
+        "<Super-a>",
+        "<S-Escape>",
+    ]
+    .iter()
+    .map(|keys| parse_keys(&mut tk, keys, Format::empty()).unwrap())
+    .collect();
+
+    let conflicts = analyse("vt100", Flag::empty(), Canon::DELBS, &bindings).unwrap();
+    let backspace = parse_keys(&mut tk, "Backspace", Format::empty()).unwrap();
+    let unproducible = |binding: usize, keys: &str| Conflict::Unproducible {
+        binding,
+        key: parse_keys(
+            &mut TermKey::new_abstract("vt100", Flag::empty()),
+            keys,
+            Format::empty(),
+        )
+        .unwrap()
+        .remove(0),
+    };
+    assert_eq!(
+        conflicts,
+        vec![
+            Conflict::Shadowed {
+                prefix: 1,
+                longer: 0
+            },
+            Conflict::Uncanonical {
+                binding: 2,
+                canonical: backspace,
+            },
+            Conflict::Duplicate {
+                first: 2,
+                second: 3
+            },
+            Conflict::Shadowed {
+                prefix: 4,
+                longer: 0
+            },
+            Conflict::Duplicate {
+                first: 1,
+                second: 4
+            },
+            unproducible(6, "S-a"),
+            unproducible(8, "C-é"),
+            unproducible(9, "C-Tab"),
+            unproducible(10, "Super-a"),
+            unproducible(11, "S-Escape"),
+        ]
+    );
+
+    let conflicts = analyse("vt100", Flag::empty(), Canon::empty(), &bindings[2..3]).unwrap();
+    assert!(conflicts.is_empty(), "{:?}", conflicts);
+
+    // only terminfo has xterm's F40, which vt100 lacks
+    let f40 = vec![parse_keys(&mut tk, "F40", Format::empty()).unwrap()];
+    let conflicts = analyse("xterm", Flag::empty(), Canon::empty(), &f40).unwrap();
+    assert!(conflicts.is_empty(), "{:?}", conflicts);
+    let conflicts = analyse("vt100", Flag::empty(), Canon::empty(), &f40).unwrap();
+    assert_eq!(conflicts, vec![unproducible(0, "F40")]);
+
+    assert!(matches!(
+        analyse("vt100\0", Flag::empty(), Canon::empty(), &f40),
+        Err(Error::NulInTermName)
+    ));
+}
@@ -0,0 +1,110 @@
+extern crate termkey;
+
+use termkey::c::{Flag, Format};
+use termkey::keymap::{from_toml, parse_keys, ConfigError};
+use termkey::TermKey;
+
+#[test]
+fn test_from_toml() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+    let text = r#"
+"<C-x><C-s>" = "save"
+"<C-x><C-c>" = "quit"
+"<PageDown>" = "scroll-down"
+q = "quit"
+"#;
+    let keymap = from_toml(&mut tk, text, Format::VIM).unwrap();
+    assert_eq!(keymap.bindings().len(), 4);
+    let keys = parse_keys(&mut tk, "C-x C-s", Format::empty()).unwrap();
+    assert_eq!(
+        keymap.get(&keys).map(|action| action.as_str()),
+        Some("save")
+    );
+    let keys = parse_keys(&mut tk, "q", Format::empty()).unwrap();
+    assert_eq!(
+        keymap.get(&keys).map(|action| action.as_str()),
+        Some("quit")
+    );
+
+    let keymap = from_toml(
+        &mut tk,
+        "\"Ctrl-PageUp Alt-x\" = \"top\"\n",
+        Format::LONGMOD,
+    )
+    .unwrap();
+    let keys = parse_keys(&mut tk, "C-PageUp M-x", Format::ALTISMETA).unwrap();
+    assert_eq!(keymap.get(&keys).map(|action| action.as_str()), Some("top"));
+}
+
+#[test]
+fn test_from_toml_errors() {
+    let mut tk = TermKey::new_abstract("vt100", Flag::empty());
+
+    let err = from_toml(
+        &mut tk,
+        "a = \"one\"\n\"C-x <C-s\" = \"two\"\n",
+        Format::empty(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ConfigError::BadKeys {
+            line: 2,
+            col: 6,
+            keys: "C-x <C-s".to_string(),
+        }
+    );
+    assert_eq!(err.to_string(), "2:6: cannot read keys `C-x <C-s`");
+
+    // columns count the escapes as written
+    let err = from_toml(&mut tk, "\"\\u00e9 <C-s\" = \"one\"\n", Format::empty()).unwrap_err();
+    assert_eq!(
+        err,
+        ConfigError::BadKeys {
+            line: 1,
+            col: 9,
+            keys: "\u{e9} <C-s".to_string(),
+        }
+    );
+    let err = from_toml(
+        &mut tk,
+        "'\\' = \"one\"\n\"\\\\ <C-s\" = \"two\"\n",
+        Format::empty(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ConfigError::BadKeys {
+            line: 2,
+            col: 5,
+            keys: "\\ <C-s".to_string(),
+        }
+    );
+
+    let err = from_toml(
+        &mut tk,
+        "\"C-x\" = \"one\"\nb = \"two\"\n  \"<C-x>\" = \"three\"\n",
+        Format::empty(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ConfigError::Duplicate {
+            line: 3,
+            col: 3,
+            keys: "<C-x>".to_string(),
+            first_line: 1,
+            first_col: 1,
+        }
+    );
+    assert_eq!(err.to_string(), "3:3: `<C-x>` is already bound at 1:1");
+
+    let err = from_toml(&mut tk, "a = \"one\"\nb = 2\n", Format::empty()).unwrap_err();
+    match err {
+        ConfigError::Toml { line, .. } => assert_eq!(line, 2),
+        _ => panic!("a number is not an action: {}", err),
+    }
+
+    let err = from_toml(&mut tk, "a = \"one\"\na = \"two\"\n", Format::empty()).unwrap_err();
+    assert_eq!(err.position().0, 2, "{}", err);
+}
@@ -0,0 +1,80 @@
+extern crate libc;
+extern crate termkey;
+
+mod common;
+
+use mio::{Events, Interest, Poll, Token};
+
+use common::fd_write;
+
+#[test]
+fn test_drain() {
+    let (reader_fd, writer_fd) = unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            panic!("Failed to create pipe");
+        }
+        let flags = libc::fcntl(fds[0], libc::F_GETFL);
+        libc::fcntl(fds[0], libc::F_SETFL, flags | libc::O_NONBLOCK);
+        (fds[0], fds[1])
+    };
+
+    std::env::set_var("TERM", "vt100");
+
+    let mut tk = termkey::TermKey::new(reader_fd, termkey::c::Flag::NOTERMIOS);
+    tk.set_waittime(10);
+
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(4);
+    poll.registry()
+        .register(&mut tk, Token(0), Interest::READABLE)
+        .unwrap();
+
+    let mut drain = termkey::KeyDrain::new();
+    let mut keys = Vec::new();
+
+    fd_write(writer_fd, "h\x1bOC");
+    poll.poll(&mut events, drain.timeout()).unwrap();
+    assert!(!events.is_empty(), "fd readable after write");
+    assert!(drain.readable(&mut tk, &mut keys).unwrap());
+    assert_eq!(keys.len(), 2, "two keys drained");
+    match keys[0] {
+        termkey::Event::Unicode { codepoint, .. } => assert_eq!(codepoint, 'h'),
+        _ => panic!("first key is h"),
+    }
+    match keys[1] {
+        termkey::Event::KeySym { sym, .. } => assert!(sym == termkey::c::Sym::RIGHT),
+        _ => panic!("second key is Right"),
+    }
+    assert!(drain.deadline().is_none(), "no deadline without a prefix");
+
+    keys.clear();
+    fd_write(writer_fd, "\x1b");
+    poll.poll(&mut events, drain.timeout()).unwrap();
+    assert!(drain.readable(&mut tk, &mut keys).unwrap());
+    assert!(keys.is_empty(), "lone Escape is held back");
+    assert!(drain.deadline().is_some(), "deadline armed for lone Escape");
+
+    poll.poll(&mut events, drain.timeout()).unwrap();
+    assert!(events.is_empty(), "poll times out");
+    assert!(drain.check_timeout(&mut tk, &mut keys).unwrap());
+    assert_eq!(keys.len(), 1, "Escape forced out after deadline");
+    match keys[0] {
+        termkey::Event::KeySym { sym, .. } => assert!(sym == termkey::c::Sym::ESCAPE),
+        _ => panic!("forced key is Escape"),
+    }
+    assert!(drain.deadline().is_none(), "deadline cleared");
+
+    keys.clear();
+    unsafe {
+        libc::close(writer_fd);
+    }
+    poll.poll(&mut events, drain.timeout()).unwrap();
+    assert!(!drain.readable(&mut tk, &mut keys).unwrap(), "EOF reported");
+
+    poll.registry().deregister(&mut tk).unwrap();
+    drop(tk);
+    unsafe {
+        libc::close(reader_fd);
+    }
+}
@@ -0,0 +1,245 @@
+extern crate libc;
+extern crate termkey;
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use termkey::c::{Flag, KeyMod};
+use termkey::query::{Query, Rgb, Selection};
+use termkey::{AttributesKind, Event, Result, TermKey};
+
+use common::{fd_write, unicode};
+
+fn pipe() -> (libc::c_int, libc::c_int) {
+    unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            panic!("Failed to create pipe");
+        }
+        (fds[0], fds[1])
+    }
+}
+
+fn fd_read(fd: libc::c_int) -> String {
+    let mut buf = [0u8; 64];
+    let len = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    String::from_utf8_lossy(&buf[..len as usize]).into_owned()
+}
+
+#[test]
+fn test_requests() {
+    assert_eq!(Query::CursorPosition.request(), "\x1b[?6n");
+    assert_eq!(Query::AnsiMode(4).request(), "\x1b[4$p");
+    assert_eq!(Query::DecMode(2004).request(), "\x1b[?2004$p");
+    assert_eq!(Query::PrimaryDeviceAttributes.request(), "\x1b[c");
+    assert_eq!(Query::SecondaryDeviceAttributes.request(), "\x1b[>c");
+    assert_eq!(Query::TertiaryDeviceAttributes.request(), "\x1b[=c");
+    assert_eq!(Query::TerminalVersion.request(), "\x1b[>q");
+
+    let report = Event::ModeReport {
+        initial: '?' as isize,
+        mode: 1,
+        value: 2,
+    };
+    assert!(Query::DecMode(1).is_reply(&report));
+    assert!(!Query::DecMode(2).is_reply(&report));
+    assert!(!Query::AnsiMode(1).is_reply(&report));
+    assert!(Query::CursorPosition.is_reply(&Event::Position { line: 1, col: 1 }));
+    assert!(!Query::CursorPosition.is_reply(&unicode('a', KeyMod::empty())));
+
+    let attributes = Event::DeviceAttributes {
+        kind: AttributesKind::Secondary,
+        params: vec![1, 2],
+    };
+    assert!(Query::SecondaryDeviceAttributes.is_reply(&attributes));
+    assert!(!Query::PrimaryDeviceAttributes.is_reply(&attributes));
+    assert_eq!(Query::BackgroundColour.request(), "\x1b]11;?\x1b\\");
+    assert_eq!(Query::PaletteColour(3).request(), "\x1b]4;3;?\x1b\\");
+    assert_eq!(
+        Query::Clipboard(Selection::Primary).request(),
+        "\x1b]52;p;?\x1b\\"
+    );
+    assert!(Query::TerminalVersion.is_reply(&Event::TerminalVersion("foot(1.16.2)".to_string())));
+}
+
+#[test]
+fn test_query() {
+    let (input_read, input_write) = pipe();
+    let (output_read, output_write) = pipe();
+
+    std::env::set_var("TERM", "vt100");
+    let mut tk = TermKey::new(input_read, Flag::NOTERMIOS);
+    tk.set_waittime(10);
+    assert!(
+        tk.query(Query::CursorPosition, Duration::from_millis(10))
+            .is_err(),
+        "no output fd"
+    );
+    tk.set_output_fd(output_write);
+    assert_eq!(tk.get_output_fd(), output_write);
+
+    fd_write(input_write, "a\x1b[?1;2$yb");
+    let reply = tk.query(Query::DecMode(1), Duration::from_secs(1)).unwrap();
+    assert_eq!(fd_read(output_read), "\x1b[?1$p");
+    assert_eq!(
+        reply,
+        Some(Event::ModeReport {
+            initial: '?' as isize,
+            mode: 1,
+            value: 2,
+        })
+    );
+    assert!(
+        matches!(tk.getkey(), Result::Key(key) if key == unicode('a', KeyMod::empty())),
+        "kept for later"
+    );
+
+    let start = Instant::now();
+    let reply = tk
+        .query(Query::CursorPosition, Duration::from_millis(30))
+        .unwrap();
+    assert_eq!(reply, None);
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(fd_read(output_read), "\x1b[?6n");
+    assert!(
+        matches!(tk.getkey(), Result::Key(key) if key == unicode('b', KeyMod::empty())),
+        "read while waiting"
+    );
+
+    fd_write(input_write, "\x1b");
+    fd_write(input_write, "[?5;7R");
+    let reply = tk
+        .query(Query::CursorPosition, Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(reply, Some(Event::Position { line: 5, col: 7 }));
+    assert!(matches!(tk.getkey(), Result::None_));
+}
+
+#[test]
+fn test_query_colours() {
+    let (input_read, input_write) = pipe();
+    let (output_read, output_write) = pipe();
+
+    std::env::set_var("TERM", "vt100");
+    let mut tk = TermKey::new(input_read, Flag::NOTERMIOS);
+    tk.set_output_fd(output_write);
+
+    fd_write(input_write, "\x1b]11;rgb:0000/0000/0000\x1b\\");
+    let background = tk.query_background(Duration::from_secs(1)).unwrap();
+    assert_eq!(fd_read(output_read), "\x1b]11;?\x1b\\");
+    assert_eq!(
+        background,
+        Some(Rgb {
+            red: 0,
+            green: 0,
+            blue: 0
+        })
+    );
+
+    fd_write(input_write, "\x1b]4;2;rgb:0000/cdcd/0000\x1b\\");
+    let green = tk.query_palette(2, Duration::from_secs(1)).unwrap();
+    assert_eq!(fd_read(output_read), "\x1b]4;2;?\x1b\\");
+    assert_eq!(
+        green,
+        Some(Rgb {
+            red: 0,
+            green: 0xcdcd,
+            blue: 0
+        })
+    );
+
+    let start = Instant::now();
+    let foreground = tk.query_foreground(Duration::from_millis(30)).unwrap();
+    assert_eq!(foreground, None, "no answer");
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(fd_read(output_read), "\x1b]10;?\x1b\\");
+}
+
+#[test]
+fn test_rgb_parse() {
+    let white = Rgb {
+        red: 0xffff,
+        green: 0xffff,
+        blue: 0xffff,
+    };
+    assert_eq!(Rgb::parse("rgb:ffff/ffff/ffff"), Some(white));
+    assert_eq!(Rgb::parse("rgb:f/ff/fff"), Some(white));
+    assert_eq!(
+        Rgb::parse("rgb:8/80/800").map(|rgb| (rgb.red, rgb.green, rgb.blue)),
+        Some((0x8888, 0x8080, 0x8008))
+    );
+    assert_eq!(Rgb::parse("rgba:ffff/ffff/ffff/0000"), Some(white));
+    assert_eq!(Rgb::parse("rgb:ffff/ffff"), None);
+    assert_eq!(Rgb::parse("rgb:ffff/ffff/ffff/ffff"), None);
+    assert_eq!(Rgb::parse("rgb:fffff/0/0"), None);
+    assert_eq!(Rgb::parse("rgb:g/0/0"), None);
+    assert_eq!(Rgb::parse("#ffffff"), None);
+}
+
+#[test]
+fn test_query_clipboard() {
+    let (input_read, input_write) = pipe();
+    let (output_read, output_write) = pipe();
+
+    std::env::set_var("TERM", "vt100");
+    let mut tk = TermKey::new(input_read, Flag::NOTERMIOS);
+    tk.set_output_fd(output_write);
+
+    fd_write(input_write, "a\x1b]52;c;aGVsbG8gd29ybGQ=\x1b\\b");
+    let contents = tk
+        .query_clipboard(Selection::Clipboard, Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(fd_read(output_read), "\x1b]52;c;?\x1b\\");
+    assert_eq!(contents.as_deref(), Some(&b"hello world"[..]));
+    assert!(
+        matches!(tk.getkey(), Result::Key(key) if key == unicode('a', KeyMod::empty())),
+        "typed before the answer"
+    );
+    assert!(
+        matches!(tk.getkey(), Result::Key(key) if key == unicode('b', KeyMod::empty())),
+        "typed after the answer"
+    );
+
+    fd_write(input_write, "x\x1b]52;c;eA==\x1b\\y\x1b]52;p;w7w=\x1b\\");
+    let contents = tk
+        .query_clipboard(Selection::Primary, Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(fd_read(output_read), "\x1b]52;p;?\x1b\\");
+    assert_eq!(contents.as_deref(), Some("\u{fc}".as_bytes()));
+    let queued: Vec<_> = (0..3)
+        .map(|_| match tk.getkey() {
+            Result::Key(key) => key,
+            _ => panic!("three keys kept"),
+        })
+        .collect();
+    assert_eq!(queued[0], unicode('x', KeyMod::empty()));
+    assert!(Query::Clipboard(Selection::Clipboard).is_reply(&queued[1]));
+    assert_eq!(queued[2], unicode('y', KeyMod::empty()));
+
+    let contents = tk
+        .query_clipboard(Selection::Clipboard, Duration::from_millis(20))
+        .unwrap();
+    assert_eq!(contents, None, "no answer");
+}
+
+#[test]
+fn test_clipboard_reply() {
+    let reply = |payload: &str| Event::Osc {
+        number: 52,
+        payload: payload.to_string(),
+    };
+    let clipboard = Query::Clipboard(Selection::Clipboard);
+    assert_eq!(clipboard.clipboard(&reply("c;")), Some(Vec::new()));
+    assert_eq!(clipboard.clipboard(&reply("c;YQ")), Some(b"a".to_vec()));
+    assert_eq!(clipboard.clipboard(&reply("c;YWI=")), Some(b"ab".to_vec()));
+    assert_eq!(clipboard.clipboard(&reply("c;YWJj")), Some(b"abc".to_vec()));
+    assert_eq!(clipboard.clipboard(&reply("c;Y")), None, "truncated");
+    assert_eq!(clipboard.clipboard(&reply("c;Y*Fi")), None, "not base64");
+    assert_eq!(
+        clipboard.clipboard(&reply("p;YQ==")),
+        None,
+        "other selection"
+    );
+    assert!(!clipboard.is_reply(&reply("p;YQ==")));
+}
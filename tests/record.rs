@@ -0,0 +1,125 @@
+extern crate libc;
+extern crate termkey;
+
+mod common;
+
+use std::time::Duration;
+
+use termkey::c::{Flag, KeyMod, Sym};
+use termkey::record::{Chunk, Recorder, Recording, Replayer};
+use termkey::{Event, Result, TermKey};
+
+use common::{keysym, unicode};
+
+fn chunk(millis: u64, bytes: &[u8]) -> Chunk {
+    Chunk {
+        time: Duration::from_millis(millis),
+        bytes: bytes.to_vec(),
+    }
+}
+
+#[test]
+fn test_format() {
+    let recording = Recording {
+        waittime: 50,
+        chunks: vec![chunk(0, b"a"), chunk(1234, &[0x1b; 200]), chunk(5000, b"")],
+    };
+    let mut bytes = Vec::new();
+    recording.write_to(&mut bytes).unwrap();
+    assert!(bytes.starts_with(b"TKREC1"));
+    assert_eq!(bytes.len(), 6 + 1 + (1 + 1 + 1) + (3 + 2 + 200) + (4 + 1));
+    assert_eq!(Recording::read_from(&bytes[..]).unwrap(), recording);
+
+    assert!(Recording::read_from(&b"TKREC2\x00"[..]).is_err());
+    assert!(Recording::read_from(&bytes[..bytes.len() - 6]).is_err());
+}
+
+#[test]
+fn test_replay() {
+    let recording = Recording {
+        waittime: 50,
+        chunks: vec![
+            chunk(0, b"a\x1b"),
+            chunk(10, b"[A"),
+            chunk(200, b"\x1b"),
+            chunk(400, b"b\x1b"),
+            chunk(420, b"x"),
+            chunk(600, b"\x1b"),
+            chunk(700, b""),
+        ],
+    };
+    let mut replay = Replayer::new(TermKey::new_abstract("vt100", Flag::empty()), recording);
+    let key = |replay: &mut Replayer| match replay.waitkey() {
+        Result::Key(key) => key,
+        _ => panic!("expected a key"),
+    };
+
+    assert_eq!(key(&mut replay), unicode('a', KeyMod::empty()));
+    assert_eq!(
+        key(&mut replay),
+        keysym(Sym::UP),
+        "completed within the wait time"
+    );
+    assert_eq!(replay.now(), Duration::from_millis(10));
+    assert_eq!(key(&mut replay), keysym(Sym::ESCAPE), "forced out");
+    assert_eq!(replay.now(), Duration::from_millis(250));
+    assert_eq!(key(&mut replay), unicode('b', KeyMod::empty()));
+    assert_eq!(key(&mut replay), unicode('x', KeyMod::ALT));
+    assert_eq!(replay.now(), Duration::from_millis(420));
+    assert_eq!(
+        key(&mut replay),
+        keysym(Sym::ESCAPE),
+        "forced out at the end"
+    );
+    assert_eq!(replay.now(), Duration::from_millis(650));
+    assert!(matches!(replay.waitkey(), Result::Eof));
+
+    let recording = Recording {
+        waittime: 500,
+        chunks: vec![chunk(0, b"\x1b"), chunk(200, b"x")],
+    };
+    let keys: Vec<Event> =
+        Replayer::new(TermKey::new_abstract("vt100", Flag::empty()), recording).collect();
+    assert_eq!(keys, vec![unicode('x', KeyMod::ALT)], "a longer wait time");
+}
+
+#[test]
+fn test_record() {
+    let (reader_fd, writer_fd) = unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            panic!("Failed to create pipe");
+        }
+        (fds[0], fds[1])
+    };
+
+    std::env::set_var("TERM", "vt100");
+    let mut tk = TermKey::new(reader_fd, Flag::NOTERMIOS);
+    tk.set_waittime(20);
+    let mut recorder = Recorder::new(tk, Vec::new()).unwrap();
+
+    unsafe {
+        libc::write(writer_fd, b"h\x1b".as_ptr() as *const libc::c_void, 2);
+    }
+    assert!(matches!(recorder.waitkey(), Result::Key(key) if key == unicode('h', KeyMod::empty())));
+    assert!(matches!(recorder.waitkey(), Result::Key(key) if key == keysym(Sym::ESCAPE)));
+    unsafe {
+        libc::close(writer_fd);
+    }
+    assert!(matches!(recorder.waitkey(), Result::Eof));
+
+    let (_, bytes) = recorder.into_inner();
+    let recording = Recording::read_from(&bytes[..]).unwrap();
+    assert_eq!(recording.waittime, 20);
+    assert_eq!(recording.chunks.len(), 2);
+    assert_eq!(recording.chunks[0].bytes, b"h\x1b");
+    assert!(recording.chunks[1].bytes.is_empty(), "end of input");
+    assert!(recording.chunks[1].time >= recording.chunks[0].time + Duration::from_millis(20));
+
+    let keys: Vec<Event> =
+        Replayer::new(TermKey::new_abstract("vt100", Flag::empty()), recording).collect();
+    assert_eq!(
+        keys,
+        vec![unicode('h', KeyMod::empty()), keysym(Sym::ESCAPE)]
+    );
+}
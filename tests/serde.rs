@@ -0,0 +1,223 @@
+extern crate termkey;
+
+use serde::{Deserialize, Serialize};
+use termkey::c::{Flag, Format, KeyEvent, KeyMod, MouseEvent, Sym};
+use termkey::{kitty, AttributesKind, CsiArgs, Event, MouseButton, Utf8Char};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Compact(#[serde(with = "termkey::compact")] Event);
+
+fn every_event() -> Vec<Event> {
+    let mut args = CsiArgs {
+        args: [0; 16],
+        len: 3,
+    };
+    args.args[..3].copy_from_slice(&[1, -1, 42]);
+    vec![
+        Event::Unicode {
+            codepoint: 'a',
+            mods: KeyMod::empty(),
+            utf8: Utf8Char::new('a'),
+        },
+        Event::Unicode {
+            codepoint: 'é',
+            mods: KeyMod::CTRL | KeyMod::ALT,
+            utf8: Utf8Char::new('é'),
+        },
+        Event::Function {
+            num: 5,
+            mods: KeyMod::SHIFT,
+        },
+        Event::KeySym {
+            sym: Sym::PAGEDOWN,
+            mods: KeyMod::CTRL,
+        },
+        Event::KeySym {
+            sym: Sym::Custom(100),
+            mods: KeyMod::empty(),
+        },
+        Event::Mouse {
+            ev: MouseEvent::PRESS,
+            mods: KeyMod::empty(),
+            button: MouseButton::Left,
+            line: 3,
+            col: 4,
+            pixels: false,
+        },
+        Event::Mouse {
+            ev: MouseEvent::RELEASE,
+            mods: KeyMod::SHIFT,
+            button: MouseButton::Other(13),
+            line: 5000,
+            col: 200,
+            pixels: true,
+        },
+        Event::Position { line: 25, col: 80 },
+        Event::ModeReport {
+            initial: '?' as isize,
+            mode: 1049,
+            value: 2,
+        },
+        Event::UnknownCsi {
+            args,
+            initial: '>' as isize,
+            intermediate: 0,
+            command: 'q' as isize,
+        },
+        Event::KittyKey {
+            code: termkey::KeyCode::Unicode('a'),
+            mods: KeyMod::SHIFT,
+            event: KeyEvent::REPEAT,
+            shifted: Some('A'),
+            base: None,
+            text: "A".to_string(),
+        },
+        Event::KittyFlags {
+            flags: kitty::Flags::DISAMBIGUATE_ESCAPE_CODES | kitty::Flags::REPORT_EVENT_TYPES,
+        },
+        Event::Paste {
+            text: "hello\nworld".to_string(),
+            more: false,
+        },
+        Event::Paste {
+            text: "hello".to_string(),
+            more: true,
+        },
+        Event::FocusGained,
+        Event::FocusLost,
+        Event::DeviceAttributes {
+            kind: AttributesKind::Secondary,
+            params: vec![41, 388, 0],
+        },
+        Event::TerminalVersion("xterm(388)".to_string()),
+        Event::Osc {
+            number: 11,
+            payload: "rgb:0000/0000/0000".to_string(),
+        },
+        Event::Dcs {
+            args: CsiArgs {
+                args: [1; 16],
+                len: 1,
+            },
+            initial: 0,
+            intermediate: '$' as isize,
+            command: 'r' as isize,
+            payload: "0m".to_string(),
+        },
+        Event::Apc {
+            payload: "Gi=1;OK".to_string(),
+        },
+    ]
+}
+
+#[test]
+fn test_roundtrip() {
+    for key in every_event() {
+        let json = serde_json::to_string(&key).unwrap();
+        let back: Event = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, key, "{} reads back", json);
+
+        let json = serde_json::to_string(&Compact(key.clone())).unwrap();
+        let back: Compact = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.0, key, "compact {} reads back", json);
+    }
+}
+
+#[test]
+fn test_representation() {
+    let key = Event::KeySym {
+        sym: Sym::PAGEDOWN,
+        mods: KeyMod::CTRL,
+    };
+    assert_eq!(
+        serde_json::to_string(&key).unwrap(),
+        r#"{"key":"PageDown","mods":["ctrl"]}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&Compact(key)).unwrap(),
+        r#""C-PageDown""#
+    );
+
+    let key = Event::Function {
+        num: 5,
+        mods: KeyMod::empty(),
+    };
+    assert_eq!(serde_json::to_string(&key).unwrap(), r#"{"key":"F5"}"#);
+
+    let key = Event::Mouse {
+        ev: MouseEvent::PRESS,
+        mods: KeyMod::empty(),
+        button: MouseButton::Left,
+        line: 3,
+        col: 4,
+        pixels: false,
+    };
+    assert_eq!(
+        serde_json::to_string(&key).unwrap(),
+        r#"{"mouse":{"event":"press","button":"left","line":3,"col":4}}"#
+    );
+
+    assert_eq!(
+        serde_json::to_string(&Event::FocusGained).unwrap(),
+        r#"{"focus":"gained"}"#
+    );
+    let key = Event::DeviceAttributes {
+        kind: AttributesKind::Primary,
+        params: vec![62, 22],
+    };
+    assert_eq!(
+        serde_json::to_string(&key).unwrap(),
+        r#"{"device_attributes":{"kind":"primary","params":[62,22]}}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&Compact(Event::Paste {
+            text: "x".to_string(),
+            more: false
+        }))
+        .unwrap(),
+        r#"{"paste":"x"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&Compact(Event::Paste {
+            text: "x".to_string(),
+            more: true
+        }))
+        .unwrap(),
+        r#"{"paste_part":"x"}"#
+    );
+    let key = Event::KittyKey {
+        code: termkey::KeyCode::Unicode('a'),
+        mods: KeyMod::CTRL,
+        event: KeyEvent::RELEASE,
+        shifted: None,
+        base: None,
+        text: String::new(),
+    };
+    assert_eq!(
+        serde_json::to_string(&Compact(key)).unwrap(),
+        r#""C-a:Release""#
+    );
+}
+
+#[test]
+fn test_flags() {
+    assert_eq!(
+        serde_json::to_string(&Format::VIM).unwrap(),
+        r#"["altismeta","wrapbracket"]"#
+    );
+    assert_eq!(
+        serde_json::from_str::<Flag>(r#"["ctrlc","utf8"]"#).unwrap(),
+        Flag::CTRLC | Flag::UTF8
+    );
+    assert_eq!(
+        serde_json::from_str::<KeyMod>("[]").unwrap(),
+        KeyMod::empty()
+    );
+    assert!(serde_json::from_str::<KeyMod>(r#"["control"]"#).is_err());
+    assert_eq!(serde_json::to_string(&Sym::Custom(70)).unwrap(), "70");
+    assert_eq!(
+        serde_json::from_str::<Sym>(r#""Escape""#).unwrap(),
+        Sym::ESCAPE
+    );
+    assert!(serde_json::from_str::<Event>(r#"{"key":"NoSuchKey"}"#).is_err());
+}
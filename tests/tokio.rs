@@ -0,0 +1,65 @@
+extern crate libc;
+extern crate termkey;
+
+mod common;
+
+use std::future::poll_fn;
+use std::pin::Pin;
+
+use futures_core::Stream;
+
+use common::fd_write;
+
+async fn next(stream: &mut termkey::AsyncTermKey) -> Option<std::io::Result<termkey::Event>> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_stream() {
+    let (reader_fd, writer_fd) = unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            panic!("Failed to create pipe");
+        }
+        (fds[0], fds[1])
+    };
+
+    std::env::set_var("TERM", "vt100");
+
+    let mut tk = termkey::TermKey::new(reader_fd, termkey::c::Flag::NOTERMIOS);
+    tk.set_waittime(10);
+    let mut stream = termkey::AsyncTermKey::new(tk).unwrap();
+
+    fd_write(writer_fd, "h");
+    match next(&mut stream).await {
+        Some(Ok(termkey::Event::Unicode { codepoint, .. })) => assert_eq!(codepoint, 'h'),
+        _ => panic!("stream yields h"),
+    }
+
+    fd_write(writer_fd, "\x1bOC");
+    match next(&mut stream).await {
+        Some(Ok(termkey::Event::KeySym { sym, .. })) => {
+            assert!(sym == termkey::c::Sym::RIGHT, "stream yields Right")
+        }
+        _ => panic!("stream yields Right"),
+    }
+
+    // A lone Escape is ambiguous until the waittime has passed
+    fd_write(writer_fd, "\x1b");
+    match next(&mut stream).await {
+        Some(Ok(termkey::Event::KeySym { sym, .. })) => {
+            assert!(sym == termkey::c::Sym::ESCAPE, "stream yields Escape")
+        }
+        _ => panic!("stream yields Escape after waittime"),
+    }
+
+    unsafe {
+        libc::close(writer_fd);
+    }
+    assert!(next(&mut stream).await.is_none(), "stream ends at EOF");
+
+    drop(stream);
+    unsafe {
+        libc::close(reader_fd);
+    }
+}